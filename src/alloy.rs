@@ -0,0 +1,244 @@
+/*!
+Alloy growth with a second metal species
+
+The second metal (e.g. silver or palladium) is stored in the otherwise unused fourth state of the bulk. For every new atom the species is drawn
+from the feed composition, which can change over time, i.e. with the number of grown metal atoms. Each species picks its vacancy with its own
+probability list and is only accepted with the average (relative) bond weight to its metal neighbors, so with bond weights of
+A-A = 1.0, A-B = 0.8, B-B = 0.6 gold prefers to attach to gold and the second metal tends to mix in or segregate accordingly.
+Each species needs a positive bond to at least one species, and the growth stops when no atom is accepted for a while.
+
+The composition of a grown particle is analyzed per layer and per spherical shell around its center.
+*/
+
+use rand::Rng;
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::{io::Write, fs::File};
+
+use crate::helpers::*;
+use crate::parameters::*;
+use crate::storage::*;
+use crate::crystal::*;
+
+
+/// Parameters of the second species.
+#[derive(Clone,Debug)]
+pub struct Alloy {
+    pub prob_list: [u64; VAC_LISTS],
+    pub bonds: [f32; 3],
+    pub feed: Vec<(usize, f32)>,
+}
+
+impl Default for Alloy {
+    fn default() -> Self {
+        Alloy{ prob_list: ALLOY_PROB_LIST, bonds: ALLOY_BONDS, feed: ALLOY_FEED.to_vec() }
+    }
+}
+
+impl Alloy {
+    /// Fraction of the second species in the feed after a number of grown atoms -- linearly interpolated between the given points.
+    pub fn fraction(&self, atoms: usize) -> f32 {
+        match self.feed.iter().position(|&(feed_atoms, _)| feed_atoms > atoms) {
+            Some(0) => self.feed[0].1,
+            Some(index) => {
+                let (a0, f0) = self.feed[index-1];
+                let (a1, f1) = self.feed[index];
+                f0 + (f1 - f0)*(atoms - a0) as f32/(a1 - a0) as f32
+            },
+            None => self.feed.last().map_or(0.0, |feed| feed.1),
+        }
+    }
+
+    /// Check that every species can bind to at least one species -- otherwise its atoms would be rejected forever.
+    pub fn check_bonds(&self) -> Result<(), String> {
+        let rows = [("gold", [self.bonds[0], self.bonds[1]]), ("alloy", [self.bonds[1], self.bonds[2]])];
+        match rows.iter().find(|(_, row)| !row.iter().any(|&bond| bond > 0.0)) {
+            Some((species, _)) => Err(format!("Alloy bonds {:?} without a positive bond of {}", self.bonds, species)),
+            None => Ok(()),
+        }
+    }
+
+    /// Bond weight between two metal atoms.
+    pub fn bond(&self, a: Atom, b: Atom) -> f32 {
+        match (a, b) {
+            (Atom::Alloy, Atom::Alloy) => self.bonds[2],
+            (Atom::Alloy, _) | (_, Atom::Alloy) => self.bonds[1],
+            _ => self.bonds[0],
+        }
+    }
+}
+
+/// Number of gold and alloy atoms in a layer or shell.
+#[derive(Copy,Clone,Debug,Default)]
+pub struct Composition {
+    pub gold: usize,
+    pub alloy: usize,
+}
+
+impl Composition {
+    pub fn fraction(&self) -> f32 {
+        if self.gold + self.alloy == 0 { 0.0 } else { self.alloy as f32/(self.gold + self.alloy) as f32 }
+    }
+}
+
+
+impl Crystal {
+    /// Switch the alloy growth on or off.
+    pub fn switch_alloy(&mut self) {
+        self.alloy = match self.alloy {
+            Some(_) => None,
+            None => Some(Alloy::default()),
+        };
+    }
+
+    /// Add atoms of both species -- used by random_add when an alloy is present.
+    pub fn alloy_add(&mut self, number_of_atoms: usize) {
+        let alloy = match &self.alloy {
+            Some(alloy) => alloy.clone(),
+            None => return,
+        };
+        if let Err(message) = alloy.check_bonds() {
+            println!("{} -- no atoms added", message);
+            return
+        }
+        let max_bond = alloy.bonds.iter().cloned().fold(0.0, f32::max);
+
+        let mut rng = rand::thread_rng();
+        let mut added = 0;
+        let mut rejected = 0;
+        while added < number_of_atoms {
+            if self.vacancies.list.iter().all(|list| list.is_empty()) { break }
+            if rejected == ALLOY_ATTEMPTS {
+                println!("No atom accepted in {} attempts in a row -- stopped after {} atoms", rejected, added);
+                break
+            }
+            rejected += 1;
+
+            // species from the feed and a vacancy according to its probability list
+            let grown = self.bulk.number_of_gold + self.bulk.number_of_alloy;
+            let (atom, vacancy) = if rng.gen::<f32>() < alloy.fraction(grown) {
+                (Atom::Alloy, self.random_vacancy_weighted(&alloy.prob_list))
+            }
+            else {
+                (Atom::Gold, self.random_vacancy())
+            };
+            let ijk = match vacancy.filter(|&vacancy| self.bulk.get(vacancy, Atom::Empty)) {
+                Some(ijk) => ijk,
+                None => continue,
+            };
+
+            // accept with the average bond weight to the neighbors
+            let mut bonds = 0.0;
+            let mut neighbors = 0;
+            for l in 0..12 {
+                let nn_ijk = self.neighbor(ijk, l);
+                let neighbor = self.bulk.atom(nn_ijk);
+                if neighbor == Atom::Gold || neighbor == Atom::Alloy {
+                    bonds += alloy.bond(atom, neighbor);
+                    neighbors += 1;
+                }
+            }
+            if neighbors > 0 && rng.gen::<f32>() < bonds/(neighbors as f32*max_bond) && self.add_metal(ijk, atom) {
+                added += 1;
+                rejected = 0;
+                if self.advance(added) { break }
+            }
+        }
+    }
+
+    /// Composition of every layer between k_min and k_max.
+    pub fn composition_layers(&self) -> Vec<Composition> {
+        let mut layers = vec![Composition::default(); (self.bulk.k_max - self.bulk.k_min + 1) as usize];
+        self.for_each_metal(|ijk, atom| {
+            let layer = &mut layers[(ijk.k - self.bulk.k_min) as usize];
+            if atom == Atom::Alloy { layer.alloy += 1 } else { layer.gold += 1 }
+        });
+        layers
+    }
+
+    /// Composition of spherical shells of the given width (in nm) around the center of the crystal.
+    pub fn composition_shells(&self, width: f32) -> Vec<Composition> {
+        let e = &self.extrema;
+        let center = XYZ{x: (e.x_min + e.x_max)/2.0, y: (e.y_min + e.y_max)/2.0, z: (e.z_min + e.z_max)/2.0};
+        let mut shells: Vec<Composition> = Vec::new();
+        self.for_each_metal(|ijk, atom| {
+            let xyz = self.lattice_at(ijk).get_xyz(ijk);
+            let r = ((xyz.x - center.x).powi(2) + (xyz.y - center.y).powi(2) + (xyz.z - center.z).powi(2)).sqrt();
+            let index = (r/width) as usize;
+            if index >= shells.len() { shells.resize(index + 1, Composition::default()) }
+            if atom == Atom::Alloy { shells[index].alloy += 1 } else { shells[index].gold += 1 }
+        });
+        shells
+    }
+
+    /// Call a function for every gold and alloy atom in the bulk.
+    fn for_each_metal<F: FnMut(IJK, Atom)>(&self, mut function: F) {
+        self.bulk.for_each_atom(|ijk, atom| {
+            if atom != Atom::Dirt {
+                function(ijk, atom);
+            }
+        });
+    }
+
+    /// Write the composition profiles per layer and per shell to a file.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save_composition(&self) {
+        let filename = format!("{}_composition_atoms{}.csv", FILENAME, self.bulk.number_of_atoms);
+        let mut f = File::create(filename).expect("Unable to create file");
+        writeln!(f, "gold: {} alloy: {} feed: {:?}", self.bulk.number_of_gold, self.bulk.number_of_alloy, self.alloy.as_ref().map(|alloy| &alloy.feed)).expect("Unable to write in file");
+        writeln!(f, "profile, position, gold, alloy, alloy fraction").expect("Unable to write in file");
+        for (index, layer) in self.composition_layers().iter().enumerate() {
+            writeln!(f, "layer, {}, {}, {}, {}", self.bulk.k_min as usize + index, layer.gold, layer.alloy, layer.fraction()).unwrap();
+        }
+        for (index, shell) in self.composition_shells(SHELL_WIDTH).iter().enumerate() {
+            writeln!(f, "shell, {}, {}, {}, {}", (index as f32 + 0.5)*SHELL_WIDTH, shell.gold, shell.alloy, shell.fraction()).unwrap();
+        }
+        println!("Composition saved: {} gold and {} alloy atoms", self.bulk.number_of_gold, self.bulk.number_of_alloy);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lattice::*;
+
+    #[test]
+    fn feed_is_interpolated() {
+        let alloy = Alloy{ prob_list: ALLOY_PROB_LIST, bonds: ALLOY_BONDS, feed: vec![(0, 0.0), (100, 0.5)] };
+        assert_eq!(alloy.fraction(0), 0.0);
+        assert_eq!(alloy.fraction(50), 0.25);
+        assert_eq!(alloy.fraction(1000), 0.5);
+    }
+
+    #[test]
+    fn alloy_growth_counts_both_species() {
+        let mut flake = Crystal::new(Lattice::new(STACKING_FAULTS.to_vec(), DIAMETER));
+        flake.add_atom(CENTER);
+        flake.alloy = Some(Alloy{ prob_list: ALLOY_PROB_LIST, bonds: ALLOY_BONDS, feed: vec![(0, 0.5)] });
+        flake.random_add(2000);
+        let b = &flake.bulk;
+        assert!(b.number_of_alloy > 0);
+        assert_eq!(b.number_of_gold + b.number_of_alloy, b.number_of_atoms);
+        let layers = flake.composition_layers();
+        assert_eq!(layers.iter().map(|layer| layer.gold + layer.alloy).sum::<usize>(), b.number_of_atoms);
+    }
+
+    #[test]
+    fn alloy_growth_without_bonds_stops() {
+        let mut flake = Crystal::new(Lattice::new(STACKING_FAULTS.to_vec(), DIAMETER));
+        flake.add_atom(CENTER);
+
+        // gold does not bind at all
+        let alloy = Alloy{ prob_list: ALLOY_PROB_LIST, bonds: [0.0, 0.0, 1.0], feed: vec![(0, 0.0)] };
+        assert!(alloy.check_bonds().is_err());
+        flake.alloy = Some(alloy);
+        flake.alloy_add(100);
+        assert_eq!(flake.bulk.number_of_atoms, 1);
+
+        // only alloy arrives, but it does not bind to the gold seed
+        flake.alloy = Some(Alloy{ prob_list: ALLOY_PROB_LIST, bonds: [1.0, 0.0, 1.0], feed: vec![(0, 1.0)] });
+        flake.alloy_add(100);
+        assert_eq!(flake.bulk.number_of_atoms, 1);
+    }
+}
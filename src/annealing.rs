@@ -0,0 +1,93 @@
+/*!
+Thermal annealing of a crystal
+
+Surface atoms hop to empty neighboring positions following the Metropolis algorithm in a simple broken-bond model:
+every missing gold neighbor of an atom costs half a bond energy, so a hop from a position with n neighbors to one with n' neighbors
+(not counting the hopping atom itself) changes the energy by bond_energy*(n - n'). The number of atoms is conserved and dirt atoms stay where they are.
+
+One sweep consists of as many hop attempts as there are surface atoms at its beginning.
+*/
+
+use rand::Rng;
+
+use crate::helpers::*;
+use crate::parameters::*;
+use crate::storage::*;
+use crate::crystal::*;
+
+
+impl Crystal {
+    /// Total surface energy (in eV) in the broken-bond model.
+    pub fn surface_energy(&self, bond_energy: f32) -> f64 {
+        let broken_bonds: usize = self.surface.list.iter().map(|&ijk| 12 - self.number_of_neighbors(ijk)).sum();
+        broken_bonds as f64 * bond_energy as f64 / 2.0
+    }
+
+    /// Anneal the crystal at a temperature (in K) for a number of sweeps -- returns the surface energy after each sweep.
+    pub fn anneal(&mut self, temperature: f32, sweeps: usize, bond_energy: f32) -> Vec<f64> {
+        let mut rng = rand::thread_rng();
+        let k_t = K_B*temperature as f64;
+        let bond_energy = bond_energy as f64;
+        let mut energy = self.surface_energy(bond_energy as f32);
+        let mut energies = vec![energy];
+
+        for _sweep in 0..sweeps {
+            // picking from a snapshot is much faster than from the BTreeSet -- atoms which moved in the meantime are simply skipped
+            let candidates: Vec<IJK> = self.surface.list.iter().cloned().collect();
+            for _attempt in 0..candidates.len() {
+                let ijk = candidates[rng.gen_range(0, candidates.len())];
+                if !self.bulk.is_metal(ijk) { continue }
+
+                // try a hop to a random neighboring position
+                let target = self.neighbor(ijk, rng.gen_range(0, 12));
+                if !self.hop_allowed(target) { continue }
+                let n_before = self.number_of_neighbors(ijk);
+                // the hopping atom itself does not count -- it is not always a neighbor of the target (independent particle lattices or a periodic wrap)
+                let mutual = (0..12).any(|l| self.neighbor(target, l) == ijk);
+                let n_after = self.number_of_neighbors(target) - mutual as usize;
+                if n_after == 0 { continue }                                    // no atoms leave the crystal
+
+                // Metropolis criterion
+                let delta = bond_energy*(n_before as f64 - n_after as f64);
+                if delta <= 0.0 || rng.gen::<f64>() < (-delta/k_t).exp() {
+                    let atom = self.bulk.atom(ijk);
+                    self.clear_site(ijk);
+                    self.add_metal(target, atom);
+                    energy += delta;
+                }
+            }
+            energies.push(energy);
+        }
+
+        // the extrema only have to be reconstructed once at the end
+        self.recalc_extrema();
+        energies
+    }
+
+    /// Check if a position is empty and within the boundaries.
+    fn hop_allowed(&self, ijk: IJK) -> bool {
+        ijk.i > 1 && ijk.i < FLAKE_MAX.i - 2
+            && ijk.j > 1 && ijk.j < FLAKE_MAX.j - 2
+            && ijk.k > self.substrate_pos && ijk.k < FLAKE_MAX.k - 2
+            && self.bulk.get(ijk, Atom::Empty)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lattice::*;
+
+    #[test]
+    fn anneal_conserves_atoms_and_energy() {
+        let mut flake = Crystal::new(Lattice::new(STACKING_FAULTS.to_vec(), DIAMETER));
+        flake.add_atom(CENTER);
+        flake.random_add(2000);
+        let atoms = flake.bulk.number_of_atoms;
+        let energies = flake.anneal(600.0, 3, 0.3);
+        assert_eq!(energies.len(), 4);
+        assert_eq!(flake.bulk.number_of_atoms, atoms);
+        assert!((energies[3] - flake.surface_energy(0.3)).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,94 @@
+/*!
+Handling of the FLAKE_MAX boundary of the bulk storage
+
+Atoms are only placed within the walls, i.e. at least two layers away from the border of the storage, so all neighbors (and their neighbors)
+of an atom are still within it. A flake which reaches the walls can not grow further in this direction -- this changes its shape,
+so the first contact is reported and flagged in the statistics.
+
+With RECENTER the flake is moved back to the center of the storage instead and continues growing (in k only in steps of DIV and only if the
+stacking faults stay within the storage). This needs the reconstruction of the surface and the vacancies, and it is only possible as long as
+the flake itself is smaller than the storage -- FLAKE_MAX is fixed at compile time, so larger flakes need a larger FLAKE_MAX (and memory).
+*/
+
+use crate::helpers::*;
+use crate::parameters::*;
+use crate::lattice::*;
+use crate::crystal::*;
+
+#[cfg(target_arch = "wasm32")]
+use crate::println;
+
+
+/// Check if a position lies within the walls, where atoms can be placed.
+pub fn within_walls(ijk: IJK) -> bool {
+    ijk.i > 1 && ijk.i < FLAKE_MAX.i - 2
+        && ijk.j > 1 && ijk.j < FLAKE_MAX.j - 2
+        && ijk.k > 1 && ijk.k < FLAKE_MAX.k - 2
+}
+
+impl Crystal {
+    /// Check if the flake has reached the walls (and this has not been reported yet).
+    pub fn near_boundary(&self) -> bool {
+        self.boundary.is_none() && self.distance_to_boundary() <= 2
+    }
+
+    /// Check if the flake has reached the walls -- it is recentered if possible (returns true then) or the contact is reported.
+    /// After a reported contact nothing is checked anymore until the crystal is cleared.
+    pub fn check_boundary(&mut self) -> bool {
+        if !self.near_boundary() { return false }
+        if RECENTER && self.recenter() { return true }
+        self.reached_boundary();
+        false
+    }
+
+    /// Report the first contact with the walls.
+    pub fn reached_boundary(&mut self) {
+        if self.boundary.is_none() {
+            println!("Boundary of the bulk reached with {} atoms -- the flake can not grow further in this direction (see FLAKE_MAX)", self.bulk.number_of_atoms);
+            self.boundary = Some(self.bulk.number_of_atoms);
+        }
+    }
+
+    /// Move the flake to the center of the storage -- returns false if this does not get it away from the walls.
+    /// Independent particles have their own lattices, so they are not moved -- and a periodic film stays in its cell.
+    pub fn recenter(&mut self) -> bool {
+        if self.particles.active() || self.lattice.periodic.is_some() { return false }
+
+        // the extrema of the bulk always include the center, so the real extent is taken from the surface and dirt atoms
+        let mut min = [u16::MAX; 3];
+        let mut max = [0; 3];
+        for ijk in self.surface.list.iter().chain(self.dirt.list.iter()) {
+            for (axis, &value) in [ijk.i, ijk.j, ijk.k].iter().enumerate() {
+                min[axis] = min[axis].min(value);
+                max[axis] = max[axis].max(value);
+            }
+        }
+        if min[0] > max[0] { return false }
+        let to_center = |axis: usize, center: u16| center as i32 - (min[axis] as i32 + max[axis] as i32)/2;
+        let mut shift = [to_center(0, CENTER.i), to_center(1, CENTER.j), to_center(2, CENTER.k)/DIV as i32*DIV as i32];
+
+        // the stacking faults (and the substrate) are moved along, so they have to stay within the storage
+        let moved = |k: u16| k as i32 + shift[2];
+        if self.lattice.stacking_faults.iter().any(|&k| moved(k) < 1 || moved(k) >= FLAKE_MAX.k as i32 - 1)
+            || (self.substrate_pos > 1 && moved(self.substrate_pos) < 1) {
+            shift[2] = 0;
+        }
+        let inside = |axis: usize, size: u16| min[axis] as i32 + shift[axis] > 2 && (max[axis] as i32 + shift[axis]) < size as i32 - 3;
+        if shift == [0, 0, 0] || !inside(0, FLAKE_MAX.i) || !inside(1, FLAKE_MAX.j) || !inside(2, FLAKE_MAX.k) { return false }
+
+        // only the real extent has to be moved
+        let b = &mut self.bulk;
+        b.i_min = min[0]; b.i_max = max[0];
+        b.j_min = min[1]; b.j_max = max[1];
+        b.k_min = min[2]; b.k_max = max[2];
+        b.shift(shift);
+        let stacking_faults = self.lattice.stacking_faults.iter().map(|&k| (k as i32 + shift[2]) as u16).collect();
+        let screw = self.lattice.screw.map(|screw| Screw{i: (screw.i as i32 + shift[0]) as u16, j: (screw.j as i32 + shift[1]) as u16, ..screw});
+        self.lattice = self.lattice.with_stacking_faults(stacking_faults);
+        self.lattice.screw = screw;
+        if self.substrate_pos > 1 { self.substrate_pos = (self.substrate_pos as i32 + shift[2]) as u16 }
+        self.reconstruct();
+        println!("Flake recentered with {} atoms (shifted by {:?})", self.bulk.number_of_atoms, shift);
+        true
+    }
+}
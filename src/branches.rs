@@ -0,0 +1,86 @@
+/*!
+Branching growth experiments from a common state
+
+A crystal can be forked into branches which continue from the same state -- e.g. a flake grown to 1M atoms -- under different conditions,
+instead of regrowing every variant from scratch (with different random numbers). The bulk of a branch is a snapshot copy of the state,
+which only takes memory where the branch changes it (see the storage module), the surface, the vacancies and all other parts are cloned and the coordination numbers are reconstructed.
+
+The conditions of every branch are given by a protocol file (see the protocol module), e.g. another probability list, a dirt layer
+or a stacking fault followed by some further growth. The branches are run one after another and the states after each of their stages
+are written side by side into one file, so they can be compared directly.
+*/
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::{io::Write, fs::File};
+
+use crate::crystal::*;
+use crate::protocol::*;
+use crate::parameters::*;
+
+
+impl Crystal {
+    /// A branch which continues from the current state of the crystal -- the crystal itself stays untouched.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn fork(&self) -> Crystal {
+        let bulk = self.bulk.fork().expect("Unable to fork the bulk");
+        let mut branch = Crystal::with_bulk(self.lattice.clone(), bulk);
+        branch.prob_list_num = self.prob_list_num;
+        branch.prob_list = self.prob_list;
+        branch.prob_list_log = self.prob_list_log.clone();
+        branch.surface = self.surface.clone();
+        branch.dirt = self.dirt.clone();
+        branch.vacancies = self.vacancies.clone();
+        branch.extrema = self.extrema;
+        branch.extrema_ijk = self.extrema_ijk;
+        branch.substrate_pos = self.substrate_pos;
+        branch.particles = self.particles.clone();
+        branch.supply = self.supply.clone();
+        branch.growth_mode = self.growth_mode;
+        branch.flux = self.flux;
+        branch.alloy = self.alloy.clone();
+        branch.impurities = self.impurities;
+        branch.set_threads(self.threads);
+        branch.boundary = self.boundary;
+        branch.monolayer = self.monolayer.clone();
+        branch.update_coordination();
+        branch
+    }
+
+    /// Run every protocol on its own branch of the current state -- returns the logs of all branches.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn run_branches(&self, protocols: &[Protocol]) -> Vec<Vec<StageLog>> {
+        let mut logs = Vec::new();
+        for (index, protocol) in protocols.iter().enumerate() {
+            println!("Branch {}/{} from {} atoms", index + 1, protocols.len(), self.bulk.number_of_atoms);
+            let mut branch = self.fork();
+            logs.push(branch.run_protocol(protocol));
+        }
+        logs
+    }
+
+    /// Write the logs of all branches side by side -- one line per step (the n-th stage of every branch) with the atoms, dirt, aspect ratio and length ratio of every branch.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save_branches(&self, names: &[String], logs: &[Vec<StageLog>]) {
+        let filename = format!("{}_branches{}_atoms{}.csv", FILENAME, logs.len(), self.bulk.number_of_atoms);
+        let mut f = File::create(filename).expect("Unable to create file");
+        writeln!(f, "branches from {} atoms: {}", self.bulk.number_of_atoms, names.join(", ")).expect("Unable to write in file");
+        write!(f, "step").expect("Unable to write in file");
+        for index in 1..=logs.len() {
+            write!(f, ", atoms {0}, dirt {0}, aspect ratio {0}, len ratio {0}", index).expect("Unable to write in file");
+        }
+        writeln!(f).expect("Unable to write in file");
+
+        // branches with less stages just leave their columns empty
+        let rows = logs.iter().map(|log| log.len()).max().unwrap_or(0);
+        for row in 0..rows {
+            write!(f, "{}", row + 1).unwrap();
+            for log in logs.iter() {
+                match log.get(row) {
+                    Some(log) => write!(f, ", {}, {}, {}, {}", log.atoms, log.dirt, log.size[3], log.length_ratio).unwrap(),
+                    None => write!(f, ", , , , ").unwrap(),
+                }
+            }
+            writeln!(f).unwrap();
+        }
+    }
+}
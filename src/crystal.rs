@@ -2,9 +2,10 @@
 Buisness logic of the flake growth program
 
 It utilizes the storage back-end and provides basic methods in the Crystal struct for:
-* adding single gold or dirt atoms
+* adding single gold, alloy or dirt atoms
+* removing single atoms or whole regions again (e.g. for etching or milling)
 * randomly selecting vacancy which may be turned into a new surface atom
-* and for adding many random atoms at once in an optimized way.
+* and for adding many random atoms at once in an optimized way (or from a finite precursor supply, see the diffusion module, via random walkers, see the walkers module, from one direction, see the flux module, of two metal species, see the alloy module, or in several threads, see the parallel module).
 
 It furthermore provides some basic shapes (layers, spheres, cylinders, boxes and rounded boxes) prefilled with atoms as a starting point, a bunch of helpers (get extremas, hexagaon approximation, size) and a statistics "module".
 
@@ -13,19 +14,65 @@ It furthermore provides some basic shapes (layers, spheres, cylinders, boxes and
 
 use rand::Rng;
 use rand::seq::IteratorRandom;
+use std::sync::Arc;
 
 #[cfg(not(target_arch = "wasm32"))] 
-use std::{io::Write, fs::File}; 
+use std::{io::Write, io::BufWriter, fs::File}; 
 
 use crate::helpers::*;
 use crate::parameters::*;
 use crate::lattice::*;
 use crate::storage::*;
+use crate::particles::*;
+use crate::diffusion::*;
+use crate::walkers::*;
+use crate::flux::*;
+use crate::alloy::*;
+use crate::impurities::*;
+use crate::job::*;
+use crate::parallel::*;
+use crate::boundary::*;
+use crate::monolayer::*;
 
 #[derive(Copy,Clone,Debug)]
 pub struct Extrema {pub x_min: f32, pub x_max: f32, pub y_min: f32, pub y_max: f32, pub z_min: f32, pub z_max: f32 }
+impl Extrema {
+    /// Height, width, depth and aspect ratio spanned by the extrema.
+    pub fn size(&self) -> [f32;4] {
+        let h = (self.z_max-self.z_min) + DIAMETER;
+        let w = (self.x_max-self.x_min) + DIAMETER;
+        let d = (self.y_max-self.y_min) + DIAMETER;
+        let r = ( w  * d ).sqrt() / h;
+        [h,w,d,r]
+    }
+}
+
 #[derive(Copy,Clone,Debug)]
 pub struct ExtremaCoordinates{pub x_min: IJK, pub x_max: IJK, pub y_min: IJK, pub y_max: IJK, pub z_min: IJK, pub z_max: IJK }
+impl ExtremaCoordinates {
+    /// Coordinates of a hexagon spanned by the extrema (in the given lattice).
+    pub fn hexagon(&self, lattice: &Lattice) -> [f32;12] {
+
+        // left side
+        let x_min = self.x_min;
+        let delta1 = self.y_max.j as isize - self.x_min.j as isize;
+        let delta2 = self.x_min.j as isize - self.y_min.j as isize;
+        let a = lattice.get_xyz(IJK{i: x_min.i, j: (x_min.j as isize + delta1) as u16, k: x_min.k});
+        let b = lattice.get_xyz(self.x_min);
+        let c = lattice.get_xyz(IJK{i: (x_min.i as isize + delta2) as u16, j: (x_min.j as isize - delta2) as u16, k: x_min.k});
+
+        //right side
+        let x_max = self.x_max;
+        let delta3 = self.y_min.j as isize - self.x_max.j as isize;
+        let delta4 = self.x_max.j as isize - self.y_max.j as isize;
+        let d = lattice.get_xyz(IJK{i: x_max.i, j: (x_max.j as isize + delta3) as u16, k: x_max.k});
+        let e = lattice.get_xyz(self.x_max);
+        let f = lattice.get_xyz(IJK{i: (x_max.i as isize + delta4) as u16, j: (x_max.j as isize - delta4) as u16, k: x_max.k});
+
+        // output of the xy coordinates
+        [a.x,a.y, b.x,b.y, c.x,c.y, d.x,d.y, e.x,e.y, f.x,f.y]
+    }
+}
 
 pub struct Crystal {
     pub lattice: Lattice,
@@ -33,76 +80,211 @@ pub struct Crystal {
     pub prob_list: [u64; VAC_LISTS],
     pub prob_list_log: Vec<i8>,
     pub bulk: Bulk,
+    pub coordination: Coordination,
     pub surface: SurfaceAtoms,
     pub dirt: SurfaceAtoms,
     pub vacancies: Vacancies,
     pub extrema: Extrema,
     pub extrema_ijk: ExtremaCoordinates,
     pub substrate_pos: u16,
+    pub particles: Particles,
+    pub supply: Option<ConcentrationField>,
+    pub growth_mode: GrowthMode,
+    pub flux: Option<Flux>,
+    pub alloy: Option<Alloy>,
+    pub impurities: Option<Impurities>,
+    pub progress: Option<Arc<Progress>>,
+    pub threads: usize,
+    pub boundary: Option<usize>,
+    pub monolayer: Option<Monolayer>,
 }
 
 impl Crystal {
     pub fn new(lattice: Lattice) -> Self {
+        Crystal::with_bulk(lattice, Bulk::new())
+    }
+
+    /// Create a crystal around a given bulk, e.g. a reopened bulk file (see reconstruct).
+    pub fn with_bulk(lattice: Lattice, bulk: Bulk) -> Self {
         let prob_list = PROB_LIST[PROB_LIST_NUM];
+        let threads = number_of_threads(PARALLEL_THREADS);
         Crystal{ 
             lattice, 
             prob_list_num:  PROB_LIST_NUM, 
             prob_list, 
             prob_list_log:  prob_list.iter().map(|&el| (el as f32 + 0.1).log10() as i8).collect::<Vec<i8>>(), 
-            bulk:   	    Bulk::new(), 
+            bulk, 
+            coordination:   Coordination::new(COORDINATION || threads > 1),
             surface:        SurfaceAtoms::new(), 
             dirt:           SurfaceAtoms::new(), 
             vacancies:      Vacancies::new(), 
             extrema:        Extrema{x_min: 0.0, x_max: 0.0, y_min: 0.0, y_max: 0.0, z_min: 0.0, z_max: 0.0 }, 
             extrema_ijk:    ExtremaCoordinates{x_min: CENTER, x_max: CENTER, y_min: CENTER, y_max: CENTER, z_min: CENTER, z_max: CENTER }, 
-            substrate_pos:  1
+            substrate_pos:  1,
+            particles:      Particles::new(),
+            supply:         if SUPPLY { Some(ConcentrationField::new(SUPPLY_CELLS, SUPPLY_CELL_SIZE)) } else { None },
+            growth_mode:    if WALKERS { GrowthMode::Walkers } else { GrowthMode::Vacancies },
+            flux:           if FLUX { Some(Flux::default()) } else { None },
+            alloy:          if ALLOY { Some(Alloy::default()) } else { None },
+            impurities:     if IMPURITIES { Some(Impurities::default()) } else { None },
+            progress:       None,
+            threads,
+            boundary:       None,
+            monolayer:      None,
         }       
     }
 
+    /// Continue a reopened bulk file (see Bulk::open) on the lattice and above the substrate stored in its header.
+    pub fn reopen(bulk: Bulk) -> Self {
+        let stored = bulk.lattice.clone();
+        let mut lattice = Lattice::new(stored.stacking_faults, DIAMETER);
+        lattice.screw = stored.screw;
+        lattice.periodic = stored.period.map(Periodic::new);
+        let mut crystal = Crystal::with_bulk(lattice, bulk);
+        crystal.substrate_pos = stored.substrate_pos;
+        crystal.reconstruct();
+        crystal
+    }
+
+    /// Store the lattice and the substrate in the header of a bulk file together with the numbers of atoms -- done after every random_add
+    /// (and when the crystal is dropped), so the file can be reopened with the current state at any time.
+    pub fn write_bulk_header(&mut self) {
+        self.bulk.lattice = BulkLattice{
+            stacking_faults: self.lattice.stacking_faults.clone(),
+            screw: self.lattice.screw,
+            period: self.lattice.periodic.map(|periodic| periodic.period),
+            substrate_pos: self.substrate_pos,
+        };
+        self.bulk.write_header();
+    }
+
     /// Reset the crystal.
     pub fn clear(&mut self) {
         self.bulk.clear();
+        self.coordination.clear();
         self.surface = SurfaceAtoms::new();
         self.dirt = SurfaceAtoms::new();
         self.vacancies = Vacancies::new();
         self.extrema = Extrema{x_min: 0.0, x_max: 0.0, y_min: 0.0, y_max: 0.0, z_min: 0.0, z_max: 0.0 };
         self.extrema_ijk = ExtremaCoordinates{x_min: CENTER, x_max: CENTER, y_min: CENTER, y_max: CENTER, z_min: CENTER, z_max: CENTER };
+        self.particles = Particles::new();
+        self.boundary = None;
+        if let Some(field) = &mut self.supply { field.reset() }
+        if let Some(monolayer) = &mut self.monolayer { monolayer.reset() }
+    }
+
+    /// Reconstruct everything beyond the bulk (coordination numbers, surface, dirt, vacancies and extrema) -- needed for a reopened bulk file.
+    pub fn reconstruct(&mut self) {
+        let mut dirt = SurfaceAtoms::new();
+        self.bulk.for_each_atom(|ijk, atom| if atom == Atom::Dirt { dirt.add(ijk) });
+        self.dirt = dirt;
+        self.update_vacancies();
+        self.recalc_extrema();
     }
 
-    /// Reconstruct the vacancy lists -- needed when lattice has changed.
+    /// Reconstruct the surface and the vacancy lists -- needed when lattice has changed.
     pub fn update_vacancies(&mut self) {
+        self.update_coordination();
+        self.update_surface();
         self.vacancies = Vacancies::new();
 
-        // iterate over all atoms in the bulk
-        for i in self.bulk.i_min-1..=self.bulk.i_max+1 {
-            for j in self.bulk.j_min-1..=self.bulk.j_max+1 {
-                for k in self.bulk.k_min-1..=self.bulk.k_max+1 {
-                    let ijk = IJK{i, j, k};
-                    if self.bulk.get(ijk, Atom::Empty) && !self.hidden_atom(ijk) { 
-                        // iterate over the 12 vacancies around an atom
-                        for l in 0..12 {
-                            let nn_ijk = self.lattice.next_neighbor(ijk,l);
-                            if nn_ijk.k > self.substrate_pos && self.bulk.get(nn_ijk, Atom::Empty) { 
-                                // calc coordiation number and write the position to the associated list
-                                match self.number_of_neighbors(nn_ijk) {
-                                    1 => { self.vacancies.list[0].insert(nn_ijk); },
-                                    x if x>1 && x<9 => {
-                                        self.vacancies.list[x-1].insert(nn_ijk);
-                                        self.vacancies.list[x-2].take(&nn_ijk);
-                                    },
-                                    _=> { }
-                                }
-                            }
-                        } 
-                    }
+        // every vacancy has at least one gold neighbor, which is (by definition) a surface atom
+        let surface: Vec<IJK> = self.surface.list.iter().cloned().collect();
+        for ijk in surface {
+            // iterate over the 12 vacancies around an atom
+            for l in 0..12 {
+                let nn_ijk = self.neighbor(ijk,l);
+                if nn_ijk.i > 1 && nn_ijk.i < FLAKE_MAX.i - 2 
+                    && nn_ijk.j > 1 && nn_ijk.j < FLAKE_MAX.j - 2
+                    && nn_ijk.k > self.substrate_pos && nn_ijk.k < FLAKE_MAX.k - 2
+                    && self.bulk.get(nn_ijk, Atom::Empty) { 
+                        // calc coordiation number and write the position to the associated list
+                        match self.number_of_neighbors(nn_ijk) {
+                            x if x>0 && x<9 => { self.vacancies.list[x-1].insert(nn_ijk); },
+                            _=> { }
+                        }
+                }
+            } 
+        }
+    }
+
+    /// Reconstruct the surface list from the occupied parts of the bulk -- the growth itself keeps it up to date,
+    /// but a changed lattice (stacking faults, screw dislocation) also changes which atoms are hidden.
+    pub fn update_surface(&mut self) {
+        let mut surface = SurfaceAtoms::new();
+        self.bulk.for_each_atom(|ijk, atom| {
+            if atom != Atom::Dirt && !self.hidden_atom(ijk) {
+                surface.add(ijk)
+            }
+        });
+        self.surface = surface;
+    }
+
+    /// Reconstruct the cached coordination numbers from the occupied parts of the bulk.
+    pub fn update_coordination(&mut self) {
+        if !self.cached_coordination() { return }
+        self.coordination.clear();
+        let lattice = &self.lattice;
+        let coordination = &mut self.coordination;
+        self.bulk.for_each_atom(|ijk, atom| {
+            if atom != Atom::Dirt {
+                for l in 0..12 {
+                    coordination.change(lattice.next_neighbor(ijk, l), 1);
+                }
+            }
+        });
+    }
+
+    /// Check if the cached coordination numbers are used -- independent particles have their own lattices, so there they are counted.
+    pub fn cached_coordination(&self) -> bool {
+        self.coordination.enabled() && !self.particles.independent
+    }
+
+    /// Set the number of threads for random_add -- the parallel growth needs the cached coordination numbers, so they are built (or freed) accordingly.
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads;
+        let enabled = COORDINATION || threads > 1;
+        if enabled != self.coordination.enabled() {
+            self.coordination.enable(enabled);
+            self.update_coordination();
+        }
+    }
+
+    /// Put an atom (or Empty) into the bulk and keep the coordination numbers of the neighbors up to date.
+    pub fn set_site(&mut self, ijk: IJK, atom: Atom) {
+        if self.cached_coordination() {
+            let was_metal = self.bulk.is_metal(ijk);
+            let is_metal = matches!(atom, Atom::Gold | Atom::Alloy);
+            if was_metal != is_metal {
+                let change = if is_metal { 1 } else { -1 };
+                for l in 0..12 {
+                    let nn_ijk = self.lattice.next_neighbor(ijk, l);
+                    self.coordination.change(nn_ijk, change);
                 }
             }
         }
+        self.bulk.set(ijk, atom);
+    }
+
+    /// Insert (or remove with None) a screw dislocation -- the vacancies are reconstructed accordingly.
+    /// It does not fit into a periodic film (see Periodic), so there it is refused.
+    pub fn set_screw(&mut self, screw: Option<Screw>) {
+        if screw.is_some() && self.lattice.periodic.is_some() {
+            println!("A screw dislocation does not fit into the periodic film -- not inserted");
+            return
+        }
+        self.lattice.screw = screw;
+        self.update_vacancies();
     }
 
     /// Switch to the next propability list.
     pub fn next_prob_list(&mut self) {     
-        self.prob_list_num = (self.prob_list_num + 1).rem_euclid(PROB_LIST.len());
+        self.set_prob_list(self.prob_list_num + 1);
+    }
+
+    /// Switch to a given propability list.
+    pub fn set_prob_list(&mut self, prob_list_num: usize) {
+        self.prob_list_num = prob_list_num.rem_euclid(PROB_LIST.len());
         self.prob_list = PROB_LIST[self.prob_list_num];
         self.prob_list_log = self.prob_list.iter().map(|&el| (el as f32 + 0.1).log10() as i8).collect::<Vec<i8>>();
         // println!("new prob_list {:?}: 10^{:2?} = {:13?}", self.prob_list_num, self.prob_list_log, self.prob_list);
@@ -110,13 +292,23 @@ impl Crystal {
 
     /// Add a gold atom to the crystal.
     pub fn add_atom(&mut self, ijk: IJK) -> bool {
+        self.add_metal(ijk, Atom::Gold)
+    }
+
+    /// Add a gold or alloy atom to the crystal.
+    pub fn add_metal(&mut self, ijk: IJK, atom: Atom) -> bool {
+        if !within_walls(ijk) {
+            self.reached_boundary();
+            return false
+        }
 
         // check if anything is already at the position
         if self.bulk.get(ijk, Atom::Empty) {
 
             // update bulk an surface
-            self.bulk.set(ijk,Atom::Gold);
-            self.surface.add(ijk);
+            self.set_site(ijk, atom);
+            if self.particles.active() { self.attribute(ijk) }
+            if !self.hidden_atom(ijk) { self.surface.add(ijk) }
             self.update_extrema(ijk);
 
             // update vacancy lists
@@ -127,7 +319,7 @@ impl Crystal {
             for l in 0..12 {                            
                 
                 // check if it is a vacancy and within the boundaries
-                let nn_ijk = self.lattice.next_neighbor(ijk,l);
+                let nn_ijk = self.neighbor(ijk,l);
                 if nn_ijk.i > 1 && nn_ijk.i < FLAKE_MAX.i - 2 
                     && nn_ijk.j > 1 && nn_ijk.j < FLAKE_MAX.j - 2
                     && nn_ijk.k > self.substrate_pos && nn_ijk.k < FLAKE_MAX.k - 2
@@ -160,12 +352,16 @@ impl Crystal {
 
     /// Add a dirt atom to the crystal.
     pub fn add_dirt(&mut self, ijk: IJK) -> bool {
+        if !within_walls(ijk) {
+            self.reached_boundary();
+            return false
+        }
 
         // check if anything is already at the position
         if self.bulk.get(ijk, Atom::Empty) {
 
             // update bulk, surface and vacancy lists
-            self.bulk.set(ijk,Atom::Dirt);
+            self.set_site(ijk, Atom::Dirt);
             self.dirt.add(ijk);
             self.update_extrema(ijk);
             self.vacancies.recursive_remove(ijk, 0);
@@ -177,31 +373,156 @@ impl Crystal {
         }
     }
 
+    /// Remove a gold or dirt atom from the crystal.
+    pub fn remove_atom(&mut self, ijk: IJK) -> bool {
+        let on_border = self.on_extrema(ijk);
+        if self.clear_site(ijk) {
+            if on_border { self.recalc_extrema() }
+            true        // atom removed
+        }
+        else {
+            false       // nothing to remove
+        }
+    }
+
+    /// Remove all atoms in the box spanned by min and max for which the filter returns true
+    /// -- e.g. `|pos| pos.z > 0.0` for removing the upper half.
+    pub fn remove_region<F: Fn(XYZ) -> bool>(&mut self, min: XYZ, max: XYZ, filter: F) -> usize {
+        let mut removed = 0;
+        let mut on_border = false;
+
+        // iterate over box
+        self.lattice.init_box_iter(min, max);
+        for iter_ijk in self.lattice.clone() {
+            if filter(self.lattice.get_xyz(iter_ijk)) {
+                let border = self.on_extrema(iter_ijk);
+                if self.clear_site(iter_ijk) {
+                    on_border |= border;
+                    removed += 1;
+                }
+            }
+        }
+
+        // the extrema only have to be reconstructed once at the end
+        if on_border { self.recalc_extrema() }
+        removed
+    }
+
+    /// Empty a site and update the bookkeeping of its neighborhood.
+    /// The extrema are not touched, so call recalc_extrema afterwards when removing many atoms at once.
+    pub fn clear_site(&mut self, ijk: IJK) -> bool {
+
+        // check what is at the position
+        if self.bulk.is_metal(ijk) {
+            self.set_site(ijk, Atom::Empty);
+            self.surface.remove(ijk);
+            if self.particles.active() { self.particles.detach(ijk) }
+        }
+        else if self.bulk.get(ijk, Atom::Dirt) {
+            self.set_site(ijk, Atom::Empty);
+            self.dirt.remove(ijk);
+        }
+        else {
+            return false        // nothing to remove
+        }
+
+        // the removed position itself might be a vacancy now
+        self.update_vacancy(ijk);
+
+        // iterate over each of the 12 position around the removed atom
+        for l in 0..12 {
+            let nn_ijk = self.neighbor(ijk,l);
+
+            // gold atoms are surface atoms now -- the other ones are re-sorted in the vacancy lists
+            if self.bulk.is_metal(nn_ijk) {
+                self.surface.add(nn_ijk);
+            }
+            else if self.bulk.get(nn_ijk, Atom::Empty) {
+                self.update_vacancy(nn_ijk);
+            }
+        }
+        true
+    }
+
+    /// Put an empty position into the vacancy list matching its coordination number (or remove it from all lists).
+    fn update_vacancy(&mut self, ijk: IJK) {
+        self.vacancies.recursive_remove(ijk, 0);
+        if ijk.i > 1 && ijk.i < FLAKE_MAX.i - 2
+            && ijk.j > 1 && ijk.j < FLAKE_MAX.j - 2
+            && ijk.k > self.substrate_pos && ijk.k < FLAKE_MAX.k - 2
+            && self.bulk.get(ijk, Atom::Empty) {
+
+                // more than 8 neighbors stay in the last used list -- just like during growth
+                match self.number_of_neighbors(ijk) {
+                    0 => {},
+                    x if x<9 => { self.vacancies.list[x-1].insert(ijk); },
+                    _ => { self.vacancies.list[7].insert(ijk); }
+                }
+        }
+    }
+
+    /// Check if a position defines any of the extrema.
+    fn on_extrema(&self, ijk: IJK) -> bool {
+        let e = &self.extrema_ijk;
+        self.bulk.on_border(ijk)
+            || ijk == e.x_min || ijk == e.x_max || ijk == e.y_min || ijk == e.y_max || ijk == e.z_min || ijk == e.z_max
+    }
+
+    /// Reconstruct the extrema from scratch -- needed when atoms have been removed.
+    /// Only surface and dirt atoms can define the extrema, so the bulk does not have to be scanned.
+    pub fn recalc_extrema(&mut self) {
+        self.extrema = Extrema{x_min: 0.0, x_max: 0.0, y_min: 0.0, y_max: 0.0, z_min: 0.0, z_max: 0.0 };
+        self.extrema_ijk = ExtremaCoordinates{x_min: CENTER, x_max: CENTER, y_min: CENTER, y_max: CENTER, z_min: CENTER, z_max: CENTER };
+        self.bulk.reset_extrema();
+        let atoms: Vec<IJK> = self.surface.list.iter().chain(self.dirt.list.iter()).cloned().collect();
+        for ijk in atoms {
+            self.update_extrema(ijk);
+            self.bulk.update_extrema(ijk);
+        }
+    }
+
     /// Check if an atom is hidden inside the bulk, i.e. not at the surface.
     fn hidden_atom(&self, ijk: IJK) -> bool {
-        self.number_of_neighbors(ijk) == 12
+        if self.cached_coordination() { return self.coordination.get(ijk) == 12 }
+        (0..12).all(|l| self.bulk.is_metal(self.neighbor(ijk, l)))
+    }
+
+    /// Get the l-th next neighbor of a position -- with independent particles in the lattice of the particle it belongs to.
+    pub fn neighbor(&self, ijk: IJK, l: usize) -> IJK {
+        if self.particles.independent {
+            self.lattice_at(ijk).next_neighbor(ijk, l)
+        }
+        else {
+            self.lattice.next_neighbor(ijk, l)
+        }
     }
 
     /// Calc the number of neigboring gold atoms for a given position.
-    fn number_of_neighbors(&self, ijk: IJK) -> usize {
+    pub fn number_of_neighbors(&self, ijk: IJK) -> usize {
+        if self.cached_coordination() { return self.coordination.get(ijk) }
         let mut number = 0;
         for l in 0..12 {
-            let nn_ijk = self.lattice.next_neighbor(ijk, l);
-            if self.bulk.get(nn_ijk, Atom::Gold) {
+            let nn_ijk = self.neighbor(ijk, l);
+            if self.bulk.is_metal(nn_ijk) {
                 number += 1;
             }
         }
         number
     }
 
-    /// Pick a random vacancy (which can then be added to the bulk via add_atom).
-    pub fn random_vacancy(&self) -> IJK {
+    /// Pick a random vacancy (which can then be added to the bulk via add_atom) -- None if the chosen list is empty, e.g. without any vacancies.
+    pub fn random_vacancy(&self) -> Option<IJK> {
+        self.random_vacancy_weighted(&self.prob_list)
+    }
+
+    /// Pick a random vacancy with the given probability list.
+    pub fn random_vacancy_weighted(&self, prob_list: &[u64; VAC_LISTS]) -> Option<IJK> {
 
         // set up a weighted probability list (prob_sum)
         let mut probabilities = Vec::<u64>::new();
         let mut prob_sum = Vec::<u64>::new();
         for (index,list) in self.vacancies.list.iter().enumerate(){
-            probabilities.push(self.prob_list[index]*list.len() as u64);
+            probabilities.push(prob_list[index]*list.len() as u64);
             prob_sum.push(probabilities.iter().sum());
         }
 
@@ -214,25 +535,59 @@ impl Crystal {
         if let Some(pos) = prob_sum.iter().position(|&x| x >= random_number) { chosen_list = pos; }
 
         // pick random atom from the chosen list
-        match self.vacancies.list[chosen_list].iter().choose(&mut rand::thread_rng()) {
-            Some(&ijk) => ijk,
-            None => CENTER
-        }         
+        self.vacancies.list[chosen_list].iter().choose(&mut rand::thread_rng()).cloned()
     }
 
 
     /// A slightly faster version of random_vacancy + add_atom
     /// -- useful for adding large amounts of atoms at once.
     pub fn random_add(&mut self, number_of_atoms: usize) {
+        self.grow_randomly(number_of_atoms);
+        self.write_bulk_header();
+    }
+
+    /// Add the atoms in the way chosen for the crystal -- random_add without the header of a bulk file.
+    fn grow_randomly(&mut self, number_of_atoms: usize) {
+        self.check_boundary();
+
+        // a monolayer has its own vacancy classes
+        if self.monolayer.is_some() {
+            return self.monolayer_add(number_of_atoms)
+        }
+
+        // random walkers, a finite supply, a directional flux and alloys add the atoms one by one
+        if self.growth_mode == GrowthMode::Walkers {
+            self.walker_add(number_of_atoms, &STICKING);
+            return
+        }
+        if self.supply.is_some() {
+            return self.supplied_add(number_of_atoms)
+        }
+        if self.flux.is_some() {
+            return self.flux_add(number_of_atoms)
+        }
+        if self.alloy.is_some() {
+            return self.alloy_add(number_of_atoms)
+        }
+        if self.threads > 1 && number_of_atoms >= PARALLEL_MIN_ATOMS && self.parallel_possible() {
+            return self.parallel_add(number_of_atoms)
+        }
 
         // init some often used variables
         let mut random_number: u64 = 0;
         let mut probabilities = Vec::<u64>::new();
         let mut prob_sum = Vec::<u64>::new();
+        let mut added = Vec::<IJK>::new();
         // let mut small_rng = rand::rngs::SmallRng::from_rng(&mut rand::thread_rng()).unwrap();    // is not faster than thread_rng
 
-        // loop to add multiple atoms
-        for _index in 0..number_of_atoms {
+        // loop to add multiple atoms -- the boundary is checked as often as the progress is reported (with a complete surface, as the flake might be moved)
+        for index in 0..number_of_atoms {
+            if self.advance(index) { break }
+            if index > 0 && index % PROGRESS_INTERVAL == 0 && self.near_boundary() {
+                self.add_to_surface(&mut added);
+                self.check_boundary();
+            }
+            if self.impurities.is_some() { self.desorb() }
 
             // set up a weighted probability list (prob_sum)
             probabilities.clear();
@@ -253,14 +608,20 @@ impl Crystal {
                 // pick random atom from the chosen list
                 if let Some(&ijk) = self.vacancies.list[chosen_list].iter().choose(&mut rand::thread_rng()) {       // this might be the most costly step (iter over the btreeset is needed)
                     self.vacancies.list[chosen_list].take(&ijk);
+                    if !self.bulk.get(ijk, Atom::Empty) { continue }        // only possible at the cut of a screw dislocation through stacking faults
+
+                    // an impurity might block the position instead
+                    if self.impurities.is_some() && self.impurity_event(ijk, chosen_list) { continue }
                     
                     // at to bulk and upgrade numbers
-                    self.bulk.set(ijk,Atom::Gold);
+                    self.set_site(ijk, Atom::Gold);
+                    if self.particles.active() { self.attribute(ijk) }
                     self.update_extrema(ijk);
+                    added.push(ijk);
                     
-                    // update vacancies lists
+                    // update vacancies lists and remove now-hidden atoms from the surface
                     for l in 0..12 {
-                        let nn_ijk = self.lattice.next_neighbor(ijk,l);
+                        let nn_ijk = self.neighbor(ijk,l);
                         if nn_ijk.i > 1 && nn_ijk.i < FLAKE_MAX.i - 2 
                             && nn_ijk.j > 1 && nn_ijk.j < FLAKE_MAX.j - 2
                             && nn_ijk.k > self.substrate_pos && nn_ijk.k < FLAKE_MAX.k - 2
@@ -274,30 +635,30 @@ impl Crystal {
                                     _=> { }
                                 }
                         }
+                        else if self.bulk.is_metal(nn_ijk) && self.hidden_atom(nn_ijk) {
+                            self.surface.remove(nn_ijk)
+                        }
                     }
                 }
             }
         }   
         
-        // reconstruct the surface list -- it is much faster to do this only once at the end
-        self.surface.list.clear();
-        for i in self.bulk.i_min..=self.bulk.i_max {
-            for j in self.bulk.j_min..=self.bulk.j_max {
-                for k in self.bulk.k_min..=self.bulk.k_max {
-                    let ijk = IJK{i, j, k};
-                    if self.bulk.get(ijk, Atom::Gold) && !self.hidden_atom(ijk) {             
-                        self.surface.add(ijk)
-                    }
-                    
-                }
-            }
+        self.add_to_surface(&mut added);
+    }
+
+    /// The added atoms are put into the surface list at the end of the growth -- most of them are already hidden then
+    /// and the sorted insertion of the others is much faster than during the growth.
+    pub fn add_to_surface(&mut self, added: &mut Vec<IJK>) {
+        added.sort();
+        for ijk in added.drain(..) {
+            if !self.hidden_atom(ijk) { self.surface.add(ijk) }
         }
     }
 
     
     /// Update the extrama positions and coordinates of the crystal.
     pub fn update_extrema(&mut self, ijk: IJK) {
-        let xyz = self.lattice.get_xyz(ijk);
+        let xyz = self.lattice_at(ijk).get_xyz(ijk);
         if xyz.x < self.extrema.x_min {
             self.extrema.x_min = xyz.x;
             self.extrema_ijk.x_min = ijk;
@@ -328,35 +689,13 @@ impl Crystal {
 
     /// Calculate height, width, depth and aspect ratio of the crystal.
     pub fn get_size(&self) -> [f32;4] {
-        let h = (self.extrema.z_max-self.extrema.z_min) + DIAMETER;
-        let w = (self.extrema.x_max-self.extrema.x_min) + DIAMETER;
-        let d = (self.extrema.y_max-self.extrema.y_min) + DIAMETER;
-        let r = ( w  * d ).sqrt() / h;
-        [h,w,d,r]
+        self.extrema.size()
     }
 
 
     /// Calculate the coordinates of a hexagon spanned by the extrema.
     pub fn get_hexagon(&self) -> [f32;12] {
-
-        // left side
-        let x_min = self.extrema_ijk.x_min;
-        let delta1 = self.extrema_ijk.y_max.j as isize - self.extrema_ijk.x_min.j as isize;
-        let delta2 = self.extrema_ijk.x_min.j as isize - self.extrema_ijk.y_min.j as isize;
-        let a = self.lattice.get_xyz(IJK{i: x_min.i, j: (x_min.j as isize + delta1) as u16, k: x_min.k});
-        let b = self.lattice.get_xyz(self.extrema_ijk.x_min);
-        let c = self.lattice.get_xyz(IJK{i: (x_min.i as isize + delta2) as u16, j: (x_min.j as isize - delta2) as u16, k: x_min.k});
-
-        //right side
-        let x_max = self.extrema_ijk.x_max;
-        let delta3 = self.extrema_ijk.y_min.j as isize - self.extrema_ijk.x_max.j as isize;
-        let delta4 = self.extrema_ijk.x_max.j as isize - self.extrema_ijk.y_max.j as isize;
-        let d = self.lattice.get_xyz(IJK{i: x_max.i, j: (x_max.j as isize + delta3) as u16, k: x_max.k});
-        let e = self.lattice.get_xyz(self.extrema_ijk.x_max);
-        let f = self.lattice.get_xyz(IJK{i: (x_max.i as isize + delta4) as u16, j: (x_max.j as isize - delta4) as u16, k: x_max.k});
-
-        // output of the xy coordinates
-        [a.x,a.y, b.x,b.y, c.x,c.y, d.x,d.y, e.x,e.y, f.x,f.y]
+        self.extrema_ijk.hexagon(&self.lattice)
     }
 
 
@@ -380,6 +719,7 @@ impl Crystal {
                         Atom::Empty => {}
                         Atom::Gold  => {self.add_atom(iter_ijk);},
                         Atom::Dirt  => {self.add_dirt(iter_ijk);},
+                        Atom::Alloy => {self.add_metal(iter_ijk, Atom::Alloy);},
                     }
                 }
     
@@ -403,6 +743,7 @@ impl Crystal {
                 Atom::Empty => {}
                 Atom::Gold  => {self.add_atom(iter_ijk);},
                 Atom::Dirt  => {self.add_dirt(iter_ijk);},
+                Atom::Alloy => {self.add_metal(iter_ijk, Atom::Alloy);},
             }
         }
     }
@@ -674,11 +1015,18 @@ impl Crystal {
     #[cfg(not(target_arch = "wasm32"))]
     pub fn statistics(&mut self) -> usize {
 
+        // periodic films have their own statistics
+        if self.lattice.periodic.is_some() { return self.film_statistics() }
+
+        // the geometric stop conditions replace the STOP_MARKS if given
+        let steps = if STOP_CONDITIONS.is_empty() { STOP_MARKS.len() } else { STOP_CONDITIONS.len() };
+
         // create file and write header
-        let filename = format!("{}_sub{}_stack{:?}_cycles{}_steps{}_p{}.csv", FILENAME, self.substrate_pos ,self.lattice.stacking_faults, NUMBER_OF_CYCLES, STOP_MARKS.len(), self.prob_list_num + 1);
+        let filename = format!("{}_sub{}_stack{:?}_cycles{}_steps{}_p{}.csv", FILENAME, self.substrate_pos ,self.lattice.stacking_faults, NUMBER_OF_CYCLES, steps, self.prob_list_num + 1);
         let mut f = File::create(filename).expect("Unable to create file"); 
-        write!(f, "substrate: {} stacking: {:?} cycles: {} steps {} prob_list_log p{} -- {:?}\n", self.substrate_pos ,self.lattice.stacking_faults, NUMBER_OF_CYCLES, STOP_MARKS.len(), self.prob_list_num + 1, self.prob_list_log).expect("Unable to write in file");
-        write!(f, "atoms, aspect ratio, k_min, k_max, len1, len2, len ratio\n").expect("Unable to write in file");
+        write!(f, "substrate: {} stacking: {:?} cycles: {} steps {} prob_list_log p{} -- {:?}\n", self.substrate_pos ,self.lattice.stacking_faults, NUMBER_OF_CYCLES, steps, self.prob_list_num + 1, self.prob_list_log).expect("Unable to write in file");
+        if !STOP_CONDITIONS.is_empty() { writeln!(f, "stop conditions: {:?}", STOP_CONDITIONS).expect("Unable to write in file") }
+        write!(f, "atoms, aspect ratio, k_min, k_max, len1, len2, len ratio, boundary\n").expect("Unable to write in file");
         // write!(f, "atoms, aspect ratio, k_min, k_max, a.x, a.y, b.x, b.y, c.x, c.y, d.x, d.y, e.x, e.y, f.x, f.y\n").expect("Unable to write in file");
         
         // create atoms-to-add-list from the defined STOP_MARKS
@@ -690,18 +1038,30 @@ impl Crystal {
         // repeate the flake growth for several cycles
         let mut added_atoms: usize = 0;
         for i in 0..NUMBER_OF_CYCLES {
+            if self.cancelled() { break }
 
             // prepare the same zero conditions for every cycle
             added_atoms = 0;
             self.clear();
-            self.add_atom(CENTER);
+            if SEEDS_IN_STATISTICS {
+                let seeds = random_seeds(SEEDS, SEED_RADIUS, INDEPENDENT_STACKINGS, &self.lattice);
+                self.add_seeds(&seeds);
+            }
+            else {
+                self.add_atom(CENTER);
+            }
 
             // start the growth
             println!("{}/{}", i+1 , NUMBER_OF_CYCLES);
-            for add_atoms in atoms_to_add.iter() {
+            for step in 0..steps {
 
                 // the growth
-                self.random_add(*add_atoms);
+                let add_atoms = match STOP_CONDITIONS.get(step) {
+                    Some(condition) => self.grow_until(condition),
+                    None => { self.random_add(atoms_to_add[step]); atoms_to_add[step] },
+                };
+                // a cancelled job stops here -- the interrupted step did not reach its mark, so it is not written
+                if self.cancelled() { break }
                 
                 // update the variables and print them / write them to the file
                 added_atoms += add_atoms;
@@ -710,13 +1070,14 @@ impl Crystal {
                 let len1 = ((ax-bx).powi(2) + (ay-by).powi(2)).sqrt();
                 let len2 = ((cx-bx).powi(2) + (cy-by).powi(2)).sqrt();
                 println!("{:>15} atoms -- aspect ratio: {:>4.1}, length ratio: {:>5.3}", added_atoms, r, len1/(len1+len2));
-                write!(f, "{}, {}, {}, {}, {}, {}, {}", added_atoms, r, self.bulk.k_min, self.bulk.k_max, len1, len2, len1/(len1+len2)).unwrap();
+                write!(f, "{}, {}, {}, {}, {}, {}, {}, {}", added_atoms, r, self.bulk.k_min, self.bulk.k_max, len1, len2, len1/(len1+len2), self.boundary.is_some() as u8).unwrap();
                 // let hexagon =  self.get_hexagon();
                 // for element in hexagon.iter() { 
                 //     write!(f, ", {}", element).unwrap() 
                 // };
                  write!(f, "\n").unwrap();
             }
+            if self.particles.active() { self.particles.report() }
         }
 
         println!(" ...finished");
@@ -726,30 +1087,187 @@ impl Crystal {
     }
 
     /// Save the positions of all atoms as `XXX_number-of-atoms_YYY.csv`, where XXX is given by the FILENAME defined in parameters.rs and YYY is current number of atoms.
+    /// The last column is the species (gold or alloy), dirt atoms are not saved.
     #[cfg(not(target_arch = "wasm32"))]
     pub fn save(&mut self) {
 
         // create file and write header
         let filename = format!("{}_number-of-atoms_{}.csv", FILENAME, self.bulk.number_of_atoms);
-        let mut f = File::create(filename).expect("Unable to create file"); 
-        writeln!(f, "Bulk atoms: x, y, z").expect("Unable to write in file");
+        let mut f = BufWriter::new(File::create(filename).expect("Unable to create file")); 
+        writeln!(f, "Bulk atoms: x, y, z, species").expect("Unable to write in file");
         
         // start saving
         println!("Saving flake...");
             // iterate over all atoms in the bulk
-            for i in self.bulk.i_min-1..=self.bulk.i_max+1 {
-                for j in self.bulk.j_min-1..=self.bulk.j_max+1 {
-                    for k in self.bulk.k_min-1..=self.bulk.k_max+1 {
-                        let ijk = IJK{i, j, k};
-                        if self.bulk.get(ijk, Atom::Gold) { 
-                            // get and save the coordinates
-                            let pos = self.lattice.get_xyz(ijk);
-                            write!(f, "{}, {}, {}\n", pos.x, pos.y, pos.z).unwrap();
-                        }
-                    }
+            let lattice = &self.lattice;
+            self.bulk.for_each_atom(|ijk, atom| {
+                if atom != Atom::Dirt { 
+                    // get and save the coordinates
+                    let pos = lattice.get_xyz(ijk);
+                    let species = if atom == Atom::Alloy { "alloy" } else { "gold" };
+                    write!(f, "{}, {}, {}, {}\n", pos.x, pos.y, pos.z, species).unwrap();
                 }
-            }            
+            });
 
         println!("...finished");
     }
 }
+
+
+// a bulk file is left with the current lattice in its header
+impl Drop for Crystal {
+    fn drop(&mut self) {
+        self.write_bulk_header();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vacancy_list(flake: &Crystal, ijk: IJK) -> Option<usize> {
+        flake.vacancies.list.iter().position(|list| list.contains(&ijk))
+    }
+
+    #[test]
+    fn removal_reclassifies_vacancies() {
+        let mut flake = Crystal::new(Lattice::new(vec![], DIAMETER));
+        let b = flake.lattice.next_neighbor(CENTER, 0);
+        let c = flake.lattice.next_neighbor(CENTER, 1);         // common neighbor of CENTER and b
+        flake.add_atom(CENTER);
+        flake.add_atom(b);
+        assert_eq!(vacancy_list(&flake, c), Some(1));
+
+        // the removed position and the common neighbor have one neighbor left
+        assert!(flake.remove_atom(b));
+        assert_eq!((vacancy_list(&flake, b), vacancy_list(&flake, c)), (Some(0), Some(0)));
+        assert_eq!(flake.surface.list.iter().cloned().collect::<Vec<IJK>>(), vec![CENTER]);
+        assert_eq!(flake.bulk.number_of_atoms, 1);
+
+        // nothing is left afterwards
+        assert!(flake.remove_atom(CENTER));
+        assert!(!flake.remove_atom(CENTER));
+        assert!(flake.vacancies.list.iter().all(|list| list.is_empty()));
+        assert!(flake.surface.list.is_empty());
+
+        // dirt atoms are removed from their own list
+        flake.add_dirt(CENTER);
+        assert!(flake.remove_atom(CENTER));
+        assert!(flake.dirt.list.is_empty() && flake.bulk.number_of_atoms == 0);
+    }
+
+    #[test]
+    fn clear_site_uncovers_hidden_atoms() {
+        let mut flake = Crystal::new(Lattice::new(vec![], DIAMETER));
+        flake.add_atom(CENTER);
+        for l in 0..12 {
+            flake.add_atom(flake.lattice.next_neighbor(CENTER, l));
+        }
+        assert!(!flake.surface.list.contains(&CENTER));
+
+        // removing a neighbor makes the center a surface atom and its position a vacancy sorted by the remaining neighbors
+        let neighbor = flake.lattice.next_neighbor(CENTER, 6);
+        assert!(flake.clear_site(neighbor));
+        assert!(flake.surface.list.contains(&CENTER));
+        assert_eq!(vacancy_list(&flake, neighbor), Some(flake.number_of_neighbors(neighbor) - 1));
+    }
+
+    #[test]
+    fn incremental_bookkeeping_matches_a_rebuild() {
+        use rand::seq::IteratorRandom;
+        let mut flake = Crystal::new(Lattice::new(STACKING_FAULTS.to_vec(), DIAMETER));
+        flake.add_atom(CENTER);
+        flake.random_add(20_000);
+
+        // remove random surface atoms and a whole region, then grow again
+        let mut rng = rand::thread_rng();
+        let removed: Vec<IJK> = flake.surface.list.iter().cloned().choose_multiple(&mut rng, 500);
+        for ijk in removed {
+            flake.remove_atom(ijk);
+        }
+        flake.remove_region(XYZ{x: 0.0, y: -20.0, z: -20.0}, XYZ{x: 20.0, y: 20.0, z: 20.0}, |pos| pos.y > 0.0);
+        flake.random_add(5_000);
+
+        // the coordination numbers are the counted neighbors
+        let coordination = |flake: &Crystal, ijk: IJK| (0..12).filter(|&l| flake.bulk.is_metal(flake.neighbor(ijk, l))).count();
+        for ijk in flake.surface.list.iter().chain(flake.vacancies.list.iter().flatten()) {
+            assert_eq!(flake.number_of_neighbors(*ijk), coordination(&flake, *ijk));
+        }
+
+        // more than 8 neighbors stay in the last list during the growth, the rebuild only sorts in up to 8 neighbors
+        let surface = flake.surface.list.clone();
+        let mut vacancies = flake.vacancies.list.clone();
+        vacancies[7].retain(|&ijk| coordination(&flake, ijk) <= 8);
+        flake.update_vacancies();
+        assert_eq!(surface, flake.surface.list);
+        assert_eq!(vacancies, flake.vacancies.list);
+    }
+
+    #[test]
+    fn cached_coordination_follows_the_threads() {
+        let mut flake = Crystal::new(Lattice::new(STACKING_FAULTS.to_vec(), DIAMETER));
+        flake.set_threads(1);
+        assert_eq!(flake.cached_coordination(), COORDINATION);
+        flake.add_atom(CENTER);
+        flake.random_add(2000);
+        let counted = |flake: &Crystal| -> Vec<usize> {
+            flake.vacancies.list.iter().flatten().map(|&ijk| (0..12).filter(|&l| flake.bulk.is_metal(flake.neighbor(ijk, l))).count()).collect()
+        };
+        let numbers = |flake: &Crystal| -> Vec<usize> {
+            flake.vacancies.list.iter().flatten().map(|&ijk| flake.number_of_neighbors(ijk)).collect()
+        };
+
+        // switching on rebuilds the numbers, afterwards they are kept up to date
+        flake.set_threads(2);
+        assert!(flake.cached_coordination());
+        assert_eq!(numbers(&flake), counted(&flake));
+        flake.random_add(2000);
+        flake.remove_region(XYZ{x: 0.0, y: -20.0, z: -20.0}, XYZ{x: 20.0, y: 20.0, z: 20.0}, |pos| pos.x > 1.0);
+        assert_eq!(numbers(&flake), counted(&flake));
+        flake.clear();
+        assert!(flake.cached_coordination());
+
+        // switching off frees them again
+        flake.set_threads(1);
+        assert_eq!(flake.coordination.enabled(), COORDINATION);
+    }
+
+    #[test]
+    fn reopened_crystal_continues_on_its_lattice() {
+        let path = std::env::temp_dir().join(format!("{}_crystal_{}.bulk", FILENAME, std::process::id()));
+        let path = path.to_str().unwrap();
+        let (surface, vacancies) = {
+            let mut flake = Crystal::with_bulk(Lattice::new(vec![CENTER.k + 2], DIAMETER), Bulk::create(path).unwrap());
+            flake.add_atom(CENTER);
+            flake.random_add(3000);
+            flake.set_screw(Some(Screw{i: CENTER.i, j: CENTER.j, burgers: 3}));
+            (flake.surface.list.clone(), flake.vacancies.list.iter().map(|list| list.len()).sum::<usize>())
+        };
+
+        // the screw was inserted after the last random_add, so it comes from dropping the crystal
+        let flake = Crystal::reopen(Bulk::open(path).unwrap());
+        assert_eq!(flake.lattice.stacking_faults, vec![CENTER.k + 2]);
+        assert_eq!(flake.lattice.screw.map(|screw| screw.burgers), Some(3));
+        assert_eq!(flake.bulk.number_of_atoms, 3001);
+        assert_eq!(flake.surface.list, surface);
+        assert_eq!(flake.vacancies.list.iter().map(|list| list.len()).sum::<usize>(), vacancies);
+        drop(flake);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn periodic_film_refuses_a_screw() {
+        let mut flake = Crystal::new(Lattice::new(vec![], DIAMETER));
+        flake.set_periodic(Some(20));
+        flake.set_screw(Some(Screw{i: CENTER.i, j: CENTER.j, burgers: 3}));
+        assert!(flake.lattice.screw.is_none());
+
+        // and a new film removes an existing one
+        flake.set_periodic(None);
+        flake.set_screw(Some(Screw{i: CENTER.i, j: CENTER.j, burgers: 3}));
+        assert!(flake.lattice.screw.is_some());
+        flake.set_periodic(Some(20));
+        assert!(flake.lattice.screw.is_none());
+    }
+}
@@ -0,0 +1,239 @@
+/*!
+Finite precursor supply via a coarse diffusion field
+
+Instead of an infinite and uniform supply, the gold precursor concentration is stored on a coarse grid around the flake.
+The outer faces of the grid are kept at the bulk concentration (the bottom face is closed when there is a substrate) and in between
+the concentration relaxes towards the stationary solution of the diffusion equation via Jacobi sweeps.
+
+A vacancy is picked as before (according to its coordination number) but only accepted with the probability c/c_bulk of its cell.
+Every accepted atom consumes precursor in its cell. Every attempt counts as a time step, so depleted regions refill over time,
+and tips sticking out into the solution get more material than the center of a facet (diffusion-limited growth).
+*/
+
+use ndarray::Array3;
+use rand::Rng;
+
+use crate::helpers::*;
+use crate::parameters::*;
+use crate::crystal::*;
+
+#[cfg(target_arch = "wasm32")]
+use crate::println;
+
+
+#[derive(Clone)]
+pub struct ConcentrationField {
+    pub concentration: Array3<f32>,
+    pub cell_size: f32,
+    pub atoms_per_cell: f32,
+    pub sweeps: usize,
+    pub closed_bottom: bool,
+    pub closed: bool,
+    pub deficit: f32,                       // precursor consumed beyond an empty cell (relative to a cell at bulk concentration)
+}
+
+impl ConcentrationField {
+    /// A field of the given number of cells (with a size in nm) centered around the origin and filled with the bulk concentration.
+    pub fn new(cells: [usize; 3], cell_size: f32) -> Self {
+        ConcentrationField{
+            concentration:  Array3::<f32>::ones((cells[0], cells[1], cells[2])),
+            cell_size,
+            atoms_per_cell: SUPPLY_ATOMS_PER_CELL,
+            sweeps:         SUPPLY_SWEEPS,
+            closed_bottom:  false,
+            closed:         false,
+            deficit:        0.0,
+        }
+    }
+
+    /// Back to the bulk concentration everywhere.
+    pub fn reset(&mut self) {
+        self.concentration.fill(1.0);
+        self.deficit = 0.0;
+    }
+
+    /// The cell containing a position -- positions outside of the grid belong to the outermost cells.
+    pub fn cell(&self, xyz: XYZ) -> (usize, usize, usize) {
+        let shape = self.concentration.shape();
+        let index = |value: f32, n: usize| ((value/self.cell_size + n as f32/2.0).floor().max(0.0) as usize).min(n - 1);
+        (index(xyz.x, shape[0]), index(xyz.y, shape[1]), index(xyz.z, shape[2]))
+    }
+
+    /// Relative concentration at a position.
+    pub fn get(&self, xyz: XYZ) -> f32 {
+        self.concentration[self.cell(xyz)]
+    }
+
+    /// Remove the precursor of one atom at a position -- the concentration does not become negative, what is missing is booked as deficit.
+    pub fn consume(&mut self, xyz: XYZ) {
+        let cell = self.cell(xyz);
+        let amount = 1.0/self.atoms_per_cell;
+        let available = self.concentration[cell].min(amount).max(0.0);
+        self.concentration[cell] -= available;
+        self.deficit += amount - available;
+    }
+
+    /// Put the precursor of one atom back at a position -- an open deficit is paid off first.
+    pub fn release(&mut self, xyz: XYZ) {
+        let cell = self.cell(xyz);
+        let amount = 1.0/self.atoms_per_cell;
+        let repaid = self.deficit.min(amount);
+        self.deficit -= repaid;
+        self.concentration[cell] += amount - repaid;
+    }
+
+    /// Total amount of precursor in the field in atoms (without the deficit).
+    pub fn atoms(&self) -> f32 {
+        ((self.concentration.sum() - self.deficit)*self.atoms_per_cell).max(0.0)
+    }
+
+    /// Relax the concentration via Jacobi sweeps with the bulk concentration at the outer faces (or no flux at all when closed).
+    pub fn relax(&mut self) {
+        let shape = self.concentration.shape().to_vec();
+        let (nx, ny, nz) = (shape[0], shape[1], shape[2]);
+        let mut next = self.concentration.clone();
+        for _sweep in 0..self.sweeps {
+            for x in 1..nx-1 {
+                for y in 1..ny-1 {
+                    for z in 1..nz-1 {
+                        next[(x, y, z)] = (self.concentration[(x-1, y, z)] + self.concentration[(x+1, y, z)]
+                                         + self.concentration[(x, y-1, z)] + self.concentration[(x, y+1, z)]
+                                         + self.concentration[(x, y, z-1)] + self.concentration[(x, y, z+1)])/6.0;
+                    }
+                }
+            }
+            // the outer faces are kept at the bulk concentration -- or have no flux through them at a substrate (bottom) or when closed
+            let open = |closed: bool, inner: f32| if closed { inner } else { 1.0 };
+            for x in 0..nx {
+                for y in 0..ny {
+                    next[(x, y, 0)] = open(self.closed_bottom || self.closed, next[(x, y, 1)]);
+                    next[(x, y, nz-1)] = open(self.closed, next[(x, y, nz-2)]);
+                }
+                for z in 0..nz {
+                    next[(x, 0, z)] = open(self.closed, next[(x, 1, z)]);
+                    next[(x, ny-1, z)] = open(self.closed, next[(x, ny-2, z)]);
+                }
+            }
+            for y in 0..ny {
+                for z in 0..nz {
+                    next[(0, y, z)] = open(self.closed, next[(1, y, z)]);
+                    next[(nx-1, y, z)] = open(self.closed, next[(nx-2, y, z)]);
+                }
+            }
+            std::mem::swap(&mut self.concentration, &mut next);
+        }
+    }
+}
+
+
+impl Crystal {
+    /// Switch the finite supply on or off.
+    pub fn switch_supply(&mut self) {
+        self.supply = match self.supply {
+            Some(_) => None,
+            None => Some(ConcentrationField::new(SUPPLY_CELLS, SUPPLY_CELL_SIZE)),
+        };
+    }
+
+    /// Add atoms from the finite supply -- used by random_add when a concentration field is present.
+    pub fn supplied_add(&mut self, number_of_atoms: usize) {
+        let mut field = match self.supply.take() {
+            Some(field) => field,
+            None => return,
+        };
+        field.closed_bottom = self.substrate_pos > 1;
+
+        let mut rng = rand::thread_rng();
+        let mut added = 0;
+        let mut attempts = 0;
+        let mut failed = 0;
+        while added < number_of_atoms {
+            let ijk = match self.random_vacancy() {
+                Some(ijk) => ijk,
+                None => break,
+            };
+            let xyz = self.lattice_at(ijk).get_xyz(ijk);
+
+            // the vacancy is only filled with the probability of the local concentration
+            if rng.gen::<f32>() < field.get(xyz) && self.add_atom(ijk) {
+                field.consume(xyz);
+                added += 1;
+                failed = 0;
+                if self.advance(added) { break }
+            }
+            else {
+                // the supply is exhausted (e.g. in a closed field)
+                failed += 1;
+                if failed == SUPPLY_MAX_FAILS {
+                    println!("No atom added from the supply in {} attempts -- stopped after {} atoms", SUPPLY_MAX_FAILS, added);
+                    break
+                }
+            }
+
+            // the precursor diffuses in the meantime
+            attempts += 1;
+            if attempts % SUPPLY_INTERVAL == 0 {
+                field.relax();
+            }
+        }
+        self.supply = Some(field);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lattice::*;
+
+    #[test]
+    fn outer_faces_stay_at_bulk_concentration() {
+        let mut field = ConcentrationField::new([8, 8, 8], 1.0);
+
+        // positions outside of the grid deplete the outer cells
+        let outside = XYZ{x: 100.0, y: 0.0, z: 0.0};
+        for _ in 0..1000 {
+            field.consume(outside);
+        }
+        assert!(field.get(outside) < 1.0);
+        field.relax();
+        assert_eq!(field.get(outside), 1.0);
+    }
+
+    #[test]
+    fn consume_books_the_deficit() {
+        let mut field = ConcentrationField::new([8, 8, 8], 1.0);
+        let center = XYZ{x: 0.0, y: 0.0, z: 0.0};
+        field.concentration.fill(0.0);
+        field.consume(center);
+        assert_eq!(field.get(center), 0.0);
+        assert!(field.deficit > 0.0);
+        field.release(center);
+        assert_eq!(field.deficit, 0.0);
+        assert_eq!(field.get(center), 0.0);
+        field.release(center);
+        assert!((field.atoms() - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn closed_field_keeps_a_uniform_concentration() {
+        let mut field = ConcentrationField::new([8, 8, 8], 1.0);
+        field.closed = true;
+        field.concentration.fill(0.5);
+        field.relax();
+        assert!(field.concentration.iter().all(|&c| (c - 0.5).abs() < 1e-6));
+    }
+
+    #[test]
+    fn exhausted_supply_stops_the_growth() {
+        let mut flake = Crystal::new(Lattice::new(STACKING_FAULTS.to_vec(), DIAMETER));
+        flake.add_atom(CENTER);
+        flake.switch_supply();
+        if let Some(field) = flake.supply.as_mut() {
+            field.closed = true;
+            field.concentration.fill(0.0);
+        }
+        flake.random_add(10);
+        assert_eq!(flake.bulk.number_of_atoms, 1);
+    }
+}
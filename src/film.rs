@@ -0,0 +1,185 @@
+/*!
+Thin films with periodic boundary conditions
+
+Instead of a single flake a periodic cell of FILM_PERIOD x FILM_PERIOD positions (in i and j) is grown: the neighbors are mapped back
+into the cell by the lattice (see Periodic), so the film has no edges and the whole top surface is the growth front.
+The film starts with one complete layer on the substrate, the stacking faults of the lattice stay as they are (a screw dislocation
+does not fit into the periodic cell and is removed).
+
+The morphology is measured over the cell from the coverages θ_k of the layers: the thickness is the sum of the coverages
+and the roughness the standard deviation of the local heights, w² = Σ (k - h)² (θ_k - θ_k+1). The islands are the connected clusters
+(in-plane neighbors) of the incomplete layers -- their density is given per µm² of the cell.
+*/
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::{io::Write, fs::File};
+use std::collections::VecDeque;
+
+use crate::helpers::*;
+use crate::parameters::*;
+use crate::lattice::*;
+use crate::storage::*;
+use crate::crystal::*;
+
+#[cfg(target_arch = "wasm32")]
+use crate::println;
+
+
+/// Morphology of the top surface of a periodic film -- thickness and roughness in nm.
+#[derive(Copy,Clone,Debug)]
+pub struct FilmMorphology {
+    pub thickness: f32,
+    pub roughness: f32,
+    pub open_layers: usize,
+    pub islands: usize,
+    pub island_density: f32,
+}
+
+impl Crystal {
+    /// Restart as a periodic film with the given period -- or as a single atom again with None.
+    pub fn set_periodic(&mut self, period: Option<u16>) {
+        self.clear();
+        match period {
+            Some(period) => {
+                let periodic = Periodic::new(period);
+                self.lattice.screw = None;
+                self.lattice.periodic = Some(periodic);
+                self.substrate_pos = CENTER.k - 1;
+
+                // a monolayer grows from a seed in the periodic cell -- a complete first layer would leave it no sites
+                if self.monolayer.is_some() {
+                    self.add_atom(CENTER);
+                    return
+                }
+                for i in periodic.i_start..periodic.i_start + period {
+                    for j in periodic.j_start..periodic.j_start + period {
+                        self.bulk.set(IJK{i, j, k: CENTER.k}, Atom::Gold);
+                    }
+                }
+                // the layer is set at once and the surface and vacancies are built afterwards (much faster for large periods)
+                self.reconstruct();
+            },
+            None => {
+                self.lattice.periodic = None;
+                self.substrate_pos = 1;
+                self.add_atom(CENTER);
+            },
+        }
+    }
+
+    /// Check if an (occupied) position belongs to the film.
+    fn film_atom(&self, ijk: IJK) -> bool {
+        !self.bulk.get(ijk, Atom::Empty) && !self.bulk.get(ijk, Atom::Dirt)
+    }
+
+    /// Measure the thickness, the roughness and the islands of the film -- None without periodic boundaries.
+    pub fn film_morphology(&self) -> Option<FilmMorphology> {
+        let periodic = self.lattice.periodic?;
+        let period = periodic.period as usize;
+        let cell = (period*period) as f32;
+        let layer_height = self.lattice.get_xyz(IJK{k: CENTER.k + 1, ..CENTER}).z - self.lattice.get_xyz(CENTER).z;
+
+        // coverages of all layers above the substrate and the islands of the incomplete ones
+        let mut coverages = Vec::new();
+        let mut open_layers = 0;
+        let mut islands = 0;
+        for k in self.substrate_pos + 1..=self.bulk.k_max {
+            let mut atoms = 0;
+            for i in periodic.i_start..periodic.i_start + periodic.period {
+                for j in periodic.j_start..periodic.j_start + periodic.period {
+                    if self.film_atom(IJK{i, j, k}) { atoms += 1 }
+                }
+            }
+            if atoms == 0 { break }
+            if atoms < period*period {
+                open_layers += 1;
+                islands += self.count_islands(periodic, k);
+            }
+            coverages.push(atoms as f32/cell);
+        }
+
+        // the local height is n (layers) with the probability θ_n - θ_n+1
+        let height: f32 = coverages.iter().sum();
+        let mut variance = (1.0 - coverages.first().unwrap_or(&0.0))*height.powi(2);
+        for (index, &coverage) in coverages.iter().enumerate() {
+            let above = coverages.get(index + 1).unwrap_or(&0.0);
+            variance += (coverage - above)*((index + 1) as f32 - height).powi(2);
+        }
+
+        let area = cell*DIAMETER.powi(2)*(3.0f32).sqrt()/2.0;        // in nm²
+        Some(FilmMorphology{
+            thickness: height*layer_height,
+            roughness: variance.max(0.0).sqrt()*layer_height,
+            open_layers,
+            islands,
+            island_density: islands as f32/area*1e6,
+        })
+    }
+
+    /// Count the connected clusters of atoms in layer k of the cell.
+    fn count_islands(&self, periodic: Periodic, k: u16) -> usize {
+        let period = periodic.period as usize;
+        let index = |ijk: IJK| (ijk.i - periodic.i_start) as usize*period + (ijk.j - periodic.j_start) as usize;
+        let mut visited = vec![false; period*period];
+        let mut islands = 0;
+        for i in periodic.i_start..periodic.i_start + periodic.period {
+            for j in periodic.j_start..periodic.j_start + periodic.period {
+                let start = IJK{i, j, k};
+                if visited[index(start)] || !self.film_atom(start) { continue }
+
+                // flood fill over the in-plane neighbors (0-5), which are wrapped into the cell
+                islands += 1;
+                visited[index(start)] = true;
+                let mut queue = VecDeque::from(vec![start]);
+                while let Some(ijk) = queue.pop_front() {
+                    for l in 0..6 {
+                        let nn_ijk = self.lattice.next_neighbor(ijk, l);
+                        if !visited[index(nn_ijk)] && self.film_atom(nn_ijk) {
+                            visited[index(nn_ijk)] = true;
+                            queue.push_back(nn_ijk);
+                        }
+                    }
+                }
+            }
+        }
+        islands
+    }
+
+    /// The statistics for periodic films: the growth from a complete layer is repeated NUMBER_OF_CYCLES times
+    /// and the morphology is written for all STOP_MARKS (counted without the first layer).
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn film_statistics(&mut self) -> usize {
+        let period = match self.lattice.periodic {
+            Some(periodic) => periodic.period,
+            None => return 0,
+        };
+
+        // create file and write header
+        let filename = format!("{}_film_period{}_stack{:?}_cycles{}_steps{}_p{}.csv", FILENAME, period, self.lattice.stacking_faults, NUMBER_OF_CYCLES, STOP_MARKS.len(), self.prob_list_num + 1);
+        let mut f = File::create(filename).expect("Unable to create file");
+        writeln!(f, "film period: {} stacking: {:?} cycles: {} steps {} prob_list_log p{} -- {:?}", period, self.lattice.stacking_faults, NUMBER_OF_CYCLES, STOP_MARKS.len(), self.prob_list_num + 1, self.prob_list_log).expect("Unable to write in file");
+        writeln!(f, "atoms, thickness, roughness, open layers, islands, island density, boundary").expect("Unable to write in file");
+
+        let mut added_atoms: usize = 0;
+        for cycle in 0..NUMBER_OF_CYCLES {
+            if self.cancelled() { break }
+
+            // every cycle starts from the complete layer
+            added_atoms = 0;
+            self.set_periodic(Some(period));
+            println!("{}/{}", cycle + 1, NUMBER_OF_CYCLES);
+            for &mark in STOP_MARKS.iter() {
+                self.random_add(mark - added_atoms);
+                // a cancelled job stops here -- the interrupted step did not reach its mark, so it is not written
+                if self.cancelled() { break }
+                added_atoms = mark;
+                if let Some(film) = self.film_morphology() {
+                    println!("{:>15} atoms -- thickness: {:>6.2}, roughness: {:>5.3}, islands: {}", added_atoms, film.thickness, film.roughness, film.islands);
+                    writeln!(f, "{}, {}, {}, {}, {}, {}, {}", added_atoms, film.thickness, film.roughness, film.open_layers, film.islands, film.island_density, self.boundary.is_some() as u8).unwrap();
+                }
+            }
+        }
+        println!(" ...finished");
+        added_atoms
+    }
+}
@@ -0,0 +1,257 @@
+/*!
+Directional deposition flux with geometric shadowing
+
+For vapor deposition or sedimentation the atoms arrive from one direction. The source direction is given by a tilt angle
+(measured from the surface normal, i.e. the z-axis) and an azimuth (measured from the x-axis) and every arriving atom deviates from it
+by a random angle with the given spread (standard deviation).
+
+Every arriving atom draws its own direction. A vacancy is picked as before (according to its coordination number) but only filled when
+a straight ray from it towards this direction leaves the crystal without passing through any occupied position (or the substrate).
+For short vacancy lists the visible vacancies are determined before picking one, since otherwise a single shadowed vacancy with
+a high probability would block the whole growth. The visibility of a vacancy is kept for a while for every bin of directions
+(FLUX_BIN, the rays are traced along the center of the bin), since a single new atom only shadows a few other positions.
+
+Large tilt angles result in glancing-angle deposition, where the seeds shadow each other and grow towards the source.
+*/
+
+use rand::Rng;
+use rand::seq::IteratorRandom;
+use std::collections::HashMap;
+use std::hash::BuildHasherDefault;
+
+use crate::helpers::*;
+use crate::parameters::*;
+use crate::storage::*;
+use crate::crystal::*;
+
+#[cfg(target_arch = "wasm32")]
+use crate::println;
+
+
+/// Direction (in degrees) and angular spread of the deposition flux.
+#[derive(Copy,Clone,Debug)]
+pub struct Flux {
+    pub tilt: f32,
+    pub azimuth: f32,
+    pub spread: f32,
+}
+
+impl Default for Flux {
+    fn default() -> Self {
+        Flux{ tilt: FLUX_TILT, azimuth: FLUX_AZIMUTH, spread: FLUX_SPREAD }
+    }
+}
+
+impl Flux {
+    /// A random unit vector pointing towards the source.
+    pub fn random_direction(&self) -> XYZ {
+        let mut rng = rand::thread_rng();
+        let (tilt, azimuth, spread) = (self.tilt.to_radians(), self.azimuth.to_radians(), self.spread.to_radians());
+
+        // gaussian deviation via Box-Muller
+        let mut gauss = || (-2.0*(1.0 - rng.gen::<f32>()).ln()).sqrt()*(2.0*std::f32::consts::PI*rng.gen::<f32>()).cos();
+        let x = tilt.sin()*azimuth.cos() + spread*gauss();
+        let y = tilt.sin()*azimuth.sin() + spread*gauss();
+        let z = tilt.cos() + spread*gauss();
+        let norm = (x*x + y*y + z*z).sqrt();
+        XYZ{x: x/norm, y: y/norm, z: z/norm}
+    }
+}
+
+/// The bin of a direction (FLUX_BIN wide in every component).
+fn direction_bin(direction: XYZ) -> [i16;3] {
+    let width = FLUX_BIN.to_radians();
+    [(direction.x/width).round() as i16, (direction.y/width).round() as i16, (direction.z/width).round() as i16]
+}
+
+/// The direction in the center of a bin.
+fn bin_direction(bin: [i16;3]) -> XYZ {
+    let [x, y, z] = [bin[0] as f32, bin[1] as f32, bin[2] as f32];
+    let norm = (x*x + y*y + z*z).sqrt();
+    XYZ{x: x/norm, y: y/norm, z: z/norm}
+}
+
+/// The visibility of the vacancies for the bins of directions they were checked for.
+type VisibilityCache = HashMap<IJK, Vec<([i16;3], bool)>, BuildHasherDefault<PositionHasher>>;
+
+
+impl Crystal {
+    /// Switch the directional flux on or off.
+    pub fn switch_flux(&mut self) {
+        self.flux = match self.flux {
+            Some(_) => None,
+            None => Some(Flux::default()),
+        };
+    }
+
+    /// Add atoms from a directional flux -- used by random_add when a flux is present.
+    pub fn flux_add(&mut self, number_of_atoms: usize) {
+        let flux = match self.flux {
+            Some(flux) => flux,
+            None => return,
+        };
+
+        let mut rng = rand::thread_rng();
+        let mut cache = VisibilityCache::default();
+        let mut added = 0;
+        let mut missed = 0;
+        while added < number_of_atoms {
+            if self.vacancies.list.iter().all(|list| list.is_empty()) { break }
+
+            // the direction of the arriving atom
+            let bin = direction_bin(flux.random_direction());
+
+            // the visible vacancies of the short lists (typically those with high probabilities) are determined directly,
+            // the long ones are handled via rejection further down
+            let visible: Vec<Option<Vec<IJK>>> = self.vacancies.list.iter().map(|list| {
+                if list.len() > FLUX_DIRECT {
+                    None
+                }
+                else {
+                    Some(list.iter().filter(|&&ijk| self.cached_visible(&mut cache, ijk, bin)).cloned().collect())
+                }
+            }).collect();
+            let counts: Vec<u64> = visible.iter().zip(self.vacancies.list.iter()).map(|(visible, list)| {
+                visible.as_ref().map_or(list.len(), |visible| visible.len()) as u64
+            }).collect();
+            let mut weights: Vec<u64> = counts.iter().zip(self.prob_list.iter()).map(|(count, prob)| count*prob).collect();
+
+            // lists without probability are only used when nothing else is left -- just like in random_vacancy
+            if weights.iter().sum::<u64>() == 0 { weights = counts }
+            let total: u64 = weights.iter().sum();
+            if total == 0 { break }
+
+            // chose a list and a vacancy in it -- until a visible one is found (or the atom misses the crystal)
+            let mut chosen = None;
+            for _attempt in 0..FLUX_ATTEMPTS {
+                let mut random_number = rng.gen_range(0, total);
+                let chosen_list = weights.iter().position(|&weight| {
+                    if random_number < weight { true } else { random_number -= weight; false }
+                }).unwrap_or(0);
+                match &visible[chosen_list] {
+                    Some(visible) => chosen = Some(visible[rng.gen_range(0, visible.len())]),
+                    None => {
+                        let ijk = *self.vacancies.list[chosen_list].iter().choose(&mut rng).expect("non-empty list");
+                        if self.cached_visible(&mut cache, ijk, bin) { chosen = Some(ijk) }
+                    }
+                }
+                if chosen.is_some() { break }
+            }
+
+            // the long lists are (almost) completely shadowed for this direction -- any visible vacancy of a list with a weight is taken then
+            // (traced only once for the bin), without any the atom misses the crystal
+            if chosen.is_none() {
+                let candidates: Vec<IJK> = self.vacancies.list.iter().zip(weights.iter())
+                    .filter(|(_, &weight)| weight > 0)
+                    .flat_map(|(list, _)| list.iter().cloned())
+                    .collect();
+                chosen = candidates.into_iter().filter(|&ijk| self.cached_visible(&mut cache, ijk, bin)).choose(&mut rng);
+            }
+            let ijk = match chosen {
+                Some(ijk) => {
+                    missed = 0;
+                    ijk
+                },
+                None => {
+                    missed += 1;
+                    if missed == FLUX_MISSES {
+                        println!("No visible vacancy for {} arriving atoms in a row -- stopped after {} atoms", FLUX_MISSES, added);
+                        break
+                    }
+                    continue
+                }
+            };
+
+            if self.add_atom(ijk) {
+                added += 1;
+                if self.advance(added) { break }
+
+                // the new atom only changes the surrounding vacancies at once, all others are checked again from time to time
+                if added % FLUX_REFRESH == 0 {
+                    cache.clear();
+                }
+                else {
+                    cache.remove(&ijk);
+                    for l in 0..12 {
+                        cache.remove(&self.neighbor(ijk, l));
+                    }
+                }
+            }
+        }
+    }
+
+    /// The visibility of a vacancy for a bin of directions -- traced only once until it is removed from the cache.
+    fn cached_visible(&self, cache: &mut VisibilityCache, ijk: IJK, bin: [i16;3]) -> bool {
+        let bins = cache.entry(ijk).or_default();
+        match bins.iter().find(|(cached, _)| *cached == bin) {
+            Some(&(_, visible)) => visible,
+            None => {
+                let visible = self.visible(ijk, bin_direction(bin));
+                bins.push((bin, visible));
+                visible
+            }
+        }
+    }
+
+    /// Check if a ray from a position in the given direction leaves the crystal without hitting anything.
+    pub fn visible(&self, ijk: IJK, direction: XYZ) -> bool {
+        let e = &self.extrema;
+        let start = self.lattice_at(ijk).get_xyz(ijk);
+        let step = DIAMETER/2.0;
+        let mut distance = step;
+        loop {
+            let xyz = XYZ{x: start.x + distance*direction.x, y: start.y + distance*direction.y, z: start.z + distance*direction.z};
+
+            // outside of the crystal nothing can be hit anymore
+            if xyz.x < e.x_min - DIAMETER || xyz.x > e.x_max + DIAMETER
+                || xyz.y < e.y_min - DIAMETER || xyz.y > e.y_max + DIAMETER
+                || xyz.z < e.z_min - DIAMETER || xyz.z > e.z_max + DIAMETER {
+                    return true
+            }
+
+            let ray_ijk = self.lattice.get_ijk(xyz);
+            if ray_ijk != ijk && (ray_ijk.k <= self.substrate_pos || !self.bulk.get(ray_ijk, Atom::Empty)) {
+                return false
+            }
+            distance += step;
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lattice::*;
+
+    #[test]
+    fn direction_bins_contain_their_directions() {
+        let flux = Flux{tilt: 60.0, azimuth: 30.0, spread: 5.0};
+        for _ in 0..100 {
+            let direction = flux.random_direction();
+            let center = bin_direction(direction_bin(direction));
+            let cos = direction.x*center.x + direction.y*center.y + direction.z*center.z;
+            assert!(cos.min(1.0).acos().to_degrees() < FLUX_BIN);
+        }
+    }
+
+    #[test]
+    fn flux_growth_stops_without_visible_vacancies() {
+        let mut flake = Crystal::new(Lattice::new(STACKING_FAULTS.to_vec(), DIAMETER));
+        flake.add_atom(CENTER);
+        flake.random_add(3000);
+
+        // an oblique flux fills visible vacancies only
+        flake.flux = Some(Flux{tilt: 60.0, azimuth: 0.0, spread: 0.0});
+        let atoms = flake.bulk.number_of_atoms;
+        flake.random_add(500);
+        assert_eq!(flake.bulk.number_of_atoms, atoms + 500);
+
+        // a flux from below the substrate can not reach any vacancy
+        flake.flux = Some(Flux{tilt: 180.0, azimuth: 0.0, spread: 0.0});
+        flake.substrate_pos = flake.bulk.k_min - 1;
+        flake.update_vacancies();
+        flake.random_add(10);
+        assert_eq!(flake.bulk.number_of_atoms, atoms + 500);
+    }
+}
@@ -0,0 +1,235 @@
+/*!
+Headless mode for running the growth without any window
+
+Usage: `flake_growth --headless [scene.toml] [number of atoms] [--seeds] [--supply] [--walkers] [--flux] [--alloy] [--impurities] [--monolayer] [--screw] [--until width=500] [--protocol protocol.toml] [--branch a.toml --branch b.toml] [--threads 8 [--compare]] [--film 200] [--bulk-file flake.bulk] [--open flake.bulk] [--anneal] [--ripen] [--save]`
+or `flake_growth --headless --wulff` for writing the Wulff shapes for all STOP_MARKS in the format of the statistics module
+and `flake_growth --headless --sos [number of atoms]` for the growth in the coarse layer model (see the height_map module) -- without a number its statistics are written.
+
+Without a scene file the growth starts from a single atom in the center -- or from SEEDS random seeds with `--seeds`.
+With `--supply` the atoms are taken from a finite precursor supply (see the diffusion module), with `--walkers` they are added via random walkers
+with `--flux` they arrive from the direction given in the parameters (glancing-angle deposition)
+with `--alloy` a second metal is mixed in according to the feed (the composition profiles are saved at the end)
+and with `--impurities` dirt atoms attach (and desorb) during the growth. `--screw` inserts a screw dislocation at the center.
+With `--monolayer` the growth is confined to a single layer on a substrate (see the monolayer module) -- together with `--film` the island grows from a seed in the periodic cell.
+With `--until` the growth continues after the given number of atoms until a stop condition (see the stop_conditions module) is reached.
+With `--protocol` the stages of the given protocol file are executed after the growth and logged to a file.
+With `--branch` the grown flake is forked and every given protocol is executed on its own branch, the branches are logged side by side (see the branches module).
+With `--threads` large numbers of atoms are grown in parallel (0 uses all cores, see the parallel module) and with `--compare` the same number of atoms
+is grown serially and in parallel from the same state instead, printing the time, the aspect ratio and the length ratio of both as benchmark.
+With `--film` a periodic thin film with the given period is grown instead of a flake and its morphology is printed (see the film module).
+With `--bulk-file` the bulk is kept in the given file instead of the memory (see BULK_FILE) and with `--open` such a file is reopened (with the lattice and substrate stored in it) instead of starting a new flake.
+*/
+
+use instant::Instant;
+use separator::Separatable;
+
+use crate::helpers::*;
+use crate::parameters::*;
+use crate::lattice::*;
+use crate::storage::*;
+use crate::crystal::*;
+use crate::scene_file::*;
+use crate::wulff::*;
+use crate::particles::*;
+use crate::walkers::*;
+use crate::protocol::*;
+use crate::stop_conditions::*;
+use crate::parallel::*;
+use crate::height_map::*;
+
+
+pub fn run(args: &[String]) {
+    let lattice = Lattice::new(STACKING_FAULTS.to_vec(), DIAMETER);
+
+    // the bulk either in the memory (or BULK_FILE), in a new file or in a reopened one
+    let open_path = args.iter().position(|arg| arg == "--open").and_then(|index| args.get(index + 1));
+    let bulk = match (open_path, args.iter().position(|arg| arg == "--bulk-file").and_then(|index| args.get(index + 1))) {
+        (Some(path), _) => Bulk::open(path),
+        (None, Some(path)) => Bulk::create(path),
+        (None, None) => Ok(Bulk::new()),
+    };
+    let mut flake = match bulk {
+        Ok(bulk) if open_path.is_some() => Crystal::reopen(bulk),
+        Ok(bulk) => Crystal::with_bulk(lattice, bulk),
+        Err(error) => {
+            println!("{}", error);
+            return
+        }
+    };
+
+    // thermodynamic reference instead of growth
+    if args.iter().any(|arg| arg == "--wulff") {
+        flake.wulff_statistics(&Wulff::default());
+        return
+    }
+
+    // coarse solid-on-solid model instead of the atomistic crystal
+    if args.iter().any(|arg| arg == "--sos") {
+        let mut height_map = HeightMap::new(flake.lattice.clone(), flake.prob_list);
+        match args.iter().filter_map(|arg| arg.replace('_', "").parse::<usize>().ok()).next() {
+            Some(number) => {
+                let start = Instant::now();
+                println!("Calculation {} atoms in the layer model... ", number.separated_string());
+                height_map.random_add(number);
+                println!(" ...finished in {:#?}", start.elapsed());
+                let [h,w,d,r] = height_map.get_size();
+                println!("{} atoms -- height: {:.2}, width: {:.2}, depth: {:.2}, aspect ratio: {:.2}, length ratio: {:.3}", height_map.number_of_atoms.separated_string(), h, w, d, r, height_map.length_ratio());
+            },
+            None => height_map.statistics(),
+        }
+        return
+    }
+
+    // the way atoms are added
+    if args.iter().any(|arg| arg == "--supply") && flake.supply.is_none() {
+        flake.switch_supply();
+    }
+    if args.iter().any(|arg| arg == "--walkers") {
+        flake.growth_mode = GrowthMode::Walkers;
+    }
+    if args.iter().any(|arg| arg == "--flux") && flake.flux.is_none() {
+        flake.switch_flux();
+    }
+    if args.iter().any(|arg| arg == "--alloy") && flake.alloy.is_none() {
+        flake.switch_alloy();
+    }
+    if args.iter().any(|arg| arg == "--impurities") && flake.impurities.is_none() {
+        flake.switch_impurities();
+    }
+    if args.iter().any(|arg| arg == "--monolayer") && flake.monolayer.is_none() {
+        flake.switch_monolayer();
+    }
+
+    // start from a reopened bulk file, a scene file, a periodic film or a single atom
+    let film_index = args.iter().position(|arg| arg == "--film").map(|index| index + 1);
+    let film_period = film_index.and_then(|index| args.get(index)).and_then(|arg| arg.parse::<u16>().ok());
+    let protocol_path = args.iter().position(|arg| arg == "--protocol").and_then(|index| args.get(index + 1));
+    let branch_paths: Vec<&String> = args.windows(2).filter(|pair| pair[0] == "--branch").map(|pair| &pair[1]).collect();
+    match args.iter().find(|&arg| arg.ends_with(".toml") && Some(arg) != protocol_path && !branch_paths.contains(&arg)) {
+        _ if open_path.is_some() => {
+            println!("Flake reopened with {} atoms", flake.bulk.number_of_atoms.separated_string());
+        },
+        Some(path) => match SceneFile::load(path) {
+            Ok(scene_file) => {
+                flake.load_scene(&scene_file);
+                println!("Scene loaded: {}", path);
+            },
+            Err(error) => {
+                println!("{}", error);
+                return
+            }
+        },
+        None if film_index.is_some() => {
+            let period = film_period.unwrap_or(FILM_PERIOD);
+            flake.set_periodic(Some(period));
+            println!("Periodic film with a period of {} atoms", period);
+        },
+        None if args.iter().any(|arg| arg == "--seeds") => {
+            let seeds = random_seeds(SEEDS, SEED_RADIUS, INDEPENDENT_STACKINGS, &flake.lattice);
+            flake.add_seeds(&seeds);
+        },
+        None => { flake.add_atom(CENTER); }
+    }
+
+    if args.iter().any(|arg| arg == "--screw") {
+        flake.set_screw(Some(Screw{i: CENTER.i, j: CENTER.j, burgers: SCREW_BURGERS}));
+    }
+
+    // grow the given number of atoms -- the number of threads and the period of the film are not meant
+    let threads_index = args.iter().position(|arg| arg == "--threads").map(|index| index + 1);
+    if let Some(threads) = threads_index.and_then(|index| args.get(index)).and_then(|arg| arg.parse::<usize>().ok()) {
+        flake.set_threads(number_of_threads(threads));
+        println!("Growth with {} threads", flake.threads);
+    }
+    let number = args.iter().enumerate()
+        .filter(|&(index, _)| Some(index) != threads_index && (Some(index) != film_index || film_period.is_none()))
+        .filter_map(|(_, arg)| arg.replace('_', "").parse::<usize>().ok()).next().unwrap_or(0);
+
+    // benchmark of the parallel growth -- both branches start from the same state
+    if args.iter().any(|arg| arg == "--compare") {
+        for &threads in [1, flake.threads].iter() {
+            let mut branch = flake.fork();
+            branch.set_threads(threads);
+            let start = Instant::now();
+            branch.random_add(number);
+            let [.., r] = branch.get_size();
+            println!("{:>3} threads: {} atoms in {:#?} -- aspect ratio: {:.2}, length ratio: {:.3}", threads, branch.bulk.number_of_atoms.separated_string(), start.elapsed(), r, branch.length_ratio());
+        }
+        return
+    }
+
+    let start = Instant::now();
+    println!("Calculation {} atoms... ", number.separated_string());
+    flake.random_add(number);
+    if let Some(text) = args.iter().position(|arg| arg == "--until").and_then(|index| args.get(index + 1)) {
+        match StopCondition::parse(text) {
+            Ok(condition) => { flake.grow_until(&condition); },
+            Err(error) => println!("{}", error),
+        }
+    }
+    println!(" ...finished in {:#?}", start.elapsed());
+
+    let [h,w,d,r] = flake.get_size();
+    println!("{} atoms -- height: {:.2}, width: {:.2}, depth: {:.2}, aspect ratio: {:.2}", flake.bulk.number_of_atoms.separated_string(), h, w, d, r);
+    if flake.monolayer.is_some() { println!("Monolayer island -- length ratio: {:.3}", flake.length_ratio()) }
+    if flake.impurities.is_some() { println!("{} dirt atoms incorporated", flake.bulk.number_of_dirt) }
+    if flake.particles.active() { flake.particles.report() }
+    if let Some(film) = flake.film_morphology() {
+        println!("Film -- thickness: {:.2}, roughness: {:.3}, open layers: {}, islands: {} ({:.1}/um^2)", film.thickness, film.roughness, film.open_layers, film.islands, film.island_density);
+    }
+
+    // execute the stages of a protocol
+    if let Some(path) = protocol_path {
+        match Protocol::load(path) {
+            Ok(protocol) => {
+                println!("Protocol loaded: {}", path);
+                let logs = flake.run_protocol(&protocol);
+                flake.save_protocol(&logs);
+            },
+            Err(error) => println!("{}", error),
+        }
+    }
+
+    // continue from the same state under different conditions
+    if !branch_paths.is_empty() {
+        let mut protocols = Vec::new();
+        for path in branch_paths.iter() {
+            match Protocol::load(path) {
+                Ok(protocol) => protocols.push(protocol),
+                Err(error) => {
+                    println!("{}", error);
+                    return
+                }
+            }
+        }
+        let logs = flake.run_branches(&protocols);
+        let names: Vec<String> = branch_paths.iter().map(|path| path.to_string()).collect();
+        flake.save_branches(&names, &logs);
+    }
+
+    // anneal the grown crystal and print the surface energy after each sweep
+    if args.iter().any(|arg| arg == "--anneal") {
+        println!("Annealing at {}K for {} sweeps...", ANNEAL_TEMPERATURE, ANNEAL_SWEEPS);
+        for (sweep, energy) in flake.anneal(ANNEAL_TEMPERATURE, ANNEAL_SWEEPS, BOND_ENERGY).iter().enumerate() {
+            println!("{:>5} sweeps -- surface energy: {:.1}eV", sweep, energy);
+        }
+    }
+
+    // let the particles ripen and write the time series of their sizes
+    if args.iter().any(|arg| arg == "--ripen") {
+        println!("Ripening at {}K for {} sweeps...", RIPENING_TEMPERATURE, RIPENING_SWEEPS);
+        let steps = flake.ripen(RIPENING_TEMPERATURE, RIPENING_SWEEPS, BOND_ENERGY);
+        for step in steps.iter() {
+            println!("{:>5} sweeps -- particles (atoms): {:?}, monomers: {}", step.sweep, step.atoms, step.monomers);
+        }
+        flake.save_ripening(&steps);
+    }
+
+    if flake.alloy.is_some() {
+        flake.save_composition();
+    }
+
+    if args.iter().any(|arg| arg == "--save") {
+        flake.save();
+    }
+}
@@ -0,0 +1,497 @@
+/*!
+Coarse layer model for micrometre-scale flakes
+
+The bulk needs 2 bits for every position of the FLAKE_MAX box, so flakes of several µm are out of reach. Most of the shape information is
+in the outlines of the layers, though: here every layer is a convex hexagon with close-packed edges (a triangle or a single atom in the limit),
+stored as the lowest and highest values of i, j and i+j of its positions -- 24 bytes per layer independent of its size.
+
+The growth follows the rules of the atomistic crystal, but in whole rows: a new row along an edge starts at any of its sites with the weight
+of the prob_list for their coordination number, which is counted in the fcc lattice (including the stacking faults) with the layers above
+and below, so facets and re-entrant grooves differ just as for the atoms. A started row is completed at once, as its kinks have more neighbors
+and are preferred by orders of magnitude for the usual prob_lists. Besides the rows an atom can attach beyond a corner (which starts the
+rows of both edges) and a new layer can nucleate on top of the highest or below the lowest layer.
+Islands on lower terraces, holes and the dynamics of the kinks are neglected, so the model is meant for flakes, not for rough growth.
+
+The costs of a step scale with the number of layers instead of the atoms, so the larger the flake the larger the gain -- the statistics are
+written in the format of the statistics module, so both models can be compared on overlapping sizes.
+*/
+
+use rand::Rng;
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::{io::Write, fs::File};
+
+use crate::helpers::*;
+use crate::parameters::*;
+use crate::lattice::*;
+use crate::crystal::*;
+
+#[cfg(target_arch = "wasm32")]
+use crate::println;
+
+
+// the edges in the order around the hexagon as (axis, upper bound) -- axis 0 is i, 1 is j and 2 is i+j
+// corner c lies between the edges c and c+1
+const EDGES: [(usize, bool); 6] = [(0, true), (2, true), (1, true), (0, false), (2, false), (1, false)];
+
+/// The outline of a layer: the lowest and highest i, j and i+j of its positions.
+#[derive(Copy,Clone,Debug,PartialEq)]
+struct Layer {min: [i32;3], max: [i32;3]}
+
+impl Layer {
+    fn single(i: i32, j: i32) -> Self {
+        Layer{ min: [i, j, i + j], max: [i, j, i + j] }
+    }
+
+    fn contains(&self, i: i32, j: i32) -> bool {
+        let c = [i, j, i + j];
+        (0..3).all(|axis| c[axis] >= self.min[axis] && c[axis] <= self.max[axis])
+    }
+
+    fn is_empty(&self) -> bool {
+        (0..3).any(|axis| self.min[axis] > self.max[axis])
+    }
+
+    /// The bound of an edge moved outwards by the given number of rows.
+    fn bound(&self, edge: usize, rows: i32) -> i32 {
+        match EDGES[edge] {
+            (axis, true) => self.max[axis] + rows,
+            (axis, false) => self.min[axis] - rows,
+        }
+    }
+
+    /// The positions on the line where the coordinate of the axis has the given value as range of the free coordinate (see point).
+    fn line(&self, axis: usize, value: i32) -> (i32, i32) {
+        match axis {
+            0 => (self.min[1].max(self.min[2] - value), self.max[1].min(self.max[2] - value)),
+            1 => (self.min[0].max(self.min[2] - value), self.max[0].min(self.max[2] - value)),
+            _ => (self.min[0].max(value - self.max[1]), self.max[0].min(value - self.min[1])),
+        }
+    }
+
+    /// A position on a line -- the free coordinate is j for the lines of constant i and i otherwise.
+    fn point(axis: usize, value: i32, free: i32) -> (i32, i32) {
+        match axis {
+            0 => (value, free),
+            1 => (free, value),
+            _ => (free, value - free),
+        }
+    }
+
+    /// The layer with the bound of an edge moved outwards by one row.
+    fn grown(&self, edge: usize) -> Layer {
+        let mut layer = *self;
+        match EDGES[edge] {
+            (axis, true) => layer.max[axis] += 1,
+            (axis, false) => layer.min[axis] -= 1,
+        }
+        layer
+    }
+
+    /// The layer shifted by (di, dj).
+    fn moved(&self, di: i32, dj: i32) -> Layer {
+        let (min, max) = (self.min, self.max);
+        Layer{ min: [min[0] + di, min[1] + dj, min[2] + di + dj], max: [max[0] + di, max[1] + dj, max[2] + di + dj] }
+    }
+
+    fn intersection(&self, other: &Layer) -> Layer {
+        let mut layer = *self;
+        for axis in 0..3 {
+            layer.min[axis] = layer.min[axis].max(other.min[axis]);
+            layer.max[axis] = layer.max[axis].min(other.max[axis]);
+        }
+        layer
+    }
+
+    /// Move every bound onto the outline, i.e. to the projection of the hexagon onto its axis.
+    fn tightened(&self) -> Layer {
+        let mut layer = *self;
+        while !layer.is_empty() {
+            let (min, max) = (layer.min, layer.max);
+            layer.min = [min[0].max(min[2] - max[1]), min[1].max(min[2] - max[0]), min[2].max(min[0] + min[1])];
+            layer.max = [max[0].min(max[2] - min[1]), max[1].min(max[2] - min[0]), max[2].min(max[0] + max[1])];
+            if layer.min == min && layer.max == max { break }
+        }
+        layer
+    }
+
+    /// Number of positions -- the lines of constant i summed up piecewise.
+    fn area(&self) -> u64 {
+        let layer = self.tightened();
+        if layer.is_empty() { return 0 }
+        let [a, b] = [layer.min[0], layer.max[0]];
+        let upper = sum_of_min(a, b, layer.max[1], layer.max[2]);
+        let lower = sum_of_max(a, b, layer.min[1], layer.min[2]);
+        (upper - lower + (b - a + 1) as i64) as u64
+    }
+}
+
+/// Sum of d - i for i from a to b (zero for b < a).
+fn series(a: i32, b: i32, d: i32) -> i64 {
+    if b < a { return 0 }
+    let n = (b - a + 1) as i64;
+    n*d as i64 - (a as i64 + b as i64)*n/2
+}
+
+/// Sum of min(c, d - i) for i from a to b.
+fn sum_of_min(a: i32, b: i32, c: i32, d: i32) -> i64 {
+    let t = (d - c).min(b).max(a - 1);                      // the minimum is c up to t
+    c as i64*(t - a + 1) as i64 + series(t + 1, b, d)
+}
+
+/// Sum of max(c, d - i) for i from a to b.
+fn sum_of_max(a: i32, b: i32, c: i32, d: i32) -> i64 {
+    let t = (d - c).min(b).max(a - 1);                      // the maximum is d - i up to t
+    series(a, t, d) + c as i64*(b - t) as i64
+}
+
+/// A possible growth step: the number of its sites and their coordination number.
+#[derive(Copy,Clone,Debug,Default)]
+struct Event {sites: u64, neighbors: usize}
+
+/// What a growth step does.
+#[derive(Copy,Clone,Debug)]
+enum Step {Row(usize, usize), Corner(usize, usize), Top, Bottom}
+
+pub struct HeightMap {
+    pub lattice: Lattice,
+    pub prob_list: [u64; VAC_LISTS],
+    pub number_of_atoms: usize,
+    pub extrema: Extrema,
+    pub extrema_ijk: ExtremaCoordinates,
+    pub boundary: Option<usize>,
+    k_min: u16,                                     // the lowest layer
+    layers: Vec<Layer>,                             // the outlines from k_min upwards
+    events: Vec<[Event; 12]>,                       // new rows at the 6 edges and atoms beyond the 6 corners of every layer
+    nucleation: [Event; 2],                         // a new layer on top and at the bottom
+}
+
+impl HeightMap {
+    /// A layer model with a single atom in the center.
+    pub fn new(lattice: Lattice, prob_list: [u64; VAC_LISTS]) -> Self {
+        println!("Layer model with {}x{} positions per layer", SOS_MAX, SOS_MAX);
+        let center = HeightMap::center();
+        let mut height_map = HeightMap{
+            lattice,
+            prob_list,
+            number_of_atoms: 0,
+            extrema: Extrema{x_min: 0.0, x_max: 0.0, y_min: 0.0, y_max: 0.0, z_min: 0.0, z_max: 0.0 },
+            extrema_ijk: ExtremaCoordinates{x_min: center, x_max: center, y_min: center, y_max: center, z_min: center, z_max: center },
+            boundary: None,
+            k_min: center.k,
+            layers: Vec::new(),
+            events: Vec::new(),
+            nucleation: [Event::default(); 2],
+        };
+        height_map.clear();
+        height_map
+    }
+
+    /// The center of the layers -- in k the same as for the bulk, so the stacking faults of the parameters fit.
+    pub fn center() -> IJK {
+        IJK{i: SOS_MAX/2, j: SOS_MAX/2, k: CENTER.k}
+    }
+
+    /// Restart from a single atom in the center.
+    pub fn clear(&mut self) {
+        let center = HeightMap::center();
+        let xyz = self.lattice.get_xyz(center);
+        self.k_min = center.k;
+        self.layers = vec![Layer::single(center.i as i32, center.j as i32)];
+        self.events = vec![[Event::default(); 12]];
+        self.number_of_atoms = 1;
+        self.extrema = Extrema{x_min: xyz.x, x_max: xyz.x, y_min: xyz.y, y_max: xyz.y, z_min: xyz.z, z_max: xyz.z };
+        self.extrema_ijk = ExtremaCoordinates{x_min: center, x_max: center, y_min: center, y_max: center, z_min: center, z_max: center };
+        self.boundary = None;
+        self.update_events(0);
+        self.update_nucleation();
+    }
+
+    fn layer(&self, k: u16) -> Option<&Layer> {
+        self.layers.get(k.checked_sub(self.k_min)? as usize)
+    }
+
+    /// Check if a position is occupied.
+    pub fn occupied(&self, ijk: IJK) -> bool {
+        match self.layer(ijk.k) {
+            Some(layer) => layer.contains(ijk.i as i32, ijk.j as i32),
+            None => false,
+        }
+    }
+
+    fn number_of_neighbors(&self, ijk: IJK) -> usize {
+        (0..12).filter(|&l| self.occupied(self.lattice.next_neighbor(ijk, l))).count()
+    }
+
+    /// Check if a layer lies within the walls (as for the bulk, see the boundary module) -- the first contact is reported.
+    fn inside(&mut self, layer: &Layer, k: u16) -> bool {
+        let layer = layer.tightened();
+        let inside = layer.min[0] > 1 && layer.max[0] < SOS_MAX as i32 - 2
+            && layer.min[1] > 1 && layer.max[1] < SOS_MAX as i32 - 2
+            && k > 1 && k < FLAKE_MAX.k - 2;
+        if !inside { self.reached_boundary() }
+        inside
+    }
+
+    /// The events of a layer -- the coordination of a row is taken in its middle.
+    fn update_events(&mut self, index: usize) {
+        let layer = self.layers[index];
+        let k = self.k_min + index as u16;
+        let mut events = [Event::default(); 12];
+        for (edge, &(axis, _)) in EDGES.iter().enumerate() {
+            let value = layer.bound(edge, 1);
+            let (start, end) = layer.line(axis, value);
+            if end >= start && self.inside(&layer.grown(edge), k) {
+                let (i, j) = Layer::point(axis, value, (start + end)/2);
+                events[edge] = Event{sites: (end - start + 1) as u64, neighbors: self.number_of_neighbors(IJK{i: i as u16, j: j as u16, k})};
+            }
+
+            // the corner to the next edge
+            let next = (edge + 1)%6;
+            let (i, j) = corner((axis, value), (EDGES[next].0, layer.bound(next, 1)));
+            let grown = layer.grown(edge).grown(next);
+            if grown.contains(i, j) && self.inside(&grown, k) {
+                events[6 + edge] = Event{sites: 1, neighbors: self.number_of_neighbors(IJK{i: i as u16, j: j as u16, k})};
+            }
+        }
+        self.events[index] = events;
+    }
+
+    /// The positions in layer k which lie on three atoms of the given layer (neighbors 9-11 below or 6-8 above).
+    fn hollow_sites(&self, layer: &Layer, k: u16, neighbors: std::ops::Range<usize>) -> Layer {
+        let center = IJK{k, ..HeightMap::center()};
+        neighbors.fold(*layer, |sites, l| {
+            let nn_ijk = self.lattice.next_neighbor(center, l);
+            sites.intersection(&layer.moved(center.i as i32 - nn_ijk.i as i32, center.j as i32 - nn_ijk.j as i32))
+        }).tightened()
+    }
+
+    /// The nucleation of a new layer on the hollow sites of the highest and below the lowest layer.
+    fn update_nucleation(&mut self) {
+        let k_max = self.k_min + self.layers.len() as u16 - 1;
+        let top = self.hollow_sites(&self.layers[self.layers.len() - 1], k_max + 1, 9..12);
+        let bottom = self.hollow_sites(&self.layers[0], self.k_min - 1, 6..9);
+        self.nucleation = [Event::default(); 2];
+        if !top.is_empty() && self.inside(&top, k_max + 1) {
+            self.nucleation[0] = Event{sites: top.area(), neighbors: 3};
+        }
+        if !bottom.is_empty() && self.inside(&bottom, self.k_min - 1) {
+            self.nucleation[1] = Event{sites: bottom.area(), neighbors: 3};
+        }
+    }
+
+    /// A random position of a layer.
+    fn random_site<R: Rng>(layer: &Layer, rng: &mut R) -> (i32, i32) {
+        let mut random_number = rng.gen_range(0, layer.area()) as i32;
+        for i in layer.min[0]..=layer.max[0] {
+            let (start, end) = layer.line(0, i);
+            if random_number <= end - start { return (i, start + random_number) }
+            random_number -= end - start + 1;
+        }
+        unreachable!()
+    }
+
+    /// Replace a layer by a larger one.
+    fn grow(&mut self, index: usize, layer: Layer) {
+        self.number_of_atoms += (layer.area() - self.layers[index].area()) as usize;
+        self.layers[index] = layer;
+        self.update_extrema(index);
+        for neighbor in index.saturating_sub(1)..(index + 2).min(self.layers.len()) {
+            self.update_events(neighbor);
+        }
+        self.update_nucleation();
+    }
+
+    /// Carry out one step chosen with the weights of the prob_list -- returns false if none is possible.
+    fn step<R: Rng>(&mut self, rng: &mut R) -> bool {
+
+        // only the number of sites counts as long as all weights are zero (as for the first atoms of the crystal)
+        let rate = |event: &Event| event.sites as f64*self.prob_list[event.neighbors.min(8).saturating_sub(1)] as f64;
+        let total: f64 = self.events.iter().flatten().chain(self.nucleation.iter()).map(rate).sum();
+        let weight = |event: &Event| if total > 0.0 { rate(event) } else { event.sites as f64 };
+        let total: f64 = self.events.iter().flatten().chain(self.nucleation.iter()).map(weight).sum();
+        if total == 0.0 { return false }
+
+        let mut random_number = rng.gen::<f64>()*total;
+        let mut chosen = None;
+        let steps = self.events.iter().enumerate()
+            .flat_map(|(index, events)| events.iter().enumerate().map(move |(n, event)|
+                (if n < 6 { Step::Row(index, n) } else { Step::Corner(index, n - 6) }, event)))
+            .chain(vec![(Step::Top, &self.nucleation[0]), (Step::Bottom, &self.nucleation[1])]);
+        for (step, event) in steps {
+            if weight(event) > 0.0 {
+                chosen = Some(step);
+                random_number -= weight(event);
+                if random_number < 0.0 { break }
+            }
+        }
+
+        match chosen {
+            Some(Step::Row(index, edge)) => self.grow(index, self.layers[index].grown(edge).tightened()),
+            Some(Step::Corner(index, corner)) => self.grow(index, self.layers[index].grown(corner).grown((corner + 1)%6).tightened()),
+            Some(Step::Top) => {
+                let k = self.k_min + self.layers.len() as u16;
+                let (i, j) = HeightMap::random_site(&self.hollow_sites(&self.layers[self.layers.len() - 1], k, 9..12), rng);
+                self.layers.push(Layer::single(i, j));
+                self.events.push([Event::default(); 12]);
+                self.number_of_atoms += 1;
+                self.grow(self.layers.len() - 1, Layer::single(i, j));
+            },
+            Some(Step::Bottom) => {
+                let (i, j) = HeightMap::random_site(&self.hollow_sites(&self.layers[0], self.k_min - 1, 6..9), rng);
+                self.k_min -= 1;
+                self.layers.insert(0, Layer::single(i, j));
+                self.events.insert(0, [Event::default(); 12]);
+                self.number_of_atoms += 1;
+                self.grow(0, Layer::single(i, j));
+            },
+            None => return false,
+        }
+        true
+    }
+
+    /// Add at least the given number of atoms (whole rows) with the weights of the prob_list.
+    pub fn random_add(&mut self, number_of_atoms: usize) {
+        let mut rng = rand::thread_rng();
+        let target = self.number_of_atoms + number_of_atoms;
+
+        // no step is possible once the flake fills the walls
+        while self.number_of_atoms < target && self.step(&mut rng) {}
+    }
+
+    /// Update the extrema with the corners of a layer.
+    fn update_extrema(&mut self, index: usize) {
+        let layer = self.layers[index];
+        let k = self.k_min + index as u16;
+        for (edge, &(axis, _)) in EDGES.iter().enumerate() {
+            let value = layer.bound(edge, 0);
+            let (start, end) = layer.line(axis, value);
+            for &free in [start, end].iter() {
+                let (i, j) = Layer::point(axis, value, free);
+                let ijk = IJK{i: i as u16, j: j as u16, k};
+                let xyz = self.lattice.get_xyz(ijk);
+                let (e, c) = (&mut self.extrema, &mut self.extrema_ijk);
+                if xyz.x < e.x_min { e.x_min = xyz.x; c.x_min = ijk }
+                if xyz.x > e.x_max { e.x_max = xyz.x; c.x_max = ijk }
+                if xyz.y < e.y_min { e.y_min = xyz.y; c.y_min = ijk }
+                if xyz.y > e.y_max { e.y_max = xyz.y; c.y_max = ijk }
+                if xyz.z < e.z_min { e.z_min = xyz.z; c.z_min = ijk }
+                if xyz.z > e.z_max { e.z_max = xyz.z; c.z_max = ijk }
+            }
+        }
+    }
+
+    /// Report the first contact with the walls.
+    fn reached_boundary(&mut self) {
+        if self.boundary.is_none() {
+            println!("Boundary of the layer model reached with {} atoms (see SOS_MAX)", self.number_of_atoms);
+            self.boundary = Some(self.number_of_atoms);
+        }
+    }
+
+    /// Height, width, depth and aspect ratio as for the crystal.
+    pub fn get_size(&self) -> [f32;4] {
+        self.extrema.size()
+    }
+
+    /// Length ratio of the hexagon spanned by the extrema as for the crystal.
+    pub fn length_ratio(&self) -> f32 {
+        let [ax,ay,bx,by,cx,cy,..] = self.extrema_ijk.hexagon(&self.lattice);
+        let len1 = ((ax-bx).powi(2) + (ay-by).powi(2)).sqrt();
+        let len2 = ((cx-bx).powi(2) + (cy-by).powi(2)).sqrt();
+        len1/(len1+len2)
+    }
+
+    /// The statistics of the layer model in the format of the statistics module: the growth is repeated NUMBER_OF_CYCLES times up to all STOP_MARKS.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn statistics(&mut self) {
+
+        // create file and write header
+        let filename = format!("{}_sos_stack{:?}_cycles{}_steps{}.csv", FILENAME, self.lattice.stacking_faults, NUMBER_OF_CYCLES, STOP_MARKS.len());
+        let mut f = File::create(filename).expect("Unable to create file");
+        writeln!(f, "layer model: {}x{} stacking: {:?} cycles: {} steps {} prob_list {:?}", SOS_MAX, SOS_MAX, self.lattice.stacking_faults, NUMBER_OF_CYCLES, STOP_MARKS.len(), self.prob_list).expect("Unable to write in file");
+        writeln!(f, "atoms, aspect ratio, k_min, k_max, len1, len2, len ratio, boundary").expect("Unable to write in file");
+
+        for cycle in 0..NUMBER_OF_CYCLES {
+            self.clear();
+            println!("{}/{}", cycle + 1, NUMBER_OF_CYCLES);
+            for &mark in STOP_MARKS.iter() {
+
+                // whole rows are added, so the marks are slightly exceeded
+                self.random_add(mark.saturating_sub(self.number_of_atoms));
+                let [..,r] = self.get_size();
+                let [ax,ay,bx,by,cx,cy,..] = self.extrema_ijk.hexagon(&self.lattice);
+                let len1 = ((ax-bx).powi(2) + (ay-by).powi(2)).sqrt();
+                let len2 = ((cx-bx).powi(2) + (cy-by).powi(2)).sqrt();
+                println!("{:>15} atoms (layer model) -- aspect ratio: {:>4.1}, length ratio: {:>5.3}", self.number_of_atoms, r, len1/(len1+len2));
+                writeln!(f, "{}, {}, {}, {}, {}, {}, {}, {}", self.number_of_atoms, r, self.extrema_ijk.z_min.k, self.extrema_ijk.z_max.k, len1, len2, len1/(len1+len2), self.boundary.is_some() as u8).unwrap();
+            }
+        }
+        println!(" ...finished");
+    }
+}
+
+/// The position where the lines of two different axes cross.
+fn corner((axis_a, value_a): (usize, i32), (axis_b, value_b): (usize, i32)) -> (i32, i32) {
+    let mut c = [0; 3];
+    c[axis_a] = value_a;
+    c[axis_b] = value_b;
+    match 3 - axis_a - axis_b {
+        0 => (c[2] - c[1], c[1]),
+        1 => (c[0], c[2] - c[0]),
+        _ => (c[0], c[1]),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn area_counts_the_positions() {
+        let layers = [
+            Layer{min: [0, 0, 0], max: [5, 5, 10]},
+            Layer{min: [0, 0, 3], max: [5, 5, 7]},
+            Layer{min: [-3, 2, 1], max: [4, 9, 8]},
+            Layer{min: [2, 2, 9], max: [8, 8, 9]},
+            Layer{min: [0, 0, 20], max: [5, 5, 30]},
+            Layer::single(7, -2),
+        ];
+        for layer in layers.iter() {
+            let count = (-10..20).flat_map(|i| (-10..20).map(move |j| (i, j))).filter(|&(i, j)| layer.contains(i, j)).count();
+            assert_eq!(layer.area(), count as u64, "{:?}", layer);
+            let tight = layer.tightened();
+            assert!(tight.is_empty() || (0..3).all(|axis| tight.line(axis, tight.min[axis]).0 <= tight.line(axis, tight.min[axis]).1), "{:?}", tight);
+        }
+    }
+
+    #[test]
+    fn nucleation_sites_lie_on_three_atoms() {
+        let mut height_map = HeightMap::new(Lattice::new(vec![CENTER.k + 1], DIAMETER), PROB_LIST[PROB_LIST_NUM]);
+        let c = SOS_MAX as i32/2;
+        height_map.layers[0] = Layer{min: [c - 10, c - 5, 2*c - 10], max: [c + 5, c + 10, 2*c + 8]}.tightened();
+        for &(k, first) in [(CENTER.k + 1, 9), (CENTER.k - 1, 6)].iter() {
+            let sites = height_map.hollow_sites(&height_map.layers[0], k, first..first + 3);
+            assert!(sites.area() > 0);
+            for i in c - 20..c + 20 {
+                for j in c - 20..c + 20 {
+                    let ijk = IJK{i: i as u16, j: j as u16, k};
+                    assert_eq!(sites.contains(i, j), height_map.number_of_neighbors(ijk) == 3, "{:?}", ijk);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn growth_adds_whole_layers() {
+        let mut height_map = HeightMap::new(Lattice::new(STACKING_FAULTS.to_vec(), DIAMETER), PROB_LIST[PROB_LIST_NUM]);
+        height_map.random_add(100_000);
+        assert!(height_map.number_of_atoms >= 100_000);
+        assert_eq!(height_map.layers.iter().map(|layer| layer.area()).sum::<u64>(), height_map.number_of_atoms as u64);
+        assert!(height_map.layers.iter().all(|layer| *layer == layer.tightened() && !layer.is_empty()));
+        assert!(height_map.occupied(HeightMap::center()));
+        let [h,w,..] = height_map.get_size();
+        assert!(w > h);
+    }
+}
@@ -9,7 +9,7 @@
  Small helper structs + a wasm macro needed several times
 */
 
-#[derive(Copy,Clone,Debug,PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Copy,Clone,Debug,PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct IJK {pub i: u16, pub j: u16, pub k: u16}
 // #[repr(C, align(8))]
 // pub struct IJK {pub k: u8, pub j: u16, pub i: u16}         //  doesn't make a speed difference
@@ -21,6 +21,23 @@ pub struct XYZ {pub x: f32, pub y: f32, pub z: f32}
 #[derive(Copy,Clone,Debug)]
 pub struct Color(pub f32, pub f32, pub f32);
 
+// Boltzmann constant in eV/K
+pub const K_B: f64 = 8.617_333e-5;
+
+/// A simple multiplicative hash for the positions -- much faster than the default one for the many lookups of a site index.
+#[derive(Default)]
+pub struct PositionHasher(u64);
+
+impl std::hash::Hasher for PositionHasher {
+    fn finish(&self) -> u64 { self.0 }
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes { self.write_u16(byte as u16) }
+    }
+    fn write_u16(&mut self, value: u16) {
+        self.0 = (self.0.rotate_left(5) ^ value as u64).wrapping_mul(0x51_7c_c1_b7_27_22_0a_95);
+    }
+}
+
 
 
 #[cfg(target_arch = "wasm32")]
@@ -0,0 +1,103 @@
+/*!
+Incorporation of impurities during the growth
+
+With a given probability per event and vacancy list (i.e. coordination number), a dirt atom (e.g. a surfactant or capping molecule)
+attaches instead of gold and blocks this position. Since the probabilities can be chosen per list, specific sites (and thereby facets)
+can be poisoned. Optionally, after every event a random dirt atom desorbs with a given probability and frees its position again.
+
+Both is done within random_add and via attach for single vacancies from random_vacancy.
+*/
+
+use rand::Rng;
+use rand::seq::IteratorRandom;
+
+use crate::helpers::*;
+use crate::parameters::*;
+use crate::crystal::*;
+
+
+/// Attachment probabilities of dirt for every vacancy list and desorption probability per event.
+#[derive(Copy,Clone,Debug)]
+pub struct Impurities {
+    pub probabilities: [f32; VAC_LISTS],
+    pub desorption: f32,
+}
+
+impl Default for Impurities {
+    fn default() -> Self {
+        Impurities{ probabilities: IMPURITY_PROBABILITIES, desorption: DESORPTION }
+    }
+}
+
+
+impl Crystal {
+    /// Switch the incorporation of impurities on or off.
+    pub fn switch_impurities(&mut self) {
+        self.impurities = match self.impurities {
+            Some(_) => None,
+            None => Some(Impurities::default()),
+        };
+    }
+
+    /// Desorption of a random dirt atom -- to be called once per growth event before picking the vacancy.
+    pub fn desorb(&mut self) {
+        let desorption = self.impurities.map_or(0.0, |impurities| impurities.desorption);
+        let mut rng = rand::thread_rng();
+        if desorption > 0.0 && rng.gen::<f32>() < desorption {
+            if let Some(&dirt_ijk) = self.dirt.list.iter().choose(&mut rng) {
+                self.clear_site(dirt_ijk);
+            }
+        }
+    }
+
+    /// Attach dirt at a vacancy of the given list with its probability -- returns true if dirt was attached instead of gold.
+    pub fn impurity_event(&mut self, ijk: IJK, list: usize) -> bool {
+        let probability = self.impurities.map_or(0.0, |impurities| impurities.probabilities[list]);
+        rand::thread_rng().gen::<f32>() < probability && self.add_dirt(ijk)
+    }
+
+    /// Add gold (or with impurities maybe dirt) at a vacancy from random_vacancy -- returns true if gold was added.
+    pub fn attach(&mut self, ijk: IJK) -> bool {
+        self.desorb();
+        let neighbors = self.number_of_neighbors(ijk);
+        if neighbors > 0 && self.impurity_event(ijk, (neighbors - 1).min(7)) {
+            return false
+        }
+        self.add_atom(ijk)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lattice::*;
+
+    #[test]
+    fn poisoned_lists_only_get_dirt() {
+        let mut flake = Crystal::new(Lattice::new(vec![], DIAMETER));
+        flake.add_atom(CENTER);
+        flake.random_add(1000);
+        flake.impurities = Some(Impurities{ probabilities: [1.0; VAC_LISTS], desorption: 0.0 });
+        flake.random_add(100);
+        assert_eq!(flake.bulk.number_of_atoms - flake.bulk.number_of_dirt, 1001);       // the dirt is counted as atom as well
+        assert_eq!(flake.bulk.number_of_dirt, flake.dirt.list.len());
+        assert!(!flake.dirt.list.is_empty());
+        assert!(!flake.attach(*flake.vacancies.list[0].iter().next().unwrap()));
+    }
+
+    #[test]
+    fn desorption_frees_the_dirt() {
+        let mut flake = Crystal::new(Lattice::new(vec![], DIAMETER));
+        flake.add_atom(CENTER);
+        flake.random_add(1000);
+        flake.impurities = Some(Impurities{ probabilities: [1.0; VAC_LISTS], desorption: 0.0 });
+        flake.random_add(10);
+        let dirt = flake.dirt.list.len();
+        flake.impurities = Some(Impurities{ probabilities: [0.0; VAC_LISTS], desorption: 1.0 });
+        flake.desorb();
+        assert_eq!(flake.dirt.list.len(), dirt - 1);
+        assert!(flake.attach(*flake.vacancies.list[0].iter().next().unwrap()));
+        assert_eq!(flake.bulk.number_of_atoms - flake.bulk.number_of_dirt, 1001 + 1);
+    }
+}
@@ -0,0 +1,150 @@
+/*!
+Long growth and statistics jobs in a background thread
+
+The crystal is moved into a worker thread, so the window stays responsive. The growth loops report their progress every PROGRESS_INTERVAL atoms
+via shared atomic counters and stop as soon as the job is cancelled -- the crystal is then in a consistent state with all atoms added so far.
+When the thread has finished, the crystal is handed back to the world.
+
+Threads are not available for wasm, so there the growth still runs within the render loop.
+*/
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+#[cfg(not(target_arch = "wasm32"))]
+use std::{thread, thread::JoinHandle, time::Duration};
+#[cfg(not(target_arch = "wasm32"))]
+use instant::Instant;
+
+use crate::parameters::*;
+use crate::crystal::*;
+
+
+/// Shared state between the world and a running job.
+#[derive(Debug,Default)]
+pub struct Progress {
+    pub done: AtomicUsize,
+    pub total: AtomicUsize,
+    pub cancelled: AtomicBool,
+    pub finished: AtomicBool,
+}
+
+/// What has to be done with the crystal when the job is finished.
+#[derive(Copy,Clone,Debug,PartialEq)]
+pub enum JobKind {
+    Growth,
+    Statistics,
+}
+
+/// A growth or statistics job running in its own thread -- the result is the number of added atoms.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct Job {
+    pub kind: JobKind,
+    pub progress: Arc<Progress>,
+    pub start: Instant,
+    handle: JoinHandle<(Crystal, usize)>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Job {
+    /// Move the crystal into a new thread and run the function on it -- the total is the expected number of atoms (0 if unknown).
+    pub fn spawn<F>(kind: JobKind, mut flake: Crystal, total: usize, function: F) -> Self
+        where F: FnOnce(&mut Crystal) -> usize + Send + 'static {
+        let progress = Arc::new(Progress::default());
+        progress.total.store(total, Ordering::Relaxed);
+        flake.progress = Some(progress.clone());
+        let handle = thread::spawn(move || {
+            let added_atoms = function(&mut flake);
+            if let Some(progress) = flake.progress.take() {
+                progress.finished.store(true, Ordering::Release);
+            }
+            (flake, added_atoms)
+        });
+        Job{ kind, progress, start: Instant::now(), handle }
+    }
+
+    pub fn finished(&self) -> bool {
+        self.progress.finished.load(Ordering::Acquire)
+    }
+
+    /// Stop the job after the next PROGRESS_INTERVAL atoms.
+    pub fn cancel(&self) {
+        self.progress.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Wait for the thread and take the crystal back.
+    pub fn join(self) -> (Crystal, usize) {
+        self.handle.join().expect("Growth thread panicked")
+    }
+
+    /// Added atoms per second so far.
+    pub fn rate(&self) -> f32 {
+        self.progress.done.load(Ordering::Relaxed) as f32/self.start.elapsed().as_secs_f32()
+    }
+
+    /// Estimated remaining time -- None without a known total.
+    pub fn eta(&self) -> Option<Duration> {
+        let total = self.progress.total.load(Ordering::Relaxed);
+        let done = self.progress.done.load(Ordering::Relaxed);
+        let rate = self.rate();
+        if total == 0 || done == 0 || rate <= 0.0 { return None }
+        Some(Duration::from_secs_f32(total.saturating_sub(done) as f32/rate))
+    }
+}
+
+
+impl Crystal {
+    /// Report the progress every PROGRESS_INTERVAL added atoms -- returns true if the job was cancelled and the growth should stop.
+    pub fn advance(&self, added: usize) -> bool {
+        match &self.progress {
+            Some(progress) if added > 0 && added % PROGRESS_INTERVAL == 0 => {
+                progress.done.fetch_add(PROGRESS_INTERVAL, Ordering::Relaxed);
+                progress.cancelled.load(Ordering::Relaxed)
+            },
+            _ => false,
+        }
+    }
+
+    /// Report a number of atoms added at once (e.g. by the parallel growth) -- returns true if the job was cancelled.
+    pub fn report(&self, added: usize) -> bool {
+        match &self.progress {
+            Some(progress) => {
+                progress.done.fetch_add(added, Ordering::Relaxed);
+                progress.cancelled.load(Ordering::Relaxed)
+            },
+            None => false,
+        }
+    }
+
+    /// Check if a running job was cancelled.
+    pub fn cancelled(&self) -> bool {
+        match &self.progress {
+            Some(progress) => progress.cancelled.load(Ordering::Relaxed),
+            None => false,
+        }
+    }
+}
+
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use crate::lattice::*;
+
+    #[test]
+    fn cancelled_job_returns_the_crystal() {
+        let mut flake = Crystal::new(Lattice::new(STACKING_FAULTS.to_vec(), DIAMETER));
+        flake.add_atom(CENTER);
+        let total = 100*PROGRESS_INTERVAL;
+        let job = Job::spawn(JobKind::Growth, flake, total, move |flake| {
+            let atoms = flake.bulk.number_of_atoms;
+            flake.random_add(total);
+            flake.bulk.number_of_atoms - atoms
+        });
+        job.cancel();
+        while !job.finished() { thread::sleep(Duration::from_millis(1)) }
+        let (flake, added) = job.join();
+        assert!(added < total);
+        assert_eq!(flake.bulk.number_of_atoms, added + 1);
+        assert!(flake.progress.is_none() && !flake.cancelled());
+    }
+}
@@ -1,5 +1,5 @@
 /*!
-Implementation of the fcc lattice with stacking faults
+Implementation of the fcc lattice with stacking faults (and optionally a screw dislocation)
 
 In principle only this needs to be reimplemented for allowing other shapes with other geometries to grow (e.g. nanorods -> 5-fold symmetry).
 
@@ -18,6 +18,75 @@ const X0: XYZ = XYZ{x:1.0, y:0.0, z:0.0};
 const Y0: XYZ = XYZ{x:0.5, y:SIN60, z:0.0};
 const Z0: XYZ = XYZ{x:0.5, y:YPOS, z:ZPOS};
 
+/// A screw dislocation through (i, j) in every layer with a Burgers vector in layers.
+///
+/// The cut is the half-plane between the rows j-1 and j for all positions from i on: stepping across it in +j direction
+/// moves the atom burgers layers up, so the layers around the core form a helix and never close.
+/// The shift (i, j, k+burgers) is burgers times the neighbor vector 6, a translation of the fcc lattice for any number of layers.
+/// The dislocation line runs along the same vector (through (i, j) of every layer), so this is a pure screw dislocation -- but one
+/// inclined to the [111] growth direction: relative to the surface normal the Burgers vector has an in-plane (edge) component of
+/// burgers*(0.5, 0.29)*DIAMETER in x and y, while the steps at the surface are burgers layers high.
+/// (A Burgers vector along [111] would be (i-1, j-1, k+3) per three layers, but it needs a line along [111], which does not run through the grid.)
+/// Stacking faults within burgers layers of a position change the translation, so there the neighborhood at the cut is not symmetric anymore.
+#[derive(Copy,Clone,Debug)]
+pub struct Screw {
+    pub i: u16,
+    pub j: u16,
+    pub burgers: i16,
+}
+
+impl Screw {
+    /// Correct a step from ijk to nn_ijk if it crosses the cut.
+    pub fn cross(&self, ijk: IJK, nn_ijk: IJK) -> IJK {
+        if ijk.i.max(nn_ijk.i) < self.i {
+            return nn_ijk
+        }
+        let shift = if ijk.j < self.j && nn_ijk.j >= self.j {
+            self.burgers
+        }
+        else if ijk.j >= self.j && nn_ijk.j < self.j {
+            -self.burgers
+        }
+        else {
+            return nn_ijk
+        };
+        let k = (nn_ijk.k as i16 + shift).max(0).min(FLAKE_MAX.k as i16 - 1) as u16;
+        IJK{k, ..nn_ijk}
+    }
+}
+
+/// Periodic boundary conditions along i and j: the cell of period x period positions (in i and j) around the center is repeated laterally.
+///
+/// Shifting a position by a whole period in i or j is a translation of the lattice in every layer (the stacking only depends on k),
+/// so the neighbors can simply be mapped back into the cell -- a screw dislocation does not fit into this, though.
+#[derive(Copy,Clone,Debug)]
+pub struct Periodic {
+    pub period: u16,
+    pub i_start: u16,
+    pub j_start: u16,
+}
+
+impl Periodic {
+    pub fn new(period: u16) -> Self {
+        Periodic{ period, i_start: CENTER.i - period/2, j_start: CENTER.j - period/2 }
+    }
+
+    /// Check if a position lies within the cell.
+    pub fn contains(&self, ijk: IJK) -> bool {
+        ijk.i >= self.i_start && ijk.i < self.i_start + self.period && ijk.j >= self.j_start && ijk.j < self.j_start + self.period
+    }
+
+    /// Map a position next to the cell back into it.
+    pub fn wrap(&self, ijk: IJK) -> IJK {
+        let wrap = |value: u16, start: u16| {
+            if value < start { value + self.period }
+            else if value >= start + self.period { value - self.period }
+            else { value }
+        };
+        IJK{i: wrap(ijk.i, self.i_start), j: wrap(ijk.j, self.j_start), k: ijk.k}
+    }
+}
+
 /// Implenentation of the fcc lattice.
 ///
 /// This basically does the mapping between memory locations and real world positions and also provides an iterator over a 3D box.
@@ -25,6 +94,8 @@ const Z0: XYZ = XYZ{x:0.5, y:YPOS, z:ZPOS};
 pub struct Lattice {
     pub stacking: Stackings,
     pub stacking_faults: Vec::<u16>,
+    pub screw: Option<Screw>,
+    pub periodic: Option<Periodic>,
     diameter: f32,
     // the following parameters are only needed for the iterator
     min: XYZ,
@@ -43,6 +114,8 @@ impl Lattice {
         Lattice{ 
             stacking: Stackings::new(&stacking_faults), 
             stacking_faults,
+            screw: None,
+            periodic: None,
             diameter,
             min: XYZ{x: 0.0, y: 0.0, z:0.0}, 
             max: XYZ{x: 0.0, y: 0.0, z:0.0},
@@ -54,6 +127,15 @@ impl Lattice {
             next: CENTER,
         }
     }
+
+    /// The same lattice (diameter, screw, periodic cell, ...) with other stacking faults.
+    pub fn with_stacking_faults(&self, stacking_faults: Vec<u16>) -> Self {
+        Lattice{
+            stacking: Stackings::new(&stacking_faults),
+            stacking_faults,
+            ..self.clone()
+        }
+    }
     
     /// mapping from a memory location to a point in space using the fcc lattice
     pub fn get_xyz(&self, ijk: IJK) -> XYZ {
@@ -101,21 +183,35 @@ impl Lattice {
         // Note, the stacking shift occurs between the layers.
         // So, the shift[k+1] refers to the shift from layer k to k+1
         // and the shift[k] from k to k-1.
+        // Positions at index 0 wrap around to u16::MAX instead of underflowing, i.e. their neighbors lie outside of the storage
+        // and are refused by the boundary checks of the callers (see the boundary module).
         let IJK{i,j,k} = ijk;
-        match neighbor {
+        let nn_ijk = match neighbor {
             0 => IJK{i: i + 1 ,j: j + 0 ,k: k},
             1 => IJK{i: i + 0 ,j: j + 1 ,k: k},
-            2 => IJK{i: i + 1 ,j: j - 1 ,k: k},
-            3 => IJK{i: i - 1 ,j: j + 1 ,k: k},
-            4 => IJK{i: i - 1 ,j: j + 0 ,k: k},
-            5 => IJK{i: i + 0 ,j: j - 1 ,k: k},
+            2 => IJK{i: i + 1 ,j: j.wrapping_sub(1) ,k: k},
+            3 => IJK{i: i.wrapping_sub(1) ,j: j + 1 ,k: k},
+            4 => IJK{i: i.wrapping_sub(1) ,j: j + 0 ,k: k},
+            5 => IJK{i: i + 0 ,j: j.wrapping_sub(1) ,k: k},
             6 => IJK{i: i + 0 ,j: j + 0 ,k: k + 1},
-            7 => IJK{i: i - self.stacking.shift_i[(k+1) as usize] as u16,j: (j as i16 - self.stacking.shift_j[(k+1) as usize]) as u16 ,k: k + 1},
-            8 => IJK{i: i - 1 ,j: j + 0 ,k: k + 1},        
-            9 => IJK{i: i + 0 ,j: j + 0 ,k: k - 1},  
-            10 => IJK{i: i + self.stacking.shift_i[k as usize] as u16,j: (j as i16 + self.stacking.shift_j[k as usize]) as u16 ,k: k - 1}, 
-            11 => IJK{i: i + 1 ,j: j + 0 ,k: k - 1}, 
+            7 => IJK{i: i.wrapping_sub(self.stacking.shift_i[(k+1) as usize]),j: (j as i16 - self.stacking.shift_j[(k+1) as usize]) as u16 ,k: k + 1},
+            8 => IJK{i: i.wrapping_sub(1) ,j: j + 0 ,k: k + 1},        
+            9 => IJK{i: i + 0 ,j: j + 0 ,k: k.wrapping_sub(1)},  
+            10 => IJK{i: i + self.stacking.shift_i[k as usize] as u16,j: (j as i16 + self.stacking.shift_j[k as usize]) as u16 ,k: k.wrapping_sub(1)}, 
+            11 => IJK{i: i + 1 ,j: j + 0 ,k: k.wrapping_sub(1)}, 
             _ => IJK{i,j,k}
+        };
+
+        // a screw dislocation shifts the layers when crossing its cut
+        let nn_ijk = match &self.screw {
+            Some(screw) => screw.cross(ijk, nn_ijk),
+            None => nn_ijk
+        };
+
+        // and periodic boundaries map the neighbors back into the cell
+        match &self.periodic {
+            Some(periodic) => periodic.wrap(nn_ijk),
+            None => nn_ijk
         }
     }
 
@@ -259,3 +355,88 @@ impl Stackings {
         }
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // number of neighbor relations around the core which are not mutual
+    fn asymmetric(lattice: &Lattice) -> usize {
+        let mut asymmetric = 0;
+        for di in 0..8 {
+            for dj in 0..8 {
+                for dk in 0..8 {
+                    let ijk = IJK{i: CENTER.i - 4 + di, j: CENTER.j - 4 + dj, k: CENTER.k - 4 + dk};
+                    for l in 0..12 {
+                        let nn_ijk = lattice.next_neighbor(ijk, l);
+                        if !(0..12).any(|m| lattice.next_neighbor(nn_ijk, m) == ijk) { asymmetric += 1 }
+                    }
+                }
+            }
+        }
+        asymmetric
+    }
+
+    #[test]
+    fn screw_neighbors_are_mutual() {
+        for &burgers in [1, 2, 3, -3].iter() {
+            let mut lattice = Lattice::new(vec![], DIAMETER);
+            lattice.screw = Some(Screw{i: CENTER.i, j: CENTER.j, burgers});
+            assert_eq!(asymmetric(&lattice), 0, "burgers {}", burgers);
+        }
+    }
+
+    #[test]
+    fn screw_shift_is_a_translation_along_neighbor_6() {
+        let mut lattice = Lattice::new(vec![], DIAMETER);
+        let plain = lattice.clone();
+        lattice.screw = Some(Screw{i: CENTER.i, j: CENTER.j, burgers: 3});
+        let ijk = IJK{i: CENTER.i + 2, j: CENTER.j - 1, ..CENTER};
+        let (crossed, direct) = (lattice.next_neighbor(ijk, 1), plain.next_neighbor(ijk, 1));
+        assert_eq!(crossed, IJK{k: direct.k + 3, ..direct});
+        let (a, b) = (lattice.get_xyz(crossed), lattice.get_xyz(direct));
+        for (delta, unit) in [(a.x - b.x, Z0.x), (a.y - b.y, Z0.y), (a.z - b.z, Z0.z)].iter() {
+            assert!((delta - 3.0*unit*DIAMETER).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn other_stacking_faults_keep_the_lattice() {
+        let mut lattice = Lattice::new(vec![], 2.0*DIAMETER);
+        lattice.screw = Some(Screw{i: CENTER.i, j: CENTER.j, burgers: 1});
+        lattice.periodic = Some(Periodic::new(8));
+        let faulted = lattice.with_stacking_faults(vec![CENTER.k + 1]);
+        assert_eq!(faulted.stacking_faults, vec![CENTER.k + 1]);
+        assert!(faulted.screw.is_some() && faulted.periodic.is_some());
+        assert_eq!(faulted.get_xyz(CENTER).x, lattice.get_xyz(CENTER).x);
+        let ijk = IJK{i: CENTER.i + 1, k: CENTER.k + 2, ..CENTER};
+        assert_eq!(faulted.get_xyz(ijk).x, Lattice::new(vec![CENTER.k + 1], 2.0*DIAMETER).get_xyz(ijk).x);
+        assert_ne!(faulted.get_xyz(ijk).y, lattice.get_xyz(ijk).y);
+    }
+
+    #[test]
+    fn periodic_wrap_maps_into_the_cell() {
+        let periodic = Periodic::new(4);
+        let (i0, j0) = (periodic.i_start, periodic.j_start);
+        assert_eq!(periodic.wrap(IJK{i: i0 - 1, j: j0 + 4, k: 7}), IJK{i: i0 + 3, j: j0, k: 7});
+        assert_eq!(periodic.wrap(IJK{i: i0 + 2, j: j0 - 1, k: 7}), IJK{i: i0 + 2, j: j0 + 3, k: 7});
+        assert!(periodic.contains(IJK{i: i0 + 3, j: j0, k: 7}) && !periodic.contains(IJK{i: i0 + 4, j: j0, k: 7}));
+
+        // within the cell all neighbors stay inside and are mutual -- also across the stacking faults
+        let mut lattice = Lattice::new(vec![CENTER.k + 1], DIAMETER);
+        lattice.periodic = Some(periodic);
+        for i in i0..i0 + 4 {
+            for j in j0..j0 + 4 {
+                for k in CENTER.k - 2..CENTER.k + 3 {
+                    let ijk = IJK{i, j, k};
+                    for l in 0..12 {
+                        let nn_ijk = lattice.next_neighbor(ijk, l);
+                        assert!(periodic.contains(nn_ijk));
+                        assert!((0..12).any(|m| lattice.next_neighbor(nn_ijk, m) == ijk));
+                    }
+                }
+            }
+        }
+    }
+}
@@ -8,6 +8,28 @@ mod parameters;     pub use parameters::*;
 mod lattice;        
 mod storage;        
 mod crystal;         
+mod milling;        
+mod annealing;      
+mod wulff;          
+mod particles;      
+mod diffusion;      
+mod walkers;        
+mod ripening;       
+mod flux;           
+mod alloy;          
+mod impurities;     
+mod protocol;       
+mod branches;       
+mod stop_conditions;
+mod job;            
+mod parallel;       
+mod boundary;       
+mod film;           
+mod height_map;     
+mod monolayer;      
+mod scene_file;     
+#[cfg(not(target_arch = "wasm32"))]
+mod headless;       
 mod scene;          
 mod planar_scene; 
 
@@ -17,6 +39,15 @@ mod sidebar;
 pub use sidebar::*;
 
 fn main() {
+    // headless mode without any window
+    #[cfg(not(target_arch = "wasm32"))]
+    let args: Vec<String> = std::env::args().collect();
+    #[cfg(not(target_arch = "wasm32"))]
+    if args.iter().any(|arg| arg == "--headless") {
+        headless::run(&args[1..]);
+        return
+    }
+
     let mut window = Window::new_with_size("Flake Growth", 1600, 900);
     window.set_light(Light::Absolute(Point3::new(-300.0, 300.0, 300.0)));
     window.set_background_color(1.0, 1.0, 1.0);
@@ -25,5 +56,11 @@ fn main() {
     println!("Stacking faults {:?}", STACKING_FAULTS);
     world.add_random_atoms(&mut window, true, 1);
     // world.add_random_atoms(&mut window, true, 2_000);
+
+    // start with a scene file if one is given
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(path) = args.iter().skip(1).find(|arg| arg.ends_with(".toml")) {
+        world.load_scene_file(&mut window, path);
+    }
     window.render_loop(world)
 }
@@ -0,0 +1,203 @@
+/*!
+Focused ion beam (FIB) milling of a grown crystal
+
+A beam with a gaussian profile is moved along a path (line, filled polygon or rectangular raster) in dwell points of half its spot size.
+Each ion hits the topmost atom below its impact point and sputters it with a probability depending on the coordination number of the atom:
+terrace atoms (9 neighbors) are removed with the given sputter yield, lower coordinated ones (edges, kinks, adatoms) correspondingly more easily.
+Sputtered gold atoms may optionally be redeposited at a random vacancy.
+
+Dirt atoms act like a resist layer and are removed with the plain sputter yield.
+*/
+
+use rand::Rng;
+
+use crate::helpers::*;
+use crate::parameters::*;
+use crate::storage::*;
+use crate::crystal::*;
+
+
+/// Parameters of the ion beam.
+#[derive(Copy,Clone,Debug)]
+pub struct Beam {
+    pub spot_size: f32,
+    pub dose: f32,
+    pub sputter_yield: f32,
+    pub redeposition: f32,
+}
+
+impl Default for Beam {
+    fn default() -> Self {
+        Beam{ spot_size: SPOT_SIZE, dose: DOSE, sputter_yield: SPUTTER_YIELD, redeposition: REDEPOSITION }
+    }
+}
+
+/// The path the beam is moved along -- only the x and y coordinates are used.
+#[derive(Clone,Debug)]
+pub enum BeamPath {
+    Line(XYZ, XYZ),
+    Polygon(Vec<XYZ>),
+    Raster{min: XYZ, max: XYZ},
+}
+
+impl BeamPath {
+    /// Discretize the path into dwell points with a distance of pitch.
+    pub fn dwell_points(&self, pitch: f32) -> Vec<XYZ> {
+        let mut points = Vec::new();
+        match self {
+            BeamPath::Line(start, end) => {
+                let length = ((end.x - start.x).powi(2) + (end.y - start.y).powi(2)).sqrt();
+                let steps = (length/pitch).ceil().max(1.0) as usize;
+                for step in 0..=steps {
+                    let t = step as f32 / steps as f32;
+                    points.push(XYZ{x: start.x + t*(end.x - start.x), y: start.y + t*(end.y - start.y), z: 0.0});
+                }
+            },
+            BeamPath::Polygon(corners) => {
+                // raster over the bounding box and keep the points inside
+                let x_min = corners.iter().map(|p| p.x).fold(f32::INFINITY, f32::min);
+                let x_max = corners.iter().map(|p| p.x).fold(f32::NEG_INFINITY, f32::max);
+                let y_min = corners.iter().map(|p| p.y).fold(f32::INFINITY, f32::min);
+                let y_max = corners.iter().map(|p| p.y).fold(f32::NEG_INFINITY, f32::max);
+                let raster = BeamPath::Raster{min: XYZ{x: x_min, y: y_min, z: 0.0}, max: XYZ{x: x_max, y: y_max, z: 0.0}};
+                points = raster.dwell_points(pitch).into_iter().filter(|&p| inside_polygon(p, corners)).collect();
+            },
+            BeamPath::Raster{min, max} => {
+                // serpentine scan line by line
+                let lines = ((max.y - min.y)/pitch).ceil().max(0.0) as usize;
+                let steps = ((max.x - min.x)/pitch).ceil().max(0.0) as usize;
+                for line in 0..=lines {
+                    let y = min.y + line as f32 * pitch;
+                    for step in 0..=steps {
+                        let step = if line%2 == 0 { step } else { steps - step };
+                        points.push(XYZ{x: min.x + step as f32 * pitch, y, z: 0.0});
+                    }
+                }
+            },
+        }
+        points
+    }
+}
+
+/// Even-odd rule for checking if a point lies within a polygon.
+fn inside_polygon(point: XYZ, corners: &[XYZ]) -> bool {
+    let mut inside = false;
+    let mut j = corners.len().wrapping_sub(1);
+    for i in 0..corners.len() {
+        let (a, b) = (corners[i], corners[j]);
+        if (a.y > point.y) != (b.y > point.y) && point.x < (b.x - a.x)*(point.y - a.y)/(b.y - a.y) + a.x {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+
+impl Crystal {
+    /// Mill the crystal along the given path -- returns the number of removed and redeposited atoms.
+    pub fn mill(&mut self, beam: &Beam, path: &BeamPath) -> (usize, usize) {
+        let mut rng = rand::thread_rng();
+        let mut removed = 0;
+        let mut redeposited = 0;
+
+        // gaussian profile and ions per dwell point
+        let pitch = beam.spot_size/2.0;
+        let sigma = beam.spot_size/2.355;
+        let ions_per_point = beam.dose*pitch*pitch;
+
+        for point in path.dwell_points(pitch) {
+            // the fractional part of the ions is taken into account statistically
+            let mut ions = ions_per_point.floor() as usize;
+            if rng.gen::<f32>() < ions_per_point.fract() { ions += 1 }
+
+            for _ion in 0..ions {
+                // Box-Muller transform for the impact point
+                let r = sigma*(-2.0*(1.0 - rng.gen::<f32>()).ln()).sqrt();
+                let phi = 2.0*std::f32::consts::PI*rng.gen::<f32>();
+                let impact = XYZ{x: point.x + r*phi.cos(), y: point.y + r*phi.sin(), z: 0.0};
+
+                if let Some(ijk) = self.hit_atom(impact) {
+                    // sputter probability depending on the coordination
+                    let probability = if self.bulk.get(ijk, Atom::Dirt) {
+                        beam.sputter_yield
+                    }
+                    else {
+                        beam.sputter_yield*(12 - self.number_of_neighbors(ijk)) as f32/3.0
+                    };
+                    if rng.gen::<f32>() < probability {
+                        let atom = self.bulk.atom(ijk);
+                        self.clear_site(ijk);
+                        removed += 1;
+
+                        // redeposition somewhere at the crystal
+                        // (not without any vacancy left, e.g. when the whole crystal is milled away)
+                        if atom != Atom::Dirt && rng.gen::<f32>() < beam.redeposition {
+                            if let Some(vacancy) = self.random_vacancy().filter(|&vacancy| self.bulk.get(vacancy, Atom::Empty)) {
+                                if self.add_metal(vacancy, atom) { redeposited += 1 }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        // the extrema only have to be reconstructed once at the end
+        self.recalc_extrema();
+        (removed, redeposited)
+    }
+
+    /// Find the topmost atom below a point (only x and y are used).
+    fn hit_atom(&self, xyz: XYZ) -> Option<IJK> {
+        for k in (self.bulk.k_min..=self.bulk.k_max).rev() {
+            let z = self.lattice.get_xyz(IJK{i: CENTER.i, j: CENTER.j, k}).z;
+            let ijk = self.lattice.get_ijk(XYZ{x: xyz.x, y: xyz.y, z});
+            if ijk.i > 0 && ijk.i < FLAKE_MAX.i && ijk.j > 0 && ijk.j < FLAKE_MAX.j && !self.bulk.get(ijk, Atom::Empty) {
+                return Some(ijk)
+            }
+        }
+        None
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lattice::*;
+
+    #[test]
+    fn dwell_points_follow_the_path() {
+        let xyz = |x: f32, y: f32| XYZ{x, y, z: 0.0};
+        assert_eq!(BeamPath::Line(xyz(0.0, 0.0), xyz(1.0, 0.0)).dwell_points(0.25).len(), 5);
+        let raster = BeamPath::Raster{min: xyz(0.0, 0.0), max: xyz(1.0, 1.0)}.dwell_points(0.5);
+        assert_eq!(raster.len(), 9);
+        assert_eq!(raster[3].x, 1.0);                   // the second line runs backwards
+        let triangle = BeamPath::Polygon(vec![xyz(0.0, 0.0), xyz(2.0, 0.0), xyz(0.0, 2.0)]).dwell_points(0.5);
+        assert!(!triangle.is_empty() && triangle.iter().all(|p| p.x + p.y <= 2.0));
+    }
+
+    #[test]
+    fn milling_removes_atoms() {
+        let mut flake = Crystal::new(Lattice::new(STACKING_FAULTS.to_vec(), DIAMETER));
+        flake.add_atom(CENTER);
+        flake.random_add(5000);
+        let atoms = flake.bulk.number_of_atoms;
+        let beam = Beam{ redeposition: 0.0, ..Beam::default() };
+        let (removed, redeposited) = flake.mill(&beam, &BeamPath::Line(XYZ{x: -2.0, y: 0.0, z: 0.0}, XYZ{x: 2.0, y: 0.0, z: 0.0}));
+        assert!(removed > 0);
+        assert_eq!(redeposited, 0);
+        assert_eq!(flake.bulk.number_of_atoms, atoms - removed);
+    }
+
+    #[test]
+    fn no_redeposition_without_vacancies() {
+        let mut flake = Crystal::new(Lattice::new(vec![], DIAMETER));
+        assert_eq!(flake.random_vacancy(), None);
+        flake.add_atom(CENTER);
+        let beam = Beam{ redeposition: 1.0, sputter_yield: 100.0, dose: 100.0, ..Beam::default() };
+        let (removed, redeposited) = flake.mill(&beam, &BeamPath::Line(XYZ{x: -1.0, y: 0.0, z: 0.0}, XYZ{x: 1.0, y: 0.0, z: 0.0}));
+        assert_eq!((removed, redeposited), (1, 0));
+        assert_eq!(flake.bulk.number_of_atoms, 0);
+    }
+}
@@ -0,0 +1,185 @@
+/*!
+Two-dimensional growth of a monolayer on a substrate
+
+For sub-monolayer island shapes the growth is confined to the first layer on the substrate: the atoms only attach in-plane
+(neighbors 0-5 of the lattice) and every site is bonded to the three substrate atoms below it, which are not part of the bulk.
+
+The monolayer has its own vacancy classes: the number of in-plane neighbors (1-6) and whether two adjacent ones close a triangle
+over a substrate atom, which is the case at B steps ({111} microfacets) but not at A steps ({100} microfacets). Equal probabilities
+for both give hexagonal islands, favouring one type lets it grow out, so the islands are bounded by the other one and become triangular.
+The sites are kept in plain lists with an index, so large domains are grown quickly -- with a periodic lattice
+(see the film module) even without any edges.
+*/
+
+use rand::Rng;
+use std::collections::HashMap;
+use std::hash::BuildHasherDefault;
+
+use crate::helpers::*;
+use crate::parameters::*;
+use crate::storage::*;
+use crate::lattice::*;
+use crate::crystal::*;
+use crate::boundary::*;
+
+#[cfg(target_arch = "wasm32")]
+use crate::println;
+
+
+// the in-plane neighbors in the order around the hexagon, i.e. consecutive ones are neighbors of each other, too
+const RING: [usize; 6] = [0, 1, 3, 4, 5, 2];
+
+/// The monolayer in layer k with the sites of its vacancy classes.
+#[derive(Clone)]
+pub struct Monolayer {
+    pub k: u16,
+    pub prob_list: [u64; MONOLAYER_CLASSES],
+    pub sites: Vec<Vec<IJK>>,
+    index: HashMap<IJK, (usize, usize), BuildHasherDefault<PositionHasher>>,
+    atoms: usize,                           // number of atoms the sites were determined for -- they are rebuilt if the crystal has changed otherwise
+}
+
+impl Monolayer {
+    pub fn new(k: u16) -> Self {
+        Monolayer{ k, prob_list: MONOLAYER_PROBABILITIES, sites: vec![Vec::new(); MONOLAYER_CLASSES], index: HashMap::default(), atoms: 0 }
+    }
+
+    /// Forget all sites (they are rebuilt with the next growth).
+    pub fn reset(&mut self) {
+        self.sites = vec![Vec::new(); MONOLAYER_CLASSES];
+        self.index.clear();
+        self.atoms = 0;
+    }
+
+    /// Move a site to another class (or remove it with None).
+    fn set_class(&mut self, ijk: IJK, class: Option<usize>) {
+        if let Some((old_class, pos)) = self.index.remove(&ijk) {
+            self.sites[old_class].swap_remove(pos);
+            if let Some(&moved) = self.sites[old_class].get(pos) {
+                self.index.insert(moved, (old_class, pos));
+            }
+        }
+        if let Some(class) = class {
+            self.index.insert(ijk, (class, self.sites[class].len()));
+            self.sites[class].push(ijk);
+        }
+    }
+}
+
+/// Check if the triangle of a position and two of its in-plane neighbors lies over a substrate atom (the common neighbor below all three).
+fn over_substrate(lattice: &Lattice, ijk: IJK, l1: usize, l2: usize) -> bool {
+    let below = |ijk: IJK| [9, 10, 11].iter().map(|&l| lattice.next_neighbor(ijk, l)).collect::<Vec<IJK>>();
+    let (a, b) = (below(lattice.next_neighbor(ijk, l1)), below(lattice.next_neighbor(ijk, l2)));
+    below(ijk).iter().any(|nn_ijk| a.contains(nn_ijk) && b.contains(nn_ijk))
+}
+
+impl Crystal {
+    /// Switch the monolayer growth on (in the layer of the center with the substrate below it) or off -- the substrate stays then.
+    /// A periodic film restarts from a seed, as its complete first layer leaves no monolayer sites.
+    pub fn switch_monolayer(&mut self) {
+        match self.monolayer {
+            Some(_) => {
+                self.monolayer = None;
+                self.update_vacancies();
+            },
+            None => {
+                self.substrate_pos = CENTER.k - 1;
+                self.monolayer = Some(Monolayer::new(CENTER.k));
+                if let Some(periodic) = self.lattice.periodic { self.set_periodic(Some(periodic.period)) }
+            },
+        }
+    }
+
+    /// The vacancy class of an empty position in the monolayer -- None without any in-plane neighbors.
+    pub fn monolayer_class(&self, ijk: IJK) -> Option<usize> {
+        let occupied: Vec<bool> = RING.iter().map(|&l| self.bulk.is_metal(self.lattice.next_neighbor(ijk, l))).collect();
+        let neighbors = occupied.iter().filter(|&&occupied| occupied).count();
+        if neighbors == 0 { return None }
+        let b_step = (0..6).any(|index| occupied[index] && occupied[(index + 1)%6] && over_substrate(&self.lattice, ijk, RING[index], RING[(index + 1)%6]));
+        Some(2*(neighbors - 1) + b_step as usize)
+    }
+
+    /// Update the classes of the empty in-plane neighbors of a position.
+    fn update_monolayer_sites(&mut self, monolayer: &mut Monolayer, ijk: IJK) {
+        for l in 0..6 {
+            let nn_ijk = self.lattice.next_neighbor(ijk, l);
+            if !within_walls(nn_ijk) {
+                self.reached_boundary();
+            }
+            else if self.bulk.get(nn_ijk, Atom::Empty) {
+                monolayer.set_class(nn_ijk, self.monolayer_class(nn_ijk));
+            }
+        }
+    }
+
+    /// Add atoms to the monolayer -- used by random_add when the monolayer growth is switched on.
+    pub fn monolayer_add(&mut self, number_of_atoms: usize) {
+        let mut monolayer = match self.monolayer.take() {
+            Some(monolayer) => monolayer,
+            None => return,
+        };
+
+        // the sites are rebuilt from the atoms of the layer if the crystal was changed in another way (e.g. cleared or a seed added)
+        if monolayer.atoms != self.bulk.number_of_atoms {
+            monolayer.reset();
+            let atoms: Vec<IJK> = self.surface.list.iter().filter(|ijk| ijk.k == monolayer.k).cloned().collect();
+            for ijk in atoms {
+                self.update_monolayer_sites(&mut monolayer, ijk);
+            }
+        }
+
+        let mut rng = rand::thread_rng();
+        let mut prob_sum = [0u64; MONOLAYER_CLASSES];
+        let mut added = Vec::new();
+        for index in 0..number_of_atoms {
+            if self.advance(index) { break }
+
+            // chose a class and a random site in it
+            let mut sum = 0;
+            for (class, sites) in monolayer.sites.iter().enumerate() {
+                sum += monolayer.prob_list[class]*sites.len() as u64;
+                prob_sum[class] = sum;
+            }
+            if sum == 0 { break }
+            let random_number = rng.gen_range(0, sum);
+            if let Some(class) = prob_sum.iter().position(|&x| x > random_number) {
+                let ijk = monolayer.sites[class][rng.gen_range(0, monolayer.sites[class].len())];
+                monolayer.set_class(ijk, None);
+                self.set_site(ijk, Atom::Gold);
+                self.update_extrema(ijk);
+                added.push(ijk);
+                self.update_monolayer_sites(&mut monolayer, ijk);
+            }
+        }
+
+        // all atoms of a monolayer are at the surface
+        self.add_to_surface(&mut added);
+        monolayer.atoms = self.bulk.number_of_atoms;
+        self.monolayer = Some(monolayer);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn periodic_monolayer_starts_from_a_seed() {
+        for &monolayer_first in [true, false].iter() {
+            let mut flake = Crystal::new(Lattice::new(vec![], DIAMETER));
+            if monolayer_first {
+                flake.switch_monolayer();
+                flake.set_periodic(Some(40));
+            }
+            else {
+                flake.set_periodic(Some(40));
+                flake.switch_monolayer();
+            }
+            assert_eq!(flake.bulk.number_of_atoms, 1);
+            flake.random_add(500);
+            assert_eq!(flake.bulk.number_of_atoms, 501);
+            assert_eq!((flake.extrema_ijk.z_min.k, flake.extrema_ijk.z_max.k), (CENTER.k, CENTER.k));
+        }
+    }
+}
@@ -0,0 +1,308 @@
+/*!
+Parallel growth of a single flake via domain decomposition
+
+The flake is cut into strips along i (the domains), which are alternately colored. In every synchronisation cycle the domains of one color
+grow at the same time, then the ones of the other color -- this is the synchronous sublattice algorithm (Shim & Amar). Adding an atom changes
+only its next neighbors, i.e. positions at most one layer (in i) outside of its domain. As the domains of the other color lie in between,
+two growing domains never touch the same positions and each one gets its own part of the bulk. There are as many domains as the flake allows
+(all at least PARALLEL_MIN_WIDTH wide), which are handed out to the threads one after another.
+
+Each domain owns the vacancies and surface atoms within it and runs the same kinetic Monte Carlo as random_add: a vacancy with coordination number n
+is chosen with a rate proportional to the n-th entry of the probability list. Instead of a fixed number of atoms every domain grows for the same
+time window, which is chosen such that the whole flake gets about the wanted number of atoms per cycle. Vacancies and hidden atoms outside
+of a domain are handed over to their owner after each half cycle. The domains are cut anew (with randomly shifted borders, so they leave no traces)
+whenever the flake has grown by 1/PARALLEL_RESOLUTION, with PARALLEL_CYCLES synchronisation cycles in between.
+
+This keeps the statistics of the serial growth as long as the time windows are short: atoms near the borders only see the neighboring domain
+as it was at the start of the half cycle, so new rows along the edges of the flake stall at the borders while the facets keep growing
+and too few cycles make the hexagons triangular. The hand-overs between the half cycles remain serial, so the speedup stays below the number of threads.
+Both can be checked with `--threads 8 --compare` in the headless mode, which grows the same flake serially and in parallel.
+
+The parallel growth needs the cached coordination numbers (which set_threads allocates) and is used by random_add for at least PARALLEL_MIN_ATOMS atoms if the crystal
+has more than one thread. Smaller flakes, which can not be cut into four domains yet, grow serially until they are large enough.
+*/
+
+use std::collections::BTreeSet;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use rand::Rng;
+use rand::seq::IteratorRandom;
+
+use crate::helpers::*;
+use crate::parameters::*;
+use crate::lattice::*;
+use crate::storage::*;
+use crate::crystal::*;
+
+
+/// The number of threads to use -- 0 means all cores.
+pub fn number_of_threads(threads: usize) -> usize {
+    if cfg!(target_arch = "wasm32") { return 1 }
+    match threads {
+        0 => thread::available_parallelism().map_or(1, |cores| cores.get()),
+        threads => threads,
+    }
+}
+
+/// A strip of the flake (all positions with i_start <= i < i_end) with its own vacancy lists.
+struct Domain {
+    i_start: u16,
+    i_end: u16,
+    vacancies: Vec<BTreeSet<IJK>>,
+    surface: BTreeSet<IJK>,
+    added: Vec<IJK>,
+    counted: usize,
+    handed_over: Vec<IJK>,
+    hidden: Vec<IJK>,
+}
+
+impl Domain {
+    fn contains(&self, ijk: IJK) -> bool {
+        ijk.i >= self.i_start && ijk.i < self.i_end
+    }
+
+    /// Sum of the rates of all vacancies -- it is zero if nothing can be added anymore.
+    fn rate(&self, prob_list: &[u64; VAC_LISTS]) -> u64 {
+        self.vacancies.iter().enumerate().map(|(index, list)| prob_list[index]*list.len() as u64).sum()
+    }
+
+    /// Add atoms until the time is over or the budget of atoms is used up -- the same steps as in random_add.
+    fn grow(&mut self, part: &mut BulkPart, lattice: &Lattice, prob_list: &[u64; VAC_LISTS], substrate_pos: u16, time: f64, budget: &AtomicUsize) {
+        let mut rng = rand::thread_rng();
+        let mut prob_sum = [0u64; VAC_LISTS];
+        let mut elapsed = 0.0;
+        let first_added = self.added.len();
+
+        loop {
+            // total rate of the domain and the time until the next event
+            let mut sum = 0;
+            for (index, list) in self.vacancies.iter().enumerate() {
+                sum += prob_list[index]*list.len() as u64;
+                prob_sum[index] = sum;
+            }
+            if sum == 0 { break }
+            elapsed += -(1.0 - rng.gen::<f64>()).ln()/sum as f64;
+            if elapsed > time { break }
+
+            // chose a list and a random vacancy in it
+            let random_number = rng.gen_range(0, sum);
+            let chosen_list = prob_sum.iter().position(|&x| x > random_number).unwrap_or(0);
+            let ijk = match self.vacancies[chosen_list].iter().choose(&mut rng) {
+                Some(&ijk) => ijk,
+                None => continue
+            };
+            if budget.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |atoms| atoms.checked_sub(1)).is_err() { break }
+            self.vacancies[chosen_list].take(&ijk);
+            if !part.empty(ijk) {       // only possible at the cut of a screw dislocation through stacking faults
+                budget.fetch_add(1, Ordering::Relaxed);
+                continue
+            }
+
+            // add to the bulk and update the coordination numbers
+            part.add_gold(ijk);
+            self.added.push(ijk);
+            for l in 0..12 {
+                part.add_neighbor(lattice.next_neighbor(ijk, l));
+            }
+
+            // update the vacancy lists -- the ones of other domains later -- and remember now-hidden atoms
+            for l in 0..12 {
+                let nn_ijk = lattice.next_neighbor(ijk, l);
+                if inside(nn_ijk, substrate_pos) && part.empty(nn_ijk) {
+                    if !self.contains(nn_ijk) {
+                        self.handed_over.push(nn_ijk);
+                        continue
+                    }
+                    match part.coordination(nn_ijk) {
+                        1 => { self.vacancies[0].insert(nn_ijk); },
+                        x if x>1 && x<9 => {
+                            self.vacancies[x-1].insert(nn_ijk);
+                            self.vacancies[x-2].take(&nn_ijk);
+                        },
+                        _=> { }
+                    }
+                }
+                else if part.metal(nn_ijk) && part.coordination(nn_ijk) == 12 {
+                    if self.contains(nn_ijk) {
+                        self.surface.remove(&nn_ijk);
+                    }
+                    else {
+                        self.hidden.push(nn_ijk);
+                    }
+                }
+            }
+        }
+
+        // just like at the end of random_add
+        self.added[first_added..].sort();
+        for &ijk in self.added[first_added..].iter() {
+            if part.coordination(ijk) < 12 { self.surface.insert(ijk); }
+        }
+    }
+
+    /// Put a vacancy changed by a neighboring domain into the list matching its coordination number.
+    fn update_vacancy(&mut self, ijk: IJK, coordination: usize) {
+        for list in self.vacancies.iter_mut() {
+            list.take(&ijk);
+        }
+        if coordination > 0 {
+            self.vacancies[coordination.min(8) - 1].insert(ijk);
+        }
+    }
+}
+
+/// Check if a vacancy is within the allowed region of the storage.
+fn inside(ijk: IJK, substrate_pos: u16) -> bool {
+    ijk.i > 1 && ijk.i < FLAKE_MAX.i - 2
+        && ijk.j > 1 && ijk.j < FLAKE_MAX.j - 2
+        && ijk.k > substrate_pos && ijk.k < FLAKE_MAX.k - 2
+}
+
+
+impl Crystal {
+    /// Check if the current growth can be done in parallel.
+    pub fn parallel_possible(&self) -> bool {
+        self.impurities.is_none() && !self.particles.active() && self.lattice.periodic.is_none() && self.cached_coordination()
+    }
+
+    /// Add atoms in several threads -- the crystal is split into new domains whenever it has grown by 1/PARALLEL_RESOLUTION.
+    pub fn parallel_add(&mut self, number_of_atoms: usize) {
+        let start = self.bulk.number_of_atoms;
+        let budget = AtomicUsize::new(0);
+        let mut time_per_atom = None;
+
+        loop {
+            let added = self.bulk.number_of_atoms - start;
+            if added >= number_of_atoms || self.cancelled() { break }
+            let remaining = number_of_atoms - added;
+            budget.store(remaining, Ordering::Relaxed);
+
+            // small flakes and the last atoms are grown serially (just like all vacancies with a probability of zero)
+            let rate: u64 = self.vacancies.list.iter().enumerate().map(|(index, list)| self.prob_list[index]*list.len() as u64).sum();
+            let domains = if remaining >= PARALLEL_MIN_ATOMS && rate > 0 { self.domains() } else { None };
+            let mut domains = match domains {
+                Some(domains) => domains,
+                None => {
+                    self.random_add(remaining.min(PARALLEL_MIN_ATOMS - 1));
+                    if self.bulk.number_of_atoms - start == added { break }     // nothing can be added anymore
+                    continue
+                }
+            };
+
+            // the time window in which the whole flake gets the wanted number of atoms per cycle -- the rate drops quickly when the fast
+            // vacancies are filled, so it is only the first guess and afterwards the time per atom of the previous cycle is used
+            let epoch_atoms = (self.bulk.number_of_atoms/PARALLEL_RESOLUTION).min(remaining);
+            let cycle_atoms = epoch_atoms/PARALLEL_CYCLES + 1;
+            let epoch_start = self.bulk.number_of_atoms;
+            while self.bulk.number_of_atoms - epoch_start < epoch_atoms {
+                let cycle_start = self.bulk.number_of_atoms;
+                let time = cycle_atoms as f64*time_per_atom.unwrap_or(1.0/rate as f64);
+
+                // grow the domains of one color after the other
+                let first_color = rand::thread_rng().gen_range(0, 2);
+                for color in [first_color, 1 - first_color].iter() {
+                    self.grow_domains(&mut domains, *color, time, &budget);
+                }
+                let cycle_added = self.bulk.number_of_atoms - cycle_start;
+                if cycle_added == 0 && domains.iter().all(|domain| domain.rate(&self.prob_list) == 0) { break }
+                time_per_atom = Some(time/cycle_added.max(cycle_atoms/10).max(1) as f64);
+                if self.report(cycle_added) || budget.load(Ordering::Relaxed) == 0 { break }
+            }
+            self.merge_domains(domains);
+            self.check_boundary();
+        }
+    }
+
+    /// Cut the flake into strips of at least PARALLEL_MIN_WIDTH with randomly shifted borders -- None if there are less than four of them.
+    fn domains(&mut self) -> Option<Vec<Domain>> {
+        let first = self.bulk.i_min.saturating_sub(1);
+        let last = self.bulk.i_max + 2;
+        let number = (last - first)/PARALLEL_MIN_WIDTH/2*2;
+        if number < 4 { return None }
+        let width = (last - first)/number;
+        let offset = rand::thread_rng().gen_range(0, width);
+
+        // the first and the last strip reach to the borders of the storage
+        let mut borders: Vec<u16> = (1..number).map(|index| first + offset + index*width).collect();
+        borders.insert(0, 0);
+        borders.push(FLAKE_MAX.i);
+
+        // the vacancy lists are split at the borders from the right
+        let mut domains: Vec<Domain> = borders.windows(2).map(|border| Domain{
+            i_start: border[0],
+            i_end: border[1],
+            vacancies: Vec::with_capacity(VAC_LISTS),
+            surface: BTreeSet::new(),
+            added: Vec::new(),
+            counted: 0,
+            handed_over: Vec::new(),
+            hidden: Vec::new(),
+        }).collect();
+        for list in self.vacancies.list.iter_mut() {
+            for domain in domains.iter_mut().rev() {
+                domain.vacancies.push(list.split_off(&IJK{i: domain.i_start, j: 0, k: 0}));
+            }
+        }
+        for domain in domains.iter_mut().rev() {
+            domain.surface = self.surface.list.split_off(&IJK{i: domain.i_start, j: 0, k: 0});
+        }
+        Some(domains)
+    }
+
+    /// Let all domains of one color grow at the same time and hand over the vacancies changed at their borders.
+    fn grow_domains(&mut self, domains: &mut [Domain], color: usize, time: f64, budget: &AtomicUsize) {
+        let ranges: Vec<(u16, u16)> = domains.iter().skip(color).step_by(2)
+            .map(|domain| (domain.i_start.saturating_sub(1), (domain.i_end + 1).min(FLAKE_MAX.i)))
+            .collect();
+        let parts = self.bulk.parts(&mut self.coordination, &ranges);
+        let lattice = &self.lattice;
+        let prob_list = &self.prob_list;
+        let substrate_pos = self.substrate_pos;
+        let threads = self.threads;
+        let queue = Mutex::new(domains.iter_mut().skip(color).step_by(2).zip(parts).collect::<Vec<_>>());
+        thread::scope(|scope| {
+            for _ in 0..threads {
+                scope.spawn(|| loop {
+                    let next = queue.lock().expect("Domain queue poisoned").pop();
+                    match next {
+                        Some((domain, mut part)) => domain.grow(&mut part, lattice, prob_list, substrate_pos, time, budget),
+                        None => break
+                    }
+                });
+            }
+        });
+
+        // the numbers of atoms and the vacancies and hidden atoms of the neighboring domains
+        for index in (color..domains.len()).step_by(2) {
+            self.bulk.count_gold(&domains[index].added[domains[index].counted..]);
+            domains[index].counted = domains[index].added.len();
+            let handed_over = std::mem::take(&mut domains[index].handed_over);
+            for ijk in handed_over {
+                let coordination = self.coordination.get(ijk);
+                if let Some(owner) = domains.iter_mut().find(|domain| domain.contains(ijk)) {
+                    owner.update_vacancy(ijk, coordination);
+                }
+            }
+            let hidden = std::mem::take(&mut domains[index].hidden);
+            for ijk in hidden {
+                if let Some(owner) = domains.iter_mut().find(|domain| domain.contains(ijk)) {
+                    owner.surface.remove(&ijk);
+                }
+            }
+        }
+    }
+
+    /// Put the vacancies and the surface back together and update the extrema.
+    fn merge_domains(&mut self, mut domains: Vec<Domain>) {
+        for (index, list) in self.vacancies.list.iter_mut().enumerate() {
+            *list = domains.iter_mut().flat_map(|domain| std::mem::take(&mut domain.vacancies[index])).collect();
+        }
+        self.surface.list = domains.iter_mut().flat_map(|domain| std::mem::take(&mut domain.surface)).collect();
+        for domain in domains {
+            for ijk in domain.added {
+                self.update_extrema(ijk);
+            }
+        }
+    }
+}
@@ -3,6 +3,7 @@ Compile time parameters
 */
 
 use crate::helpers::*;
+use crate::stop_conditions::*;
 
 // Maximal (static) size of the flake 
 pub const BITS: u16 = 2;
@@ -16,9 +17,25 @@ pub const FLAKE_MAX: IJK = IJK{i:6000, j:6000, k:300};          // this uses 2.7
 #[cfg(target_arch = "wasm32")]
 pub const FLAKE_MAX: IJK = IJK{i:2000, j:2000, k:300};          // wasm is 32bit so this is the limit
 
+// Cached coordination numbers with 4 (or 8) bits per position -- this triples (or quintuples) the memory of the bulk, so by default they are
+// only used for the parallel growth (more than one thread), which needs them, and otherwise computed
+pub const COORDINATION: bool = false;
+pub const COORD_BITS: u16 = 4;
+pub const COORD_DIV: u16 = 8 / COORD_BITS;
+
+// Bulk in a memory-mapped file instead of the (virtual) memory, e.g. Some("flake.bulk") -- the OS pages it out to the disk, so flakes larger than the RAM
+// can be grown, and the file stays as a snapshot which can be reopened (`--open` in the headless mode) -- wasm always keeps the bulk in memory
+pub const BULK_FILE: Option<&str> = None;
+
 // and resulting center location of the flake -- Don't change!
 pub const CENTER: IJK = IJK{i: FLAKE_MAX.i/2, j: FLAKE_MAX.j/2, k: FLAKE_MAX.k/2};
 
+// move the flake back to the center when it reaches the boundary of the bulk (otherwise it stops growing in this direction, which is reported and flagged in the statistics)
+pub const RECENTER: bool = false;
+
+// coarse layer model (height_map module): walls along i and j -- the layers are stored as outlines, so 60000 means 17x17um^2 without any memory for it (65000 at most)
+pub const SOS_MAX: u16 = 60000;
+
 // Stacking faults arrangement -- Shouldn't be larger than FLAKE.MAX.k
 // pub const STACKING_FAULTS: [u16; 0] = [];
 // pub const STACKING_FAULTS: [u16; 1] = [CENTER.k];
@@ -40,6 +57,7 @@ pub const PROB_LIST: [[u64; VAC_LISTS]; 4] = [[0, 0, 1, 1_000, 100_000, 1_000_00
 // and the colors
 pub const GOLD: Color =  Color(1.6,1.3,0.0);
 pub const DIRT: Color =  Color(0.8,1.0,0.3);
+pub const ALLOY_COLOR: Color = Color(1.2,1.2,1.3);
 pub const ATOM_COLORS: [Color; 3] = [Color(1.0,1.0,0.0), Color(1.0,0.8,0.3), Color(0.8,0.8,0.8)];
 pub const LAYER_COLORS: [Color; 3] = [Color(0.8,0.8,0.0), Color(0.8,0.6,0.2), Color(0.6,0.6,0.6)];
 pub const VAC_COLORS: [Color; 9] = [Color(0.4,1.0,0.4), Color(0.4,0.4,1.0), Color(1.0,0.8,0.8), Color(1.2,0.6,0.6), Color(1.4,0.4,0.4), Color(1.6,0.2,0.2), Color(2.0,0.0,0.0), Color(4.0,0.0,0.0), Color(8.0,0.0,0.0)];
@@ -47,6 +65,100 @@ pub const VAC_COLORS: [Color; 9] = [Color(0.4,1.0,0.4), Color(0.4,0.4,1.0), Colo
 // Diameter of a gold atom in a fcc lattice in nm
 pub const DIAMETER: f32 = 0.40782;             
 
+// focused ion beam milling: spot size (FWHM in nm), dose (ions per nm^2), 
+// sputter yield of a terrace atom (9 neighbors) per ion and probability of a sputtered atom to be redeposited
+pub const SPOT_SIZE: f32 = 1.0;
+pub const DOSE: f32 = 20.0;
+pub const SPUTTER_YIELD: f32 = 0.3;
+pub const REDEPOSITION: f32 = 0.05;
+
+// annealing: effective broken-bond energy (in eV), temperature (in K) and number of sweeps
+pub const BOND_ENERGY: f32 = 0.3;
+pub const ANNEAL_TEMPERATURE: f32 = 600.0;
+pub const ANNEAL_SWEEPS: usize = 20;
+
+// Wulff construction: relative facet energies of {111}, {100} and {110} and adhesion energy to a substrate (None = free particle)
+pub const WULFF_ENERGIES: [f32; 3] = [1.0, 1.1, 1.2];
+pub const WULFF_ADHESION: Option<f32> = None;
+
+// multiple seeds: number of seeds, radius (in nm) of the region around the center they are placed in,
+// and whether each particle gets its own random stacking sequence or all share the one of the crystal
+// (the statistics only start from the seeds with SEEDS_IN_STATISTICS, otherwise from a single atom)
+pub const SEEDS: usize = 5;
+pub const SEED_RADIUS: f32 = 30.0;
+pub const INDEPENDENT_STACKINGS: bool = true;
+pub const SEEDS_IN_STATISTICS: bool = false;
+
+// finite precursor supply: on/off at start, number and size (in nm) of the cells of the concentration grid around the flake,
+// precursor atoms in a cell at bulk concentration, Jacobi sweeps after every SUPPLY_INTERVAL attempts (i.e. the diffusivity)
+// and the number of attempts in a row without an added atom after which the growth stops
+pub const SUPPLY: bool = false;
+pub const SUPPLY_CELLS: [usize; 3] = [64, 64, 32];
+pub const SUPPLY_CELL_SIZE: f32 = 5.0;
+pub const SUPPLY_ATOMS_PER_CELL: f32 = 500.0;
+pub const SUPPLY_INTERVAL: usize = 10_000;
+pub const SUPPLY_SWEEPS: usize = 4;
+pub const SUPPLY_MAX_FAILS: usize = 1_000_000;
+
+// diffusion-limited aggregation: random walkers instead of the vacancy lists at start, sticking probability for 1..12 gold neighbors
+// (all 1.0 results in fractal clusters, small values for low coordinations in compact flakes), distance (in nm) of the release sphere to the crystal
+// and number of steps (or release attempts) after which a walker which never sticks gives up and the walker growth stops
+pub const WALKERS: bool = false;
+pub const STICKING: [f32; 12] = [1.0; 12];
+pub const WALKER_MARGIN: f32 = 5.0;
+pub const WALKER_MAX_STEPS: usize = 100_000_000;
+
+// Ostwald ripening: temperature (in K), number of sweeps and the number of neighbors up to which atoms always detach (the bond energy is BOND_ENERGY)
+pub const RIPENING_TEMPERATURE: f32 = 900.0;
+pub const RIPENING_SWEEPS: usize = 20;
+pub const RIPENING_REFERENCE: f32 = 3.0;
+
+// directional deposition: on/off at start, tilt from the surface normal, azimuth from the x-axis and angular spread of the flux (all in degree)
+pub const FLUX: bool = false;
+pub const FLUX_TILT: f32 = 80.0;
+pub const FLUX_AZIMUTH: f32 = 0.0;
+pub const FLUX_SPREAD: f32 = 5.0;
+// vacancy lists up to this length are checked for visibility completely before picking a vacancy, the visibility of a vacancy is reused
+// for directions within the same bin (in degree) for FLUX_REFRESH added atoms (except next to a new atom), after FLUX_ATTEMPTS shadowed picks
+// any visible vacancy is taken, and the growth stops when FLUX_MISSES arriving atoms in a row find no visible vacancy at all
+pub const FLUX_DIRECT: usize = 64;
+pub const FLUX_BIN: f32 = 1.0;
+pub const FLUX_REFRESH: usize = 1000;
+pub const FLUX_ATTEMPTS: usize = 1_000;
+pub const FLUX_MISSES: usize = 100;
+
+// monolayer growth on a substrate: probabilities of the vacancy classes of the monolayer -- for 1 to 6 in-plane neighbors each without/with
+// a pair of neighbors closing a triangle over a substrate atom (B step), i.e. unequal pairs favour one type of steps and give triangular islands
+pub const MONOLAYER_CLASSES: usize = 12;
+pub const MONOLAYER_PROBABILITIES: [u64; MONOLAYER_CLASSES] = [1, 0, 100, 10, 10_000, 1_000, 1_000_000, 1_000_000, 100_000_000, 100_000_000, 10_000_000_000, 10_000_000_000];
+
+// alloy growth: on/off at start, probability list of the second species, relative bond weights A-A, A-B and B-B,
+// fraction of the second species in the feed at a given number of grown atoms (linearly interpolated) and the shell width (in nm) of the composition profile,
+// the growth stops after ALLOY_ATTEMPTS rejected atoms in a row
+pub const ALLOY: bool = false;
+pub const ALLOY_PROB_LIST: [u64; VAC_LISTS] = [0, 0, 1, 1_000, 1_000_000, 1_000_000_000, 1_000_000_000, 1_000_000_000, 1_000_000_000];
+pub const ALLOY_BONDS: [f32; 3] = [1.0, 0.8, 0.6];
+pub const ALLOY_FEED: [(usize, f32); 3] = [(0, 0.0), (10_000, 0.5), (100_000, 0.2)];
+pub const ALLOY_ATTEMPTS: usize = 100_000;
+pub const SHELL_WIDTH: f32 = 1.0;
+
+// impurities: on/off at start, probability that dirt attaches instead of gold for every vacancy list and probability that a dirt atom desorbs per event
+pub const IMPURITIES: bool = false;
+pub const IMPURITY_PROBABILITIES: [f32; VAC_LISTS] = [0.0, 0.0, 0.01, 0.1, 0.01, 0.0, 0.0, 0.0, 0.0];
+pub const DESORPTION: f32 = 0.001;
+
+// screw dislocation: Burgers vector in layers, i.e. the height of the spiral steps (see Screw for its in-plane component)
+pub const SCREW_BURGERS: i16 = 3;
+
+// thin films: lateral period (in atoms along i and j) of the periodic cell
+pub const FILM_PERIOD: u16 = 200;
+
+// folder of the scene files which can be loaded one after another
+pub const SCENE_DIR: &str = "scenes";
+
+// growth protocol executed via the GUI
+pub const PROTOCOL_FILE: &str = "protocols/staged_growth.toml";
+
 // statistics mode
 pub const FILENAME: &str = "flake";
 pub const NUMBER_OF_CYCLES: usize = 3;
@@ -54,6 +166,24 @@ pub const STOP_MARKS: [usize; 5] = [100, 1_000, 10_000, 100_000, 1_000_000];
 // pub const STOP_MARKS: [usize; 10] = [100, 300, 1_000, 3_000, 10_000, 30_000, 100_000, 300_000, 1_000_000, 3_000_000];
 // pub const STOP_MARKS: [usize; 21] = [100, 180, 320, 560, 1_000, 1_800, 3_200, 5_600, 10_000, 18_000, 32_000, 56_000, 100_000, 180_000, 320_000, 560_000, 1_000_000, 1_800_000, 3_200_000, 5_600_000, 10_000_000];
 // pub const STOP_MARKS: [usize; 51] = [100, 130, 160, 200, 250, 320, 400, 500, 630, 790, 1_000, 1_300, 1_600, 2_000, 2_500, 3_200, 4_000, 5_000, 6_300, 7_900, 10_000, 13_000, 16_000, 20_000, 25_000, 32_000, 40_000, 50_000, 63_000, 79_000, 100_000, 130_000, 160_000, 200_000, 250_000, 320_000, 400_000, 500_000, 630_000, 790_000, 1_000_000, 1_300_000, 1_600_000, 2_000_000, 2_500_000, 3_200_000, 4_000_000, 5_000_000, 6_300_000, 7_900_000, 10_000_000];
+// geometric stop marks used instead of the STOP_MARKS if given, e.g. [StopCondition::Width(100.0), StopCondition::Width(300.0), StopCondition::Boundary(10)]
+pub const STOP_CONDITIONS: [StopCondition; 0] = [];
+// target of the interactive growth, the number of checks per growth (relative to the number of atoms) and the maximal number of atoms per target
+pub const STOP_TARGET: StopCondition = StopCondition::Width(500.0);
+pub const STOP_RESOLUTION: usize = 100;
+pub const STOP_MAX_ATOMS: usize = 100_000_000;
+
+// number of atoms after which a background job reports its progress and checks for cancellation
+pub const PROGRESS_INTERVAL: usize = 10_000;
+
+// parallel growth: number of threads (1 for the serial growth, 0 for all cores), minimal number of atoms per call, minimal width (in i) of the domains
+// (narrower ones change the shape of the flakes), new domains whenever the flake has grown by 1/PARALLEL_RESOLUTION and the synchronisation cycles in between
+// (less cycles are faster but change the shape of the flakes, too) -- wasm always grows serially
+pub const PARALLEL_THREADS: usize = 1;
+pub const PARALLEL_MIN_ATOMS: usize = 100_000;
+pub const PARALLEL_MIN_WIDTH: u16 = 50;
+pub const PARALLEL_RESOLUTION: usize = 20;
+pub const PARALLEL_CYCLES: usize = 100;
 
 // window parameter
 #[cfg(feature = "sidebar")]
@@ -0,0 +1,261 @@
+/*!
+Several seeds growing in one simulation box
+
+All particles share the bulk and the vacancy lists, so they compete for the same atoms. Each atom is attributed to the particle it grew on
+and a vacancy to the particle of a neighboring atom (only the atoms are stored, so the attribution takes no memory for the vacancies).
+A particle can either use the stacking of the crystal or its own stacking sequence -- in the latter case all neighbors of its atoms
+(and vacancies) are determined via its own lattice.
+
+When an atom touches atoms of another particle for the first time, a coalescence event is recorded. If the stacking of both particles does not match
+in this layer, the position is additionally counted as part of a grain boundary.
+*/
+
+use std::collections::{HashMap, BTreeSet};
+use std::hash::BuildHasherDefault;
+use rand::Rng;
+
+use crate::helpers::*;
+use crate::parameters::*;
+use crate::lattice::*;
+use crate::storage::*;
+use crate::crystal::*;
+
+#[cfg(target_arch = "wasm32")]
+use crate::println;
+
+
+/// Starting point of a particle -- without stacking faults the particle uses the stacking of the crystal.
+#[derive(Clone,Debug)]
+pub struct Seed {
+    pub ijk: IJK,
+    pub stacking_faults: Option<Vec<u16>>,
+}
+
+/// The first contact of two particles.
+#[derive(Copy,Clone,Debug)]
+pub struct Coalescence {
+    pub atoms: usize,
+    pub particles: (u16, u16),
+    pub position: IJK,
+    pub grain_boundary: bool,
+}
+
+#[derive(Clone)]
+pub struct Particles {
+    pub ids: HashMap<IJK, u16, BuildHasherDefault<PositionHasher>>,              // the particles of the atoms
+    pub lattices: Vec<Option<Lattice>>,
+    pub sizes: Vec<usize>,
+    pub coalescence: Vec<Coalescence>,
+    pub grain_boundaries: BTreeSet<IJK>,
+    pub independent: bool,
+}
+
+impl Particles {
+    pub fn new() -> Self {
+        Particles{
+            ids:                HashMap::default(),
+            lattices:           Vec::new(),
+            sizes:              Vec::new(),
+            coalescence:        Vec::new(),
+            grain_boundaries:   BTreeSet::new(),
+            independent:        false
+        }
+    }
+
+    /// The attribution is only needed for more than one seed.
+    pub fn active(&self) -> bool {
+        self.sizes.len() > 1
+    }
+
+    /// Check if two particles already touched each other.
+    pub fn merged(&self, a: u16, b: u16) -> bool {
+        let pair = (a.min(b), a.max(b));
+        self.coalescence.iter().any(|event| event.particles == pair)
+    }
+
+    /// Book a removed atom off its particle -- as a vacancy the position belongs to the particle of a neighbor again.
+    pub fn detach(&mut self, ijk: IJK) {
+        if let Some(id) = self.ids.remove(&ijk) {
+            self.sizes[id as usize] -= 1;
+        }
+    }
+
+    /// The particle of a position: stored for an atom, the one of the first neighboring atom for a vacancy
+    /// (with the neighbors in the lattice of that particle, the crystal's otherwise).
+    pub fn id(&self, ijk: IJK, lattice: &Lattice) -> Option<u16> {
+        if let Some(&id) = self.ids.get(&ijk) { return Some(id) }
+        if !self.independent {
+            return (0..12).find_map(|l| self.ids.get(&lattice.next_neighbor(ijk, l)).cloned())
+        }
+        (0..12).find_map(|l| {
+            self.lattices.iter().enumerate().find_map(|(id, own)| {
+                let nn_ijk = own.as_ref().unwrap_or(lattice).next_neighbor(ijk, l);
+                self.ids.get(&nn_ijk).filter(|&&other| other as usize == id).cloned()
+            })
+        })
+    }
+
+    /// Print the sizes of all particles and all coalescence events.
+    pub fn report(&self) {
+        println!("Particles (atoms): {:?}", self.sizes);
+        for event in self.coalescence.iter() {
+            println!("{:>15} atoms -- particles {} and {} coalesced at {:?}{}", event.atoms, event.particles.0, event.particles.1, event.position,
+                     if event.grain_boundary { " forming a grain boundary" } else { "" });
+        }
+        println!("Grain boundary positions: {}", self.grain_boundaries.len());
+    }
+}
+
+
+/// Random seeds at distinct positions in the central layer within the given radius (in nm). With independent stackings every seed gets
+/// its own sequence with zero, one or two random stacking faults near the center -- zero faults (`Some(vec![])`) is a perfect fcc particle,
+/// while `None` means the particle uses the stacking of the crystal. If the radius does not leave room for all seeds, fewer are returned.
+pub fn random_seeds(number: usize, radius: f32, independent: bool, lattice: &Lattice) -> Vec<Seed> {
+    let mut rng = rand::thread_rng();
+    let center_z = lattice.get_xyz(CENTER).z;
+    let mut seeds: Vec<Seed> = Vec::with_capacity(number);
+    for _attempt in 0..100*number {
+        if seeds.len() == number { break }
+        let r = radius*rng.gen::<f32>().sqrt();
+        let phi = 2.0*std::f32::consts::PI*rng.gen::<f32>();
+        let ijk = lattice.get_ijk(XYZ{x: r*phi.cos(), y: r*phi.sin(), z: center_z});
+        if seeds.iter().any(|seed| seed.ijk == ijk) { continue }
+        let stacking_faults = if independent {
+            let mut faults: Vec<u16> = (0..rng.gen_range(0, 3)).map(|_| CENTER.k - 3 + rng.gen_range(0, 7)).collect();
+            faults.sort();
+            faults.dedup();
+            Some(faults)
+        }
+        else {
+            None
+        };
+        seeds.push(Seed{ ijk, stacking_faults });
+    }
+    if seeds.len() < number {
+        println!("Only {} of {} seeds fit into a radius of {} nm", seeds.len(), number, radius);
+    }
+    seeds
+}
+
+impl Crystal {
+    /// Start a new crystal from several seeds.
+    pub fn add_seeds(&mut self, seeds: &[Seed]) {
+        self.clear();
+        self.particles.independent = seeds.iter().any(|seed| seed.stacking_faults.is_some());
+        for seed in seeds.iter() {
+            self.particles.lattices.push(seed.stacking_faults.as_ref().map(|faults| Lattice::new(faults.clone(), DIAMETER)));
+            self.particles.sizes.push(0);
+        }
+        for (id, seed) in seeds.iter().enumerate() {
+            self.particles.ids.insert(seed.ijk, id as u16);
+            self.add_atom(seed.ijk);
+        }
+    }
+
+    /// Attribute a freshly added atom to a particle and look for contacts to other particles.
+    pub fn attribute(&mut self, ijk: IJK) {
+        // a position without any attribution (e.g. a single added atom without neighbors) belongs to the first particle
+        let id = self.particles.id(ijk, &self.lattice).unwrap_or(0);
+        self.particles.ids.insert(ijk, id);
+        self.particles.sizes[id as usize] += 1;
+
+        for l in 0..12 {
+            let nn_ijk = self.neighbor(ijk, l);
+            if self.bulk.is_metal(nn_ijk) {
+                // contact to another particle
+                if let Some(&other) = self.particles.ids.get(&nn_ijk) {
+                    if other != id {
+                        let grain_boundary = self.stacking_pos(id, ijk.k) != self.stacking_pos(other, ijk.k);
+                        if grain_boundary {
+                            self.particles.grain_boundaries.insert(ijk);
+                        }
+                        if !self.particles.merged(id, other) {
+                            let event = Coalescence{ atoms: self.bulk.number_of_gold, particles: (id.min(other), id.max(other)), position: ijk, grain_boundary };
+                            println!("{:>15} atoms -- particles {} and {} coalesced", event.atoms, event.particles.0, event.particles.1);
+                            self.particles.coalescence.push(event);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Height, width, depth and aspect ratio of every particle -- the per-particle version of get_size.
+    pub fn particle_sizes(&self) -> Vec<[f32;4]> {
+        let mut extrema: Vec<Option<Extrema>> = vec![None; self.particles.sizes.len()];
+        for &ijk in self.surface.list.iter() {
+            if let Some(&id) = self.particles.ids.get(&ijk) {
+                let xyz = self.lattice_at(ijk).get_xyz(ijk);
+                let e = extrema[id as usize].get_or_insert(Extrema{x_min: xyz.x, x_max: xyz.x, y_min: xyz.y, y_max: xyz.y, z_min: xyz.z, z_max: xyz.z});
+                e.x_min = e.x_min.min(xyz.x);
+                e.x_max = e.x_max.max(xyz.x);
+                e.y_min = e.y_min.min(xyz.y);
+                e.y_max = e.y_max.max(xyz.y);
+                e.z_min = e.z_min.min(xyz.z);
+                e.z_max = e.z_max.max(xyz.z);
+            }
+        }
+        extrema.iter().map(|e| e.map_or([0.0; 4], |e| e.size())).collect()
+    }
+
+    /// The lattice used for the neighbors of a position.
+    pub fn lattice_at(&self, ijk: IJK) -> &Lattice {
+        if self.particles.independent {
+            if let Some(id) = self.particles.id(ijk, &self.lattice) {
+                if let Some(lattice) = &self.particles.lattices[id as usize] {
+                    return lattice
+                }
+            }
+        }
+        &self.lattice
+    }
+
+    /// Stacking position (A, B or C) of a layer for a given particle.
+    fn stacking_pos(&self, id: u16, k: u16) -> i16 {
+        let lattice = self.particles.lattices[id as usize].as_ref().unwrap_or(&self.lattice);
+        lattice.stacking.pos[k as usize].rem_euclid(3)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stop_conditions::*;
+
+    #[test]
+    fn random_seeds_are_distinct() {
+        let lattice = Lattice::new(STACKING_FAULTS.to_vec(), DIAMETER);
+        let seeds = random_seeds(20, 5.0, true, &lattice);
+        assert_eq!(seeds.len(), 20);
+        for (index, seed) in seeds.iter().enumerate() {
+            assert!(seeds[..index].iter().all(|other| other.ijk != seed.ijk));
+            let faults = seed.stacking_faults.as_ref().unwrap();
+            assert!(faults.len() <= 2);
+            assert!(faults.iter().all(|k| (CENTER.k - 3..=CENTER.k + 3).contains(k)));
+        }
+        assert!(random_seeds(3, 1.0, false, &lattice).iter().all(|seed| seed.stacking_faults.is_none()));
+
+        // a radius of zero only leaves room for one seed
+        assert_eq!(random_seeds(3, 0.0, false, &lattice).len(), 1);
+    }
+
+    #[test]
+    fn only_atoms_are_attributed() {
+        let mut flake = Crystal::new(Lattice::new(STACKING_FAULTS.to_vec(), DIAMETER));
+        let seeds = vec![
+            Seed{ ijk: CENTER, stacking_faults: None },
+            Seed{ ijk: IJK{ i: CENTER.i + 10, ..CENTER }, stacking_faults: Some(vec![CENTER.k + 1]) },
+        ];
+        flake.add_seeds(&seeds);
+        flake.grow_until(&StopCondition::Atoms(500));
+        assert_eq!(flake.particles.ids.len(), flake.bulk.number_of_atoms);
+        assert_eq!(flake.particles.sizes.iter().sum::<usize>(), flake.bulk.number_of_atoms);
+
+        // a removed atom leaves a vacancy of its particle behind
+        let ijk = *flake.surface.list.iter().find(|&&ijk| flake.particles.ids.get(&ijk) == Some(&1)).unwrap();
+        assert!(flake.remove_atom(ijk));
+        assert!(!flake.particles.ids.contains_key(&ijk));
+        assert_eq!(flake.particles.ids.len(), flake.bulk.number_of_atoms);
+    }
+}
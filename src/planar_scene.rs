@@ -18,6 +18,10 @@ use nalgebra::{Point2, Point3};
 
 use crate::parameters::*;
 use crate::crystal::*;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::job::*;
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::atomic::Ordering;
 
 
 const INDIX: [f32; 6] = [40.0, 40.0, 40.0, 420.0, 50.0, 40.0];                              // indicator pos x
@@ -29,6 +33,8 @@ const INDI_LENGTH: f32 = 150.0;
 pub struct PlanarScene {
     pub scene: PlanarSceneNode,
     pub layers: PlanarSceneNode,
+    pub monolayer: PlanarSceneNode,
+    monolayer_atoms: usize,
     pub help: PlanarSceneNode,
     pub show_help: bool,
     pub indicators: Vec<PlanarSceneNode>,
@@ -50,6 +56,8 @@ impl PlanarScene {
         PlanarScene{ 
             scene, 
             layers:         window.add_planar_group(), 
+            monolayer:      window.add_planar_group(),
+            monolayer_atoms: 0,
             help:           window.add_planar_group(), 
             show_help:      false,
             indicators, 
@@ -119,6 +127,32 @@ impl PlanarScene {
         window.draw_text(&ratio[..], &Point2::new(x_pos, (2*window.height()-80) as f32), 50.0, &self.font, &Point3::new(0.0, 0.0, 0.0));
     }       
 
+    /// Progress, speed and remaining time of a running job -- drawn instead of the atom numbers.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn draw_job(&mut self, window: &mut Window, job: &Job) {
+        let done = job.progress.done.load(Ordering::Relaxed);
+        let total = job.progress.total.load(Ordering::Relaxed);
+        let title = match job.kind {
+            JobKind::Growth => "Growing...",
+            JobKind::Statistics => "Statistics...",
+        };
+        let progress = if total > 0 {
+            format!("{} atoms ({:.0}%)", done.separated_string(), 100.0*done as f32/total as f32)
+        }
+        else {
+            format!("{} atoms", done.separated_string())
+        };
+        let rate = format!("{} atoms/s", (job.rate() as usize).separated_string());
+        let eta = match job.eta() {
+            Some(eta) => format!("ETA: {:#?}", Duration::from_secs(eta.as_secs())),
+            None => format!("ETA: unknown"),
+        };
+        let cancel = if job.progress.cancelled.load(Ordering::Relaxed) { "Cancelling..." } else { "Cancel with ←−−" };
+        for (index, text) in [title, &progress[..], &rate[..], &eta[..], cancel].iter().enumerate() {
+            window.draw_text(text, &Point2::new(INDIX[0], INDIY[0] + (index as f32)*INDI_STEP[0]), 50.0, &self.font, &Point3::new(0.0, 0.0, 0.0));
+        }
+    }
+
     pub fn draw_key(&mut self, window: &mut Window, key: &str, rel_x: f32, rel_y: f32) {  
         self.draw_wide_key(window, key, rel_x, rel_y, 50.0);
     }
@@ -288,6 +322,36 @@ impl PlanarScene {
         self.draw_header(window, "Special Keys", x - dt, y + 2.0*dy);
         self.draw_text(window, "Probabilities", x - dt, y);
         self.draw_key(window, " P", x, y);
+        self.draw_text(window, "Mill Gap", x - dt, y - 3.0*dy);
+        self.draw_key(window, " N", x, y - 3.0*dy);
+        self.draw_text(window, "Anneal", x - dt, y - 5.0*dy);
+        self.draw_wide_key(window, "Tab", x + 1.0, y - 5.0*dy, 80.0);
+        self.draw_text(window, "Wulff Shape", x - dt, y - 6.0*dy);
+        self.draw_wide_key(window, "Ins", x + 1.0, y - 6.0*dy, 80.0);
+        self.draw_text(window, "Multi Seeds", x - dt, y - 7.0*dy);
+        self.draw_wide_key(window, "F10", x + 1.0, y - 7.0*dy, 80.0);
+        self.draw_text(window, "Supply on/off", x - dt, y - 8.0*dy);
+        self.draw_wide_key(window, "F11", x + 1.0, y - 8.0*dy, 80.0);
+        self.draw_text(window, "Walkers on/off", x - dt, y - 9.0*dy);
+        self.draw_wide_key(window, "F12", x + 1.0, y - 9.0*dy, 80.0);
+        self.draw_text(window, "Ripening", x - dt, y - 10.0*dy);
+        self.draw_key(window, " →", x, y - 10.0*dy);
+        self.draw_text(window, "Flux on/off", x - dt, y - 11.0*dy);
+        self.draw_key(window, " ←", x, y - 11.0*dy);
+        self.draw_text(window, "Alloy on/off", x - dt, y - 12.0*dy);
+        self.draw_key(window, " =", x, y - 12.0*dy);
+        self.draw_text(window, "Impurities", x - dt, y - 13.0*dy);
+        self.draw_key(window, " /", x, y - 13.0*dy);
+        self.draw_text(window, "Screw Disloc.", x - dt, y - 14.0*dy);
+        self.draw_key(window, " 0", x, y - 14.0*dy);
+        self.draw_text(window, "Protocol", x - dt, y - 15.0*dy);
+        self.draw_key(window, " \\", x, y - 15.0*dy);
+        self.draw_text(window, "Grow to Target", x - dt, y - 16.0*dy);
+        self.draw_key(window, " ]", x, y - 16.0*dy);
+        self.draw_text(window, "Periodic Film", x - dt, y - 17.0*dy);
+        self.draw_key(window, " [", x, y - 17.0*dy);
+        self.draw_text(window, "Monolayer", x - dt, y - 18.0*dy);
+        self.draw_key(window, " '", x, y - 18.0*dy);
         
 
         #[cfg(target_arch = "wasm32")]
@@ -304,6 +368,8 @@ impl PlanarScene {
         self.draw_key(window, "  ;", x, y - dy);
         self.draw_text(window, "Save Flake", x - dt, y - 2.0*dy);
         self.draw_key(window, " M", x, y - 2.0*dy);
+        self.draw_text(window, "Load Scene", x - dt, y - 4.0*dy);
+        self.draw_key(window, " Z", x, y - 4.0*dy);
         }
 
         // Space
@@ -365,4 +431,37 @@ impl PlanarScene {
             outline.set_color(0.6, 0.8, 1.1);
         }
     }
+    /// Top view of a monolayer island: the sites around it, blue at A steps and red at B steps -- only redrawn when the number of atoms has changed.
+    pub fn update_monolayer(&mut self, window: &mut Window, flake: &Crystal) {
+        let atoms = if flake.monolayer.is_some() { flake.bulk.number_of_atoms } else { 0 };
+        if atoms == self.monolayer_atoms { return }
+        self.monolayer_atoms = atoms;
+        window.remove_planar_node(&mut self.monolayer);
+        self.monolayer = window.add_planar_group();
+        self.monolayer.set_visible(self.show);
+        let monolayer = match &flake.monolayer {
+            Some(monolayer) => monolayer,
+            None => return,
+        };
+
+        // a square in the lower left corner, the island scaled to fit into it
+        let size = (window.height() as f32)/3.0;
+        let pos_x = -(window.width() as f32)/2.0 + size/2.0 + INDIX[0];
+        let pos_y = -(window.height() as f32)/2.0 + size/2.0 + 2.5*INDIY[0];
+        let mut frame = self.monolayer.add_rectangle(size, size);
+        frame.append_translation(&Translation2::new(pos_x, pos_y));
+        frame.set_color(0.9, 0.9, 0.9);
+        let e = &flake.extrema;
+        let scale = 0.9*size/((e.x_max - e.x_min).max(e.y_max - e.y_min) + DIAMETER);
+        let (center_x, center_y) = ((e.x_max + e.x_min)/2.0, (e.y_max + e.y_min)/2.0);
+        let radius = (0.5*DIAMETER*scale).max(1.0);
+        for (class, sites) in monolayer.sites.iter().enumerate() {
+            for &ijk in sites.iter() {
+                let xyz = flake.lattice.get_xyz(ijk);
+                let mut site = self.monolayer.add_circle(radius);
+                site.append_translation(&Translation2::new(pos_x + (xyz.x - center_x)*scale, pos_y + (xyz.y - center_y)*scale));
+                if class%2 == 1 { site.set_color(0.8, 0.0, 0.0) } else { site.set_color(0.0, 0.0, 0.8) }
+            }
+        }
+    }
 }
\ No newline at end of file
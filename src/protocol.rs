@@ -0,0 +1,202 @@
+/*!
+Growth protocols with staged conditions
+
+A protocol file is a small TOML file listing the stages of a synthesis, which are executed one after another on the current crystal.
+After every stage the number of atoms, the size and the number of dirt atoms are logged. The whole sequence can be repeated several times.
+
+```toml
+repeat = 2
+
+[[stage]]
+type = "grow"
+atoms = 100_000
+
+[[stage]]
+type = "prob_list"      # without an index the next probability list is used
+
+[[stage]]
+type = "grow_until"
+condition = { width = 200.0 }
+
+[[stage]]
+type = "dirt_layer"
+size = 7
+
+[[stage]]
+type = "substrate"
+
+[[stage]]
+type = "stacking_fault"
+
+[[stage]]
+type = "save_positions"     # the positions of all atoms as csv (see Crystal::save)
+```
+*/
+
+use serde::Deserialize;
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::{io::Write, fs::File};
+
+use crate::helpers::*;
+use crate::parameters::*;
+use crate::lattice::*;
+use crate::storage::*;
+use crate::crystal::*;
+use crate::stop_conditions::*;
+
+#[cfg(target_arch = "wasm32")]
+use crate::println;
+
+
+fn layer_size() -> u16 { 7 }
+fn one() -> usize { 1 }
+
+/// All stages a protocol can consist of.
+#[derive(Clone,Debug,Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Stage {
+    Grow            { atoms: usize },
+    GrowUntil       { condition: StopCondition },
+    ProbList        { index: Option<usize> },
+    DirtLayer       { #[serde(default = "layer_size")] size: u16 },
+    Substrate,
+    StackingFault,
+    SavePositions,
+}
+
+/// Description of a whole protocol.
+#[derive(Clone,Debug,Deserialize)]
+pub struct Protocol {
+    #[serde(default = "one")]
+    pub repeat: usize,
+    #[serde(default, rename = "stage")]
+    pub stages: Vec<Stage>,
+}
+
+impl Protocol {
+    /// Parse a protocol from a TOML string.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        toml::from_str(text).map_err(|error| format!("Invalid protocol file: {}", error))
+    }
+
+    /// Load a protocol from a TOML file.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|error| format!("Unable to read {}: {}", path, error))?;
+        Protocol::parse(&text)
+    }
+}
+
+/// State of the crystal at the end of a stage.
+#[derive(Clone,Debug)]
+pub struct StageLog {
+    pub cycle: usize,
+    pub stage: usize,
+    pub description: String,
+    pub atoms: usize,
+    pub dirt: usize,
+    pub prob_list: usize,
+    pub size: [f32;4],
+    pub length_ratio: f32,
+}
+
+
+impl Crystal {
+    /// Execute all stages of a protocol -- returns the state after every stage.
+    pub fn run_protocol(&mut self, protocol: &Protocol) -> Vec<StageLog> {
+        let mut logs = Vec::new();
+        for cycle in 0..protocol.repeat {
+            for (index, stage) in protocol.stages.iter().enumerate() {
+                self.run_stage(stage);
+                let [h,w,d,r] = self.get_size();
+                let log = StageLog{ cycle, stage: index, description: format!("{:?}", stage), atoms: self.bulk.number_of_atoms,
+                                    dirt: self.bulk.number_of_dirt, prob_list: self.prob_list_num, size: [h,w,d,r],
+                                    length_ratio: self.length_ratio() };
+                println!("{}/{} {:>3}: {:<40} {:>12} atoms, {:>8} dirt -- height: {:.2}, width: {:.2}, depth: {:.2}, aspect ratio: {:.2}",
+                         cycle + 1, protocol.repeat, index + 1, log.description, log.atoms, log.dirt, h, w, d, r);
+                logs.push(log);
+            }
+        }
+        logs
+    }
+
+    /// Execute a single stage.
+    pub fn run_stage(&mut self, stage: &Stage) {
+        match stage {
+            Stage::Grow{atoms} => self.random_add(*atoms),
+            Stage::GrowUntil{condition} => { self.grow_until(condition); },
+            Stage::ProbList{index: Some(index)} => self.set_prob_list(*index),
+            Stage::ProbList{index: None} => self.next_prob_list(),
+            Stage::DirtLayer{size} => {
+                let k = self.extrema_ijk.z_max.k + 1;
+                self.add_layer(IJK{i: CENTER.i, j: CENTER.j, k}, *size, Atom::Dirt);
+            },
+            Stage::Substrate => {
+                if self.substrate_pos == 1 {
+                    self.substrate_pos = self.extrema_ijk.z_min.k - 1;
+                    self.update_vacancies();
+                }
+            },
+            Stage::StackingFault => self.add_stacking_fault(self.extrema_ijk.z_max.k + 1),
+            Stage::SavePositions => {
+                #[cfg(not(target_arch = "wasm32"))]
+                self.save();
+            },
+        }
+    }
+
+    /// Add a stacking fault at the given layer -- the vacancies are reconstructed accordingly.
+    pub fn add_stacking_fault(&mut self, k: u16) {
+        let mut stacking = self.lattice.stacking_faults.clone();
+        if let Err(pos) = stacking.binary_search(&k) {
+            stacking.insert(pos, k);
+            self.lattice = self.lattice.with_stacking_faults(stacking);
+            self.update_vacancies();
+        }
+    }
+
+    /// Write the log of a protocol to a file -- one line per stage.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save_protocol(&self, logs: &[StageLog]) {
+        let filename = format!("{}_protocol_atoms{}.csv", FILENAME, self.bulk.number_of_atoms);
+        let mut f = File::create(filename).expect("Unable to create file");
+        writeln!(f, "cycle, stage, description, atoms, dirt, prob_list, height, width, depth, aspect ratio, len ratio").expect("Unable to write in file");
+        for log in logs.iter() {
+            let [h,w,d,r] = log.size;
+            writeln!(f, "{}, {}, \"{}\", {}, {}, {}, {}, {}, {}, {}, {}", log.cycle, log.stage, log.description, log.atoms, log.dirt, log.prob_list + 1, h, w, d, r, log.length_ratio).unwrap();
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_protocol() {
+        let protocol = Protocol::parse(include_str!("../protocols/staged_growth.toml")).unwrap();
+        assert!(!protocol.stages.is_empty());
+        let protocol = Protocol::parse("[[stage]]\ntype = \"grow_until\"\ncondition = { width = 20.0 }\n[[stage]]\ntype = \"dirt_layer\"").unwrap();
+        assert_eq!(protocol.repeat, 1);
+        assert!(matches!(protocol.stages[0], Stage::GrowUntil{condition: StopCondition::Width(width)} if width == 20.0));
+        assert!(matches!(protocol.stages[1], Stage::DirtLayer{size: 7}));
+        assert!(Protocol::parse("[[stage]]\ntype = \"melt\"").is_err());
+        assert!(Protocol::parse("[[stage]]\ntype = \"grow\"").is_err());
+    }
+
+    #[test]
+    fn run_protocol_stages() {
+        let mut flake = Crystal::new(Lattice::new(vec![], DIAMETER));
+        flake.add_atom(CENTER);
+        let protocol = Protocol::parse("repeat = 2\n[[stage]]\ntype = \"grow\"\natoms = 500\n[[stage]]\ntype = \"stacking_fault\"\n[[stage]]\ntype = \"substrate\"").unwrap();
+        let logs = flake.run_protocol(&protocol);
+        assert_eq!(logs.len(), 6);
+        assert_eq!(logs[3].atoms, 1001);
+        // the second fault coincides with the first one if the flake has not grown in height in between
+        assert!(flake.lattice.stacking_faults.contains(&(flake.extrema_ijk.z_max.k + 1)));
+        assert!((1..=2).contains(&flake.lattice.stacking_faults.len()));
+        assert!(flake.substrate_pos > 1);
+    }
+}
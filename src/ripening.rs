@@ -0,0 +1,172 @@
+/*!
+Ostwald ripening between several particles
+
+Surface atoms detach with a probability depending on their number of gold neighbors (broken-bond model)
+and the monomers reattach to any particle via the shared vacancy lists. Since small particles have relatively more low-coordinated atoms,
+they lose atoms while the large particles grow at their expense. The total number of gold atoms (including the monomers) is conserved:
+the monomers which are left after the last sweep condense onto the particles, so no gold is lost between two calls.
+
+The monomers are either kept in a global reservoir, or -- when the finite supply is switched on -- in the concentration field,
+which is then closed to the outside. In the latter case the monomers have to diffuse from one particle to another one.
+
+One sweep consists of as many detachment attempts as there are surface atoms at its beginning, each followed by an attachment attempt.
+*/
+
+use rand::Rng;
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::{io::Write, fs::File};
+
+use crate::helpers::*;
+use crate::parameters::*;
+use crate::storage::*;
+use crate::crystal::*;
+
+#[cfg(target_arch = "wasm32")]
+use crate::println;
+
+
+/// State of all particles after a sweep.
+#[derive(Clone,Debug)]
+pub struct RipeningStep {
+    pub sweep: usize,
+    pub monomers: usize,
+    pub atoms: Vec<usize>,
+    pub sizes: Vec<[f32;4]>,
+}
+
+impl Crystal {
+    /// Ripen the particles at a temperature (in K) for a number of sweeps -- returns the time series of all particle sizes
+    /// and finally the state after the remaining monomers condensed.
+    pub fn ripen(&mut self, temperature: f32, sweeps: usize, bond_energy: f32) -> Vec<RipeningStep> {
+        let mut rng = rand::thread_rng();
+        let k_t = K_B*temperature as f64;
+        let mut monomers: usize = 0;
+        if let Some(field) = &mut self.supply {
+            field.closed = true;
+            field.concentration.fill(0.0);
+            field.deficit = 0.0;
+        }
+        let mut steps = vec![self.ripening_step(0, monomers)];
+
+        for sweep in 1..=sweeps {
+            let candidates: Vec<IJK> = self.surface.list.iter().cloned().collect();
+            for _attempt in 0..candidates.len() {
+
+                // detachment -- atoms with RIPENING_REFERENCE or less neighbors always detach
+                let ijk = candidates[rng.gen_range(0, candidates.len())];
+                if self.bulk.is_metal(ijk) {
+                    let n = self.number_of_neighbors(ijk) as f64;
+                    if rng.gen::<f64>() < (-bond_energy as f64*(n - RIPENING_REFERENCE as f64)/k_t).exp() {
+                        let xyz = self.lattice_at(ijk).get_xyz(ijk);
+                        self.clear_site(ijk);
+                        match &mut self.supply {
+                            Some(field) => field.release(xyz),
+                            None => monomers += 1,
+                        }
+                    }
+                }
+
+                // attachment anywhere via the vacancy lists -- with the field only where there are monomers nearby
+                // (the monomers in a cell are an acceptance probability, which is certain from one monomer on)
+                let vacancy = self.random_vacancy().filter(|&ijk| self.bulk.get(ijk, Atom::Empty));
+                if let Some(ijk) = vacancy {
+                    let xyz = self.lattice_at(ijk).get_xyz(ijk);
+                    let accepted = match &self.supply {
+                        Some(field) => rng.gen::<f32>() < (field.get(xyz)*field.atoms_per_cell).min(1.0),
+                        None => monomers > 0,
+                    };
+                    if accepted && self.add_atom(ijk) {
+                        match &mut self.supply {
+                            Some(field) => field.consume(xyz),
+                            None => monomers -= 1,
+                        }
+                    }
+                }
+            }
+
+            if let Some(field) = &mut self.supply { field.relax() }
+            steps.push(self.ripening_step(sweep, monomers));
+        }
+
+        // the remaining monomers condense, before the field is opened for the growth again
+        let monomers = self.condense(monomers);
+        steps.push(self.ripening_step(sweeps + 1, monomers));
+        if let Some(field) = &mut self.supply {
+            field.closed = false;
+            field.reset();
+        }
+
+        // the extrema only have to be reconstructed once at the end
+        self.recalc_extrema();
+        steps
+    }
+
+    /// Attach the monomers of the reservoir (or the field) at random vacancies -- returns the ones which found no vacancy.
+    fn condense(&mut self, mut monomers: usize) -> usize {
+        if let Some(field) = &self.supply { monomers = field.atoms().round() as usize }
+        let mut failed = 0;
+        while monomers > 0 && failed < monomers {
+            match self.random_vacancy() {
+                Some(ijk) if self.bulk.get(ijk, Atom::Empty) && self.add_atom(ijk) => {
+                    let xyz = self.lattice_at(ijk).get_xyz(ijk);
+                    if let Some(field) = &mut self.supply { field.consume(xyz) }
+                    monomers -= 1;
+                },
+                Some(_) => failed += 1,
+                None => break,
+            }
+        }
+        if monomers > 0 { println!("{} monomers found no vacancy to condense", monomers) }
+        monomers
+    }
+
+    fn ripening_step(&self, sweep: usize, monomers: usize) -> RipeningStep {
+        let monomers = match &self.supply {
+            Some(field) => field.atoms().round() as usize,
+            None => monomers,
+        };
+        RipeningStep{ sweep, monomers, atoms: self.particles.sizes.clone(), sizes: self.particle_sizes() }
+    }
+
+    /// Write the time series of the ripening to a file -- one line per sweep and particle.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save_ripening(&self, steps: &[RipeningStep]) {
+        let filename = format!("{}_ripening_particles{}_T{}.csv", FILENAME, self.particles.sizes.len(), RIPENING_TEMPERATURE);
+        let mut f = File::create(filename).expect("Unable to create file");
+        writeln!(f, "sweep, particle, atoms, height, width, depth, aspect ratio, monomers").expect("Unable to write in file");
+        for step in steps.iter() {
+            for (id, (atoms, [h,w,d,r])) in step.atoms.iter().zip(step.sizes.iter()).enumerate() {
+                writeln!(f, "{}, {}, {}, {}, {}, {}, {}, {}", step.sweep, id, atoms, h, w, d, r, step.monomers).unwrap();
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lattice::*;
+    use crate::particles::*;
+
+    #[test]
+    fn ripening_conserves_gold() {
+        let mut flake = Crystal::new(Lattice::new(STACKING_FAULTS.to_vec(), DIAMETER));
+        let seeds = [Seed{ijk: CENTER, stacking_faults: None}, Seed{ijk: IJK{i: CENTER.i + 30, ..CENTER}, stacking_faults: None}];
+        flake.add_seeds(&seeds);
+        flake.random_add(1000);
+        for supply in [false, true].iter() {
+            if *supply { flake.switch_supply() }
+            let atoms = flake.bulk.number_of_atoms;
+            let steps = flake.ripen(900.0, 2, 0.3);
+            let sweep = &steps[steps.len() - 2];
+            assert_eq!(sweep.atoms.iter().sum::<usize>() + sweep.monomers, atoms);
+
+            // the remaining monomers condense
+            let last = steps.last().unwrap();
+            assert_eq!((last.monomers, flake.bulk.number_of_atoms), (0, atoms));
+            assert_eq!(last.atoms.iter().sum::<usize>(), flake.bulk.number_of_atoms);
+        }
+    }
+}
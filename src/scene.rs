@@ -14,6 +14,7 @@ use nalgebra::{Translation3, Point3, Vector3};
 use crate::helpers::*;
 use crate::parameters::*;
 use crate::lattice::*;
+use crate::storage::*;
 use crate::crystal::*;
 
 // #[cfg(target_arch = "wasm32")]
@@ -75,15 +76,18 @@ impl Scene {
         self.surface = window.add_group();
         if self.visual_layers {
             crystal.surface.list.iter().for_each(|&ijk| {
-                let layer = (self.lattice.stacking.pos[ijk.k as usize]).rem_euclid(3) as usize;     // a better modulo function
-                add_atom_to_group(&mut self.surface, &crystal.lattice.position(ijk), ATOM_COLORS[layer]) 
+                let lattice = crystal.lattice_at(ijk);
+                let layer = (lattice.stacking.pos[ijk.k as usize]).rem_euclid(3) as usize;     // a better modulo function
+                let color = if crystal.bulk.get(ijk, Atom::Alloy) { ALLOY_COLOR } else { ATOM_COLORS[layer] };
+                add_atom_to_group(&mut self.surface, &lattice.position(ijk), color) 
             });
         }
         else {
             crystal.surface.list.iter().for_each(|&ijk| {
                 // let dyn_color =  Color((ijk.i - CENTER.i) as f32 / 10.0, (ijk.j - CENTER.j) as f32 / 10.0, (ijk.k - CENTER.k) as f32 / 10.0);
                 // add_atom_to_group(&mut self.surface, &crystal.lattice.position(ijk), dyn_color) 
-                add_atom_to_group(&mut self.surface, &crystal.lattice.position(ijk), GOLD) 
+                let color = if crystal.bulk.get(ijk, Atom::Alloy) { ALLOY_COLOR } else { GOLD };
+                add_atom_to_group(&mut self.surface, &crystal.lattice_at(ijk).position(ijk), color) 
             });
         }
         if !self.show.surface {
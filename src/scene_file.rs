@@ -0,0 +1,174 @@
+/*!
+Declarative scene files for the starting geometries
+
+A scene file is a small TOML file listing the shapes (boxes, rounded boxes, spheres, cylinders, layers and single atoms) a crystal is built of,
+together with the stacking faults, a substrate and a camera position. Positions and sizes are given in nm, the stacking faults relative to the central layer.
+
+```toml
+camera = [-45.0, 22.5, 0.0]
+stacking_faults = [-2, 2]
+substrate = false
+
+[[shape]]
+type = "rounded_box"
+center = [-15.0, 0.0, -3.0]
+width = 20.0
+depth = 10.0
+height = 6.0
+radius = 3.0
+
+[[shape]]
+type = "box"
+species = "dirt"
+center = [-15.0, 0.0, 0.0]
+width = 10.0
+depth = 12.0
+height = 7.0
+```
+
+The antenna presets of the program are shipped as such files in the scenes folder and are compiled into the binary, so they also work for wasm.
+*/
+
+use serde::Deserialize;
+
+use crate::helpers::*;
+use crate::parameters::*;
+use crate::lattice::*;
+use crate::storage::*;
+use crate::crystal::*;
+
+/// The atom species which can be used in a scene file.
+#[derive(Copy,Clone,Debug,Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Species {
+    Gold,
+    Dirt,
+    Alloy,
+}
+
+impl Species {
+    pub fn atom(&self) -> Atom {
+        match self {
+            Species::Gold => Atom::Gold,
+            Species::Dirt => Atom::Dirt,
+            Species::Alloy => Atom::Alloy,
+        }
+    }
+}
+
+fn gold() -> Species { Species::Gold }
+
+/// All shapes known to the crystal.
+#[derive(Clone,Debug,Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Shape {
+    Atom        { center: [f32; 3], #[serde(default = "gold")] species: Species },
+    Layer       { center: [f32; 3], size: u16, #[serde(default = "gold")] species: Species },
+    Box         { center: [f32; 3], width: f32, depth: f32, height: f32, #[serde(default = "gold")] species: Species },
+    RoundedBox  { center: [f32; 3], width: f32, depth: f32, height: f32, radius: f32 },
+    Sphere      { center: [f32; 3], radius: f32 },
+    Cylinder    { center: [f32; 3], length: f32, radius: f32 },
+}
+
+/// Description of a whole scene.
+#[derive(Clone,Debug,Deserialize)]
+pub struct SceneFile {
+    pub camera: Option<[f32; 3]>,
+    pub stacking_faults: Option<Vec<i16>>,
+    #[serde(default)]
+    pub substrate: bool,
+    #[serde(default, rename = "shape")]
+    pub shapes: Vec<Shape>,
+}
+
+impl SceneFile {
+    /// Parse a scene from a TOML string -- the stacking faults have to lie within the bulk storage (layers 1 to FLAKE_MAX.k-2).
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let scene: SceneFile = toml::from_str(text).map_err(|error| format!("Invalid scene file: {}", error))?;
+        for &k in scene.stacking_faults.iter().flatten() {
+            let layer = CENTER.k as i32 + k as i32;
+            if layer < 1 || layer >= FLAKE_MAX.k as i32 - 1 {
+                return Err(format!("Invalid scene file: stacking fault {} outside of the bulk (-{} to {})", k, CENTER.k - 1, FLAKE_MAX.k - 2 - CENTER.k));
+            }
+        }
+        Ok(scene)
+    }
+
+    /// Load a scene from a TOML file.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|error| format!("Unable to read {}: {}", path, error))?;
+        SceneFile::parse(&text)
+    }
+
+    /// The absolute positions of the stacking faults (if given).
+    pub fn stacking_faults(&self) -> Option<Vec<u16>> {
+        self.stacking_faults.as_ref().map(|faults| faults.iter().map(|&k| (CENTER.k as i16 + k) as u16).collect())
+    }
+}
+
+
+fn xyz(center: &[f32; 3]) -> XYZ {
+    XYZ{x: center[0], y: center[1], z: center[2]}
+}
+
+impl Crystal {
+    /// Build the crystal from a scene -- the old crystal is removed before.
+    pub fn load_scene(&mut self, scene: &SceneFile) {
+        self.clear();
+        self.substrate_pos = 1;
+        if let Some(stacking_faults) = scene.stacking_faults() {
+            self.lattice = Lattice::new(stacking_faults, DIAMETER);
+        }
+
+        // add all the shapes
+        for shape in scene.shapes.iter() {
+            match shape {
+                Shape::Atom{center, species} => {
+                    let ijk = self.lattice.get_ijk(xyz(center));
+                    match species.atom() {
+                        Atom::Dirt => {self.add_dirt(ijk);},
+                        atom => {self.add_metal(ijk, atom);},
+                    }
+                },
+                Shape::Layer{center, size, species} => {
+                    let ijk = self.lattice.get_ijk(xyz(center));
+                    self.add_layer(ijk, *size, species.atom());
+                },
+                Shape::Box{center, width, depth, height, species} => self.add_box(xyz(center), *width, *depth, *height, species.atom()),
+                Shape::RoundedBox{center, width, depth, height, radius} => self.add_rounded_box(xyz(center), *width, *depth, *height, *radius),
+                Shape::Sphere{center, radius} => self.add_sphere(xyz(center), *radius),
+                Shape::Cylinder{center, length, radius} => self.add_cylinder(xyz(center), *length, *radius),
+            }
+        }
+
+        // substrate below the lowest layer
+        if scene.substrate {
+            self.substrate_pos = self.extrema_ijk.z_min.k - 1;
+            self.update_vacancies();
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_scene() {
+        let scene = SceneFile::parse(include_str!("../scenes/dipole_antenna.toml")).unwrap();
+        assert!(!scene.shapes.is_empty());
+        let scene = SceneFile::parse("stacking_faults = [-2, 2]\n[[shape]]\ntype = \"atom\"\ncenter = [0.0, 0.0, 0.0]").unwrap();
+        assert_eq!(scene.stacking_faults(), Some(vec![CENTER.k - 2, CENTER.k + 2]));
+        assert!(matches!(scene.shapes[0], Shape::Atom{species: Species::Gold, ..}));
+    }
+
+    #[test]
+    fn reject_invalid_scenes() {
+        assert!(SceneFile::parse("[[shape]]\ntype = \"pyramid\"\ncenter = [0.0, 0.0, 0.0]").is_err());
+        assert!(SceneFile::parse(&format!("stacking_faults = [{}]", CENTER.k)).is_err());
+        assert!(SceneFile::parse(&format!("stacking_faults = [-{}]", CENTER.k)).is_err());
+        assert!(SceneFile::parse(&format!("stacking_faults = [{}]", FLAKE_MAX.k - 2 - CENTER.k)).is_ok());
+    }
+}
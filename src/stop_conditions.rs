@@ -0,0 +1,135 @@
+/*!
+Stop conditions based on the geometry of the flake
+
+Instead of a fixed number of atoms the growth can be stopped when the flake reaches a given width, height, depth, aspect ratio
+or length ratio (of the hexagon edges, see statistics) or when it comes within a number of layers of the FLAKE_MAX boundary.
+The conditions can be used as stop marks in the statistics, as target for the interactive growth and in protocol files.
+
+The growth is done in chunks of 1/STOP_RESOLUTION of the current number of atoms, so the conditions are checked often enough
+without slowing down the growth too much (every call of random_add reconstructs the surface list).
+*/
+
+use serde::Deserialize;
+
+use crate::parameters::*;
+use crate::crystal::*;
+
+#[cfg(target_arch = "wasm32")]
+use crate::println;
+
+
+/// A geometric (or atom number) target of the growth -- all lengths in nm.
+#[derive(Copy,Clone,Debug,Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopCondition {
+    Atoms(usize),
+    Width(f32),
+    Height(f32),
+    Depth(f32),
+    AspectRatio(f32),
+    LengthRatio(f32),
+    Boundary(u16),
+}
+
+impl StopCondition {
+    /// Parse a condition like `width=500` or `boundary=10` as given at the command line.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let error = || format!("Invalid stop condition: {}", text);
+        let mut parts = text.splitn(2, '=');
+        let name = parts.next().ok_or_else(error)?;
+        let value = parts.next().ok_or_else(error)?.replace('_', "");
+        let float = || value.parse::<f32>().map_err(|_| error());
+        match name {
+            "atoms" => value.parse::<usize>().map(StopCondition::Atoms).map_err(|_| error()),
+            "width" => float().map(StopCondition::Width),
+            "height" => float().map(StopCondition::Height),
+            "depth" => float().map(StopCondition::Depth),
+            "aspect_ratio" => float().map(StopCondition::AspectRatio),
+            "length_ratio" => float().map(StopCondition::LengthRatio),
+            "boundary" => value.parse::<u16>().map(StopCondition::Boundary).map_err(|_| error()),
+            _ => Err(error()),
+        }
+    }
+}
+
+
+impl Crystal {
+    /// Check if the crystal fulfills a condition -- a length ratio below 0.5 is reached from above, one above 0.5 from below.
+    pub fn reached(&self, condition: &StopCondition) -> bool {
+        let [h,w,d,r] = self.get_size();
+        match *condition {
+            StopCondition::Atoms(atoms) => self.bulk.number_of_atoms >= atoms,
+            StopCondition::Width(width) => w >= width,
+            StopCondition::Height(height) => h >= height,
+            StopCondition::Depth(depth) => d >= depth,
+            StopCondition::AspectRatio(ratio) => r >= ratio,
+            StopCondition::LengthRatio(ratio) => {
+                let length_ratio = self.length_ratio();
+                if ratio < 0.5 { length_ratio <= ratio } else { length_ratio >= ratio }
+            },
+            StopCondition::Boundary(layers) => self.distance_to_boundary() <= layers,
+        }
+    }
+
+    /// Grow until the condition is reached (or nothing can be added anymore) -- returns the number of added atoms.
+    pub fn grow_until(&mut self, condition: &StopCondition) -> usize {
+        let start = self.bulk.number_of_atoms;
+        while !self.reached(condition) {
+            let atoms = self.bulk.number_of_atoms;
+            let chunk = match *condition {
+                StopCondition::Atoms(target) => (target - atoms).min(atoms/STOP_RESOLUTION + 1),
+                _ => atoms/STOP_RESOLUTION + 1,
+            };
+            self.random_add(chunk);
+            if self.cancelled() { break }
+            if !self.reached(condition) && (self.bulk.number_of_atoms == atoms || self.bulk.number_of_atoms.saturating_sub(start) >= STOP_MAX_ATOMS) {
+                println!("Stop condition {:?} not reached after {} atoms", condition, self.bulk.number_of_atoms.saturating_sub(start));
+                break
+            }
+        }
+        self.bulk.number_of_atoms.saturating_sub(start)
+    }
+
+    /// Ratio of two neighboring edges of the hexagon spanned by the extrema -- 0.5 for a regular hexagon.
+    pub fn length_ratio(&self) -> f32 {
+        let [ax,ay,bx,by,cx,cy,..] = self.get_hexagon();
+        let len1 = ((ax-bx).powi(2) + (ay-by).powi(2)).sqrt();
+        let len2 = ((cx-bx).powi(2) + (cy-by).powi(2)).sqrt();
+        len1/(len1+len2)
+    }
+
+    /// Smallest distance (in layers) of the occupied region to the border of the bulk storage.
+    pub fn distance_to_boundary(&self) -> u16 {
+        let b = &self.bulk;
+        [b.i_min, FLAKE_MAX.i - 1 - b.i_max, b.j_min, FLAKE_MAX.j - 1 - b.j_max, b.k_min, FLAKE_MAX.k - 1 - b.k_max].iter().cloned().min().unwrap_or(0)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lattice::*;
+
+    #[test]
+    fn parse_conditions() {
+        assert!(matches!(StopCondition::parse("atoms=1_000_000"), Ok(StopCondition::Atoms(1_000_000))));
+        assert!(matches!(StopCondition::parse("width=500"), Ok(StopCondition::Width(width)) if width == 500.0));
+        assert!(matches!(StopCondition::parse("length_ratio=0.4"), Ok(StopCondition::LengthRatio(ratio)) if ratio == 0.4));
+        assert!(matches!(StopCondition::parse("boundary=10"), Ok(StopCondition::Boundary(10))));
+        assert!(StopCondition::parse("width").is_err());
+        assert!(StopCondition::parse("width=wide").is_err());
+        assert!(StopCondition::parse("atoms=1.5").is_err());
+        assert!(StopCondition::parse("volume=3").is_err());
+    }
+
+    #[test]
+    fn grow_until_a_condition() {
+        let mut flake = Crystal::new(Lattice::new(STACKING_FAULTS.to_vec(), DIAMETER));
+        flake.add_atom(CENTER);
+        let added = flake.grow_until(&StopCondition::Atoms(1234));
+        assert_eq!((added, flake.bulk.number_of_atoms), (1233, 1234));
+        flake.grow_until(&StopCondition::Width(8.0));
+        assert!(flake.reached(&StopCondition::Width(8.0)));
+    }
+}
@@ -2,34 +2,137 @@
  Storage back-end of the crystal struct
 
 The **storage struct** provides:
-* Bulk: A lean 3D storage for the atom (empty, gold or dirt) of each position of the crystal which e.g. can hold 10.8 billion positions within 2.5GB.
+* Bulk: A lean 3D storage for the atom (empty, gold, dirt or a second metal) of each position of the crystal which e.g. can hold 10.8 billion positions within 2.5GB.
 * SurfaceAtoms: A list which holds all atom positions of the surface atoms.
 * Vacancies: A list of lists of the positions of all vacancies depending on their coordination number.
+* Coordination: The number of metal neighbors of each position, so the coordination number is a single lookup instead of twelve.
 
-Bulk is implemented via an ndarray, its size is (so far) predefined at compile time but it actually does "dynammically" use the memory due to the advantegeous handling of zeroed pages by the OS :-)
+Bulk is implemented as a flat vector of bytes (viewed as an ndarray where whole regions are needed), its size is (so far) predefined at compile time but it actually does "dynammically" use the memory due to the advantegeous handling of zeroed pages by the OS :-)
+
+The coordination numbers need COORD_BITS per position in addition to the BITS of the bulk, i.e. with 4 bits the memory is tripled
+(the OS still only provides the pages which are really touched). Therefore they are only allocated when the crystal grows with several threads
+or when COORDINATION is set in the parameters.
+
+With BULK_FILE the bulk lives in a file which is mapped into the memory instead, so the OS can page it out to the disk (e.g. a SSD) and flakes
+larger than the RAM can be grown. The file has the same (sparse) layout behind a header page with the numbers of atoms, the extrema and the lattice (stacking faults, screw dislocation, period and substrate),
+so it is also a snapshot of the flake which can be reopened later (the surface and the vacancies have to be reconstructed then).
+The same format is used for forking a bulk: a fork is a snapshot copy of the occupied region in a temporary file, which the fork maps
+privately, so it only gets own pages in the memory where it is changed afterwards -- the file is removed together with the fork.
 
 SurfaceAtoms and Vacancies utilize a BTreeSet datastructure to quickly find (random) locations within them.
 */
 
 use std::collections::BTreeSet;
-use ndarray::{Array3};
+use ndarray::{Array3, ArrayView3, ArrayViewMut3, Axis, s};
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::{fs::{File, OpenOptions}, io, io::{Seek, SeekFrom, Write}, sync::atomic::{AtomicUsize, Ordering}};
+#[cfg(not(target_arch = "wasm32"))]
+use memmap2::{MmapMut, MmapOptions};
 
 use crate::helpers::*;
 use crate::parameters::*;
+use crate::lattice::Screw;
 
 #[cfg(target_arch = "wasm32")]
 use crate::println;
 
+/// Alloy is the second metal (e.g. silver or palladium) -- use is_metal to check for gold as well as alloy atoms.
+#[derive(Copy,Clone,Debug,PartialEq)]
 pub enum Atom {
     Empty,
     Gold,
     Dirt,
+    Alloy,
+}
+
+// Size of the header of a bulk file (a whole page, so the storage behind it stays aligned) and its first bytes
+#[cfg(not(target_arch = "wasm32"))]
+const HEADER: usize = 4096;
+#[cfg(not(target_arch = "wasm32"))]
+const MAGIC: &[u8; 8] = b"FLAKEBLK";
+// numbering of the temporary files of the forks
+#[cfg(not(target_arch = "wasm32"))]
+static FORKS: AtomicUsize = AtomicUsize::new(0);
+
+fn storage_shape() -> (usize, usize, usize) {
+    (FLAKE_MAX.i as usize, FLAKE_MAX.j as usize, (FLAKE_MAX.k/DIV + 1) as usize)
+}
+
+/// The memory behind the bulk: a zeroed vector in the (virtual) memory or a file mapped into it.
+enum Memory {
+    Array(Vec<u8>),
+    #[cfg(not(target_arch = "wasm32"))]
+    File(MmapMut, File),
+    #[cfg(not(target_arch = "wasm32"))]
+    Fork(MmapMut, TempPath),
+}
+
+impl Memory {
+    /// The whole storage in the memory -- the OS only provides the zeroed pages which are really touched.
+    fn zeros() -> Vec<u8> {
+        let (i, j, k) = storage_shape();
+        vec![0; i*j*k]
+    }
+
+    /// The whole storage as one slice (behind the header for a file) in the order of storage_shape.
+    fn data(&self) -> &[u8] {
+        match self {
+            Memory::Array(array) => array,
+            #[cfg(not(target_arch = "wasm32"))]
+            Memory::File(map, _) | Memory::Fork(map, _) => &map[HEADER..],
+        }
+    }
+
+    fn data_mut(&mut self) -> &mut [u8] {
+        match self {
+            Memory::Array(array) => array,
+            #[cfg(not(target_arch = "wasm32"))]
+            Memory::File(map, _) | Memory::Fork(map, _) => &mut map[HEADER..],
+        }
+    }
+}
+
+/// A temporary file which is removed when it is dropped -- after the mapping in front of it in Memory::Fork, as some OS (e.g. Windows)
+/// can not remove a mapped file. On unix it is already removed right after mapping it, so it does not even stay behind after a crash.
+#[cfg(not(target_arch = "wasm32"))]
+struct TempPath(std::path::PathBuf);
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        if self.0.exists() {
+            if let Err(error) = std::fs::remove_file(&self.0) {
+                println!("Unable to remove {}: {}", self.0.display(), error);
+            }
+        }
+    }
+}
+
+/// The lattice and the substrate a bulk file was grown on -- kept in its header (see Crystal::write_bulk_header),
+/// so a reopened flake continues on the same lattice (see Crystal::reopen).
+#[derive(Clone,Debug)]
+pub struct BulkLattice {
+    pub stacking_faults: Vec<u16>,
+    pub screw: Option<Screw>,
+    pub period: Option<u16>,
+    pub substrate_pos: u16,
+}
+
+impl Default for BulkLattice {
+    fn default() -> Self {
+        BulkLattice{ stacking_faults: Vec::new(), screw: None, period: None, substrate_pos: 1 }
+    }
 }
 
 pub struct Bulk { 
-    storage: Array3<u8>,
+    memory: Memory,
+    pub lattice: BulkLattice,
     unit: u8,
     pub number_of_atoms: usize,
+    pub number_of_gold: usize,
+    pub number_of_dirt: usize,
+    pub number_of_alloy: usize,
     pub i_min: u16, 
     pub i_max: u16, 
     pub j_min: u16, 
@@ -41,12 +144,28 @@ pub struct Bulk {
 
 impl Bulk {
     pub fn new() -> Self {
-        let storage = Array3::<u8>::zeros((FLAKE_MAX.i as usize, FLAKE_MAX.j as usize, (FLAKE_MAX.k/DIV + 1) as usize));   
-        println!("Array shape {:?} with DIV {:?}", storage.shape(), DIV);                     
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(path) = BULK_FILE {
+            return Bulk::create(path).expect("Unable to create the bulk file")
+        }
+        Bulk::in_memory()
+    }
+
+    /// A bulk in the memory only, even with a BULK_FILE -- e.g. for a placeholder which must not touch the file of another crystal.
+    pub fn in_memory() -> Self {
+        Bulk::with_memory(Memory::Array(Memory::zeros()))
+    }
+
+    fn with_memory(memory: Memory) -> Self {
+        println!("Array shape {:?} with DIV {:?}", storage_shape(), DIV);
         Bulk { 
-            storage, 
+            memory,
+            lattice: BulkLattice::default(),
             unit: (2u16.pow(BITS as u32) - 1) as u8,                         // needed for the bitmask further down and should only be calculated once
             number_of_atoms: 0, 
+            number_of_gold: 0, 
+            number_of_dirt: 0, 
+            number_of_alloy: 0, 
             i_min: CENTER.i, 
             i_max: CENTER.i, 
             j_min: CENTER.j, 
@@ -59,8 +178,27 @@ impl Bulk {
     pub fn clear(&mut self) {
         // To reset the storage we have two possibilities:
         // The quick one seems to need twice the memory size very briefly during allocation:
-        self.storage = Array3::<u8>::zeros((0, 0, 0));                                  // This is the solution for freeing the memory, first!
-        self.storage = Array3::<u8>::zeros((FLAKE_MAX.i as usize, FLAKE_MAX.j as usize, (FLAKE_MAX.k/DIV + 1) as usize)); 
+        let memory = std::mem::replace(&mut self.memory, Memory::Array(Vec::new()));
+        self.memory = match memory {
+            Memory::Array(array) => {
+                drop(array);                                                            // This is the solution for freeing the memory, first!
+                Memory::Array(Memory::zeros())
+            },
+            // a fork starts over in the memory
+            #[cfg(not(target_arch = "wasm32"))]
+            Memory::Fork(map, path) => {
+                drop(map);
+                drop(path);
+                Memory::Array(Memory::zeros())
+            },
+            // a file is cut back to its header and extended again, which frees its pages in the same way
+            #[cfg(not(target_arch = "wasm32"))]
+            Memory::File(map, file) => {
+                drop(map);
+                file.set_len(HEADER as u64).and_then(|_| file.set_len(Bulk::file_size())).expect("Unable to clear the bulk file");
+                Memory::File(unsafe { MmapMut::map_mut(&file) }.expect("Unable to map the bulk file"), file)
+            },
+        };
 
         // And the second one is safer but takes longer:
         // for i in self.i_min..=self.i_max {
@@ -77,6 +215,156 @@ impl Bulk {
         // some memory handling slower.
 
         self.number_of_atoms = 0;
+        self.number_of_gold = 0;
+        self.number_of_dirt = 0;
+        self.number_of_alloy = 0;
+        self.reset_extrema();
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn file_size() -> u64 {
+        let (i, j, k) = storage_shape();
+        (HEADER + i*j*k) as u64
+    }
+
+    /// Create (or overwrite) a bulk file -- it is sparse, so only the touched pages take space on the disk.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn create(path: &str) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(path)?;
+        file.set_len(Bulk::file_size())?;
+        let map = unsafe { MmapMut::map_mut(&file)? };
+        println!("Bulk file: {}", path);
+        let mut bulk = Bulk::with_memory(Memory::File(map, file));
+        bulk.write_header();
+        Ok(bulk)
+    }
+
+    /// Reopen a bulk file written with the same FLAKE_MAX and BITS -- further changes go into the file again.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn open(path: &str) -> io::Result<Self> {
+        let invalid = |text: &str| io::Error::new(io::ErrorKind::InvalidData, format!("{} {}", path, text));
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        if file.metadata()?.len() != Bulk::file_size() { return Err(invalid("does not fit FLAKE_MAX and BITS")) }
+        let map = unsafe { MmapMut::map_mut(&file)? };
+        if &map[..MAGIC.len()] != MAGIC { return Err(invalid("is no bulk file")) }
+
+        // the header holds the shape, the numbers of atoms, the extrema and the lattice (see header)
+        let number = |index: usize| {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(&map[8 + 8*index..16 + 8*index]);
+            u64::from_le_bytes(bytes) as usize
+        };
+        let word = |index: usize| u16::from_le_bytes([map[72 + 2*index], map[73 + 2*index]]);
+        let (i, j, k) = storage_shape();
+        if [number(0), number(1), number(2), number(3)] != [i, j, k, BITS as usize] { return Err(invalid("does not fit FLAKE_MAX and BITS")) }
+        let numbers = [number(4), number(5), number(6), number(7)];
+        let extrema = [word(0), word(1), word(2), word(3), word(4), word(5)];
+        let lattice = BulkLattice{
+            substrate_pos: word(6).max(1),
+            period: Some(word(7)).filter(|&period| period > 0),
+            screw: Some(Screw{ i: word(8), j: word(9), burgers: word(10) as i16 }).filter(|screw| screw.i > 0),
+            stacking_faults: (0..word(11) as usize).map(|index| word(12 + index)).collect(),
+        };
+
+        println!("Bulk file: {}", path);
+        let mut bulk = Bulk::with_memory(Memory::File(map, file));
+        bulk.lattice = lattice;
+        bulk.number_of_atoms = numbers[0];
+        bulk.number_of_gold = numbers[1];
+        bulk.number_of_dirt = numbers[2];
+        bulk.number_of_alloy = numbers[3];
+        bulk.i_min = extrema[0];
+        bulk.i_max = extrema[1];
+        bulk.j_min = extrema[2];
+        bulk.j_max = extrema[3];
+        bulk.k_min = extrema[4];
+        bulk.k_max = extrema[5];
+        Ok(bulk)
+    }
+
+    /// The header of a bulk file: the shape and the numbers of atoms (u64), then the extrema, the substrate, the period (0 without),
+    /// the screw dislocation (i = 0 without) and the stacking faults after their number (u16).
+    #[cfg(not(target_arch = "wasm32"))]
+    fn header(&self) -> Vec<u8> {
+        let (i, j, k) = storage_shape();
+        let mut header = MAGIC.to_vec();
+        for &number in [i, j, k, BITS as usize, self.number_of_atoms, self.number_of_gold, self.number_of_dirt, self.number_of_alloy].iter() {
+            header.extend_from_slice(&(number as u64).to_le_bytes());
+        }
+        let lattice = &self.lattice;
+        let screw = lattice.screw.unwrap_or(Screw{ i: 0, j: 0, burgers: 0 });
+        let faults = lattice.stacking_faults.len().min((HEADER - 96)/2);
+        let words = [self.i_min, self.i_max, self.j_min, self.j_max, self.k_min, self.k_max,
+                     lattice.substrate_pos, lattice.period.unwrap_or(0), screw.i, screw.j, screw.burgers as u16, faults as u16];
+        for &word in words.iter().chain(lattice.stacking_faults[..faults].iter()) {
+            header.extend_from_slice(&word.to_le_bytes());
+        }
+        header
+    }
+
+    /// Write the numbers of atoms, the extrema and the lattice into the header of a bulk file (nothing to do for a bulk in memory)
+    /// -- the OS writes it to the disk together with the other changed pages, even if the program ends without a flush.
+    pub fn write_header(&mut self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let header = self.header();
+            if let Memory::File(map, _) = &mut self.memory {
+                map[..header.len()].copy_from_slice(&header);
+            }
+        }
+    }
+
+    /// Write the header and all changed pages of a bulk file to the disk right away.
+    pub fn flush(&mut self) {
+        self.write_header();
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            if let Memory::File(map, _) = &mut self.memory {
+                map.flush().expect("Unable to write the bulk file");
+            }
+        }
+    }
+
+    /// A snapshot copy of the bulk: the occupied region (within the extrema) is written to a temporary file, which is mapped privately
+    /// -- so a fork only gets own pages in the memory where it is changed, and later changes of the original do not reach it.
+    /// The file is removed together with the fork (or when the copy fails).
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn fork(&self) -> io::Result<Self> {
+        let path = TempPath(std::env::temp_dir().join(format!("{}_fork_{}_{}.bulk", FILENAME, std::process::id(), FORKS.fetch_add(1, Ordering::Relaxed))));
+        let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(&path.0)?;
+        file.set_len(Bulk::file_size())?;
+        file.write_all(&self.header())?;
+
+        // every row of i is one contiguous block from j_min to j_max
+        let (_, j_size, k_size) = storage_shape();
+        let storage = self.storage();
+        for i in self.i_min..=self.i_max {
+            let row = storage.slice(s![i as usize, self.j_min as usize..=self.j_max as usize, ..]);
+            file.seek(SeekFrom::Start((HEADER + (i as usize*j_size + self.j_min as usize)*k_size) as u64))?;
+            file.write_all(row.as_slice().expect("The bulk is not contiguous"))?;
+        }
+
+        let map = unsafe { MmapOptions::new().map_copy(&file)? };
+        drop(file);
+        #[cfg(unix)]
+        std::fs::remove_file(&path.0)?;
+        let mut bulk = Bulk::with_memory(Memory::Fork(map, path));
+        bulk.lattice = self.lattice.clone();
+        bulk.number_of_atoms = self.number_of_atoms;
+        bulk.number_of_gold = self.number_of_gold;
+        bulk.number_of_dirt = self.number_of_dirt;
+        bulk.number_of_alloy = self.number_of_alloy;
+        bulk.i_min = self.i_min;
+        bulk.i_max = self.i_max;
+        bulk.j_min = self.j_min;
+        bulk.j_max = self.j_max;
+        bulk.k_min = self.k_min;
+        bulk.k_max = self.k_max;
+        Ok(bulk)
+    }
+
+    /// Reset the extrema to the center -- they can be grown again via update_extrema.
+    pub fn reset_extrema(&mut self) {
         self.i_min = CENTER.i;
         self.i_max = CENTER.i; 
         self.j_min = CENTER.j; 
@@ -85,54 +373,305 @@ impl Bulk {
         self.k_max = CENTER.k;
     }
 
+    /// Extend the extrema to include the given position.
+    pub fn update_extrema(&mut self, ijk: IJK) {
+        if ijk.i < self.i_min { self.i_min = ijk.i }
+        if ijk.i > self.i_max { self.i_max = ijk.i }
+        if ijk.j < self.j_min { self.j_min = ijk.j }
+        if ijk.j > self.j_max { self.j_max = ijk.j }
+        if ijk.k < self.k_min { self.k_min = ijk.k }
+        if ijk.k > self.k_max { self.k_max = ijk.k }
+    }
+
+    /// Check if the position lies on the border of the extrema box.
+    pub fn on_border(&self, ijk: IJK) -> bool {
+        ijk.i == self.i_min || ijk.i == self.i_max || ijk.j == self.j_min || ijk.j == self.j_max || ijk.k == self.k_min || ijk.k == self.k_max
+    }
+
     pub fn set(&mut self, ijk: IJK, atom: Atom) {
         // translate enum to value
         let value: u8 = match atom {
             Atom::Empty    => {0},
             Atom::Gold     => {1},
-            Atom::Dirt     => {2}
+            Atom::Dirt     => {2},
+            Atom::Alloy    => {3},
         };
 
         // save value
         // read data into a virtual register
-        let mut register = self.storage[[ijk.i as usize, ijk.j as usize, (ijk.k/DIV) as usize]];
+        let index = Bulk::index(ijk);
+        let mut register = self.memory.data()[index];
         // update the right bits in the byte/word/longword or whatever will be used in the end
         let pos = ijk.k%DIV;                                                           // calculate the position
+        let previous = register.wrapping_shr((pos*BITS) as u32) & self.unit;              // remember what was there before
         let change = value.wrapping_shl((pos*BITS) as u32);                             // move bit to the right position
         let bitmask = !self.unit.wrapping_shl((pos*BITS) as u32);                       // construct a bitmask for the same position
         register = (register & bitmask) + change;                                           // update the register
-        self.storage[[ijk.i as usize, ijk.j as usize, (ijk.k/DIV) as usize]] = register;    // write data back  
+        self.memory.data_mut()[index] = register;                                           // write data back
         
-        // update extrema
+        // update the numbers -- only real changes count
+        match previous {
+            1 => { self.number_of_gold -= 1; self.number_of_atoms -= 1; },
+            2 => { self.number_of_dirt -= 1; self.number_of_atoms -= 1; },
+            3 => { self.number_of_alloy -= 1; self.number_of_atoms -= 1; },
+            _ => {}
+        }
+        match value {
+            1 => { self.number_of_gold += 1; self.number_of_atoms += 1; },
+            2 => { self.number_of_dirt += 1; self.number_of_atoms += 1; },
+            3 => { self.number_of_alloy += 1; self.number_of_atoms += 1; },
+            _ => {}
+        }
+
+        // update extrema -- shrinking them is up to the caller as this needs a look at the whole crystal
         if value > 0 {   
-            self.number_of_atoms += 1;      
-            if ijk.i < self.i_min { self.i_min = ijk.i }
-            if ijk.i > self.i_max { self.i_max = ijk.i }
-            if ijk.j < self.j_min { self.j_min = ijk.j }
-            if ijk.j > self.j_max { self.j_max = ijk.j }
-            if ijk.k < self.k_min { self.k_min = ijk.k }
-            if ijk.k > self.k_max { self.k_max = ijk.k }
+            self.update_extrema(ijk);
         }
     }
 
-    pub fn get(&self, ijk: IJK, atom: Atom) -> bool {
-        let value = self.storage[[ijk.i as usize, ijk.j as usize, (ijk.k/DIV)  as usize]]; 
+    fn value(&self, ijk: IJK) -> u8 {
+        let value = self.memory.data()[Bulk::index(ijk)];
         let pos = ijk.k%DIV;                                                           // calculate position in byte/word/longword or whatever we will use in the end
-        let value = value.wrapping_shr((pos*BITS) as u32) & self.unit;                  // select the right bits
+        value.wrapping_shr((pos*BITS) as u32) & self.unit                               // select the right bits
+    }
 
+    pub fn get(&self, ijk: IJK, atom: Atom) -> bool {
+        let value = self.value(ijk);
         match atom {
             Atom::Empty    => {value == 0},
             Atom::Gold     => {value == 1},
             Atom::Dirt     => {value == 2},
+            Atom::Alloy    => {value == 3},
+        }
+    }
+
+    /// Check if there is a metal atom (gold or alloy) at a position.
+    pub fn is_metal(&self, ijk: IJK) -> bool {
+        self.value(ijk) & 1 == 1
+    }
+
+    /// Call a function for every atom within the extrema -- DIV empty positions are skipped at once by looking at whole bytes,
+    /// so only the occupied parts of the storage are really looked at.
+    pub fn for_each_atom<F: FnMut(IJK, Atom)>(&self, mut function: F) {
+        let k_start = self.k_min/DIV;
+        let storage = self.storage();
+        let view = storage.slice(s![self.i_min as usize..=self.i_max as usize, self.j_min as usize..=self.j_max as usize, k_start as usize..=(self.k_max/DIV) as usize]);
+        for ((i, j, k), &byte) in view.indexed_iter() {
+            if byte == 0 { continue }
+            for pos in 0..DIV {
+                let ijk = IJK{i: self.i_min + i as u16, j: self.j_min + j as u16, k: (k_start + k as u16)*DIV + pos};
+                match byte.wrapping_shr((pos*BITS) as u32) & self.unit {
+                    0 => {},
+                    1 => function(ijk, Atom::Gold),
+                    2 => function(ijk, Atom::Dirt),
+                    _ => function(ijk, Atom::Alloy),
+                }
+            }
+        }
+    }
+
+    /// Count gold atoms which have been written via a BulkPart and extend the extrema accordingly.
+    pub fn count_gold(&mut self, atoms: &[IJK]) {
+        self.number_of_gold += atoms.len();
+        self.number_of_atoms += atoms.len();
+        for &ijk in atoms {
+            self.update_extrema(ijk);
         }
     }
 
+    /// Move all atoms by the given numbers of positions (k only in whole bytes, i.e. multiples of DIV) together with the extrema --
+    /// the caller has to make sure that they stay within the storage. Every row of i (from j_min to j_max) is moved at once,
+    /// starting at the far end, so the rows are never overwritten before they are moved themselves.
+    pub fn shift(&mut self, shift: [i32; 3]) {
+        let (_, j_size, k_size) = storage_shape();
+        let offset = (shift[0] as isize*j_size as isize + shift[1] as isize)*k_size as isize + (shift[2]/DIV as i32) as isize;
+        let length = (self.j_max - self.j_min + 1) as usize*k_size;
+        let rows: Vec<u16> = if offset > 0 { (self.i_min..=self.i_max).rev().collect() } else { (self.i_min..=self.i_max).collect() };
+        let storage = self.memory.data_mut();
+        for i in rows {
+            let start = (i as usize*j_size + self.j_min as usize)*k_size;
+            let target = (start as isize + offset) as usize;
+            storage.copy_within(start..start + length, target);
+
+            // clear what is left of the old row
+            if target > start { storage[start..target.min(start + length)].iter_mut().for_each(|byte| *byte = 0) }
+            else { storage[(target + length).max(start)..start + length].iter_mut().for_each(|byte| *byte = 0) }
+        }
+
+        let moved = |value: u16, shift: i32| (value as i32 + shift) as u16;
+        self.i_min = moved(self.i_min, shift[0]);
+        self.i_max = moved(self.i_max, shift[0]);
+        self.j_min = moved(self.j_min, shift[1]);
+        self.j_max = moved(self.j_max, shift[1]);
+        self.k_min = moved(self.k_min, shift[2]);
+        self.k_max = moved(self.k_max, shift[2]);
+    }
+
+    /// Split the bulk and the coordination numbers into disjoint parts for the given (ascending and not overlapping) ranges of i.
+    pub fn parts<'a>(&'a mut self, coordination: &'a mut Coordination, ranges: &[(u16, u16)]) -> Vec<BulkPart<'a>> {
+        let mut parts = Vec::with_capacity(ranges.len());
+        let unit = self.unit;
+        let mut bulk_rest = self.storage_mut();
+        let mut coordination_rest = coordination.storage.view_mut();
+        let mut offset = 0;
+        for &(i_start, i_end) in ranges {
+            let (_, rest) = bulk_rest.split_at(Axis(0), (i_start - offset) as usize);
+            let (bulk, rest) = rest.split_at(Axis(0), (i_end - i_start) as usize);
+            bulk_rest = rest;
+            let (_, rest) = coordination_rest.split_at(Axis(0), (i_start - offset) as usize);
+            let (coordination, rest) = rest.split_at(Axis(0), (i_end - i_start) as usize);
+            coordination_rest = rest;
+            parts.push(BulkPart{ bulk, coordination, i_start, bulk_unit: unit, coordination_unit: (2u16.pow(COORD_BITS as u32) - 1) as u8 });
+            offset = i_end;
+        }
+        parts
+    }
+
+    /// What is at a position.
+    pub fn atom(&self, ijk: IJK) -> Atom {
+        match self.value(ijk) {
+            0 => Atom::Empty,
+            1 => Atom::Gold,
+            2 => Atom::Dirt,
+            _ => Atom::Alloy,
+        }
+    }
+
+    /// The index of the byte of a position within the storage.
+    fn index(ijk: IJK) -> usize {
+        let (_, j_size, k_size) = storage_shape();
+        (ijk.i as usize*j_size + ijk.j as usize)*k_size + (ijk.k/DIV) as usize
+    }
+
+    /// A view of the whole storage -- borrowed from the memory, so it can not outlive it.
+    fn storage(&self) -> ArrayView3<'_, u8> {
+        ArrayView3::from_shape(storage_shape(), self.memory.data()).expect("The bulk does not fit its shape")
+    }
+
+    fn storage_mut(&mut self) -> ArrayViewMut3<'_, u8> {
+        ArrayViewMut3::from_shape(storage_shape(), self.memory.data_mut()).expect("The bulk does not fit its shape")
+    }
+
+}
+
+
+
+
+// a bulk file is always left with a valid header
+impl Drop for Bulk {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+
+
+/// A slice of the bulk and of the coordination numbers (all positions with i_start <= i < i_start + length), which can be changed independently
+/// of the other parts -- the parallel growth gives one part to each thread. The numbers of atoms are counted afterwards via count_gold.
+pub struct BulkPart<'a> {
+    bulk: ArrayViewMut3<'a, u8>,
+    coordination: ArrayViewMut3<'a, u8>,
+    i_start: u16,
+    bulk_unit: u8,
+    coordination_unit: u8,
 }
 
+impl<'a> BulkPart<'a> {
+    fn value(&self, ijk: IJK) -> u8 {
+        let value = self.bulk[[(ijk.i - self.i_start) as usize, ijk.j as usize, (ijk.k/DIV) as usize]];
+        value.wrapping_shr(((ijk.k%DIV)*BITS) as u32) & self.bulk_unit
+    }
+
+    pub fn empty(&self, ijk: IJK) -> bool {
+        self.value(ijk) == 0
+    }
+
+    pub fn metal(&self, ijk: IJK) -> bool {
+        self.value(ijk) & 1 == 1
+    }
+
+    /// Put a gold atom at an empty position -- the coordination numbers of the neighbors are up to the caller.
+    pub fn add_gold(&mut self, ijk: IJK) {
+        let register = &mut self.bulk[[(ijk.i - self.i_start) as usize, ijk.j as usize, (ijk.k/DIV) as usize]];
+        *register += 1u8.wrapping_shl(((ijk.k%DIV)*BITS) as u32);
+    }
+
+    pub fn coordination(&self, ijk: IJK) -> usize {
+        let value = self.coordination[[(ijk.i - self.i_start) as usize, ijk.j as usize, (ijk.k/COORD_DIV) as usize]];
+        (value.wrapping_shr(((ijk.k%COORD_DIV)*COORD_BITS) as u32) & self.coordination_unit) as usize
+    }
+
+    /// Add one metal neighbor to a position.
+    pub fn add_neighbor(&mut self, ijk: IJK) {
+        let register = &mut self.coordination[[(ijk.i - self.i_start) as usize, ijk.j as usize, (ijk.k/COORD_DIV) as usize]];
+        *register = register.wrapping_add(1u8.wrapping_shl(((ijk.k%COORD_DIV)*COORD_BITS) as u32));
+    }
+}
+
+
+
+/// The number of metal neighbors (0 to 12) of every position -- kept up to date by the crystal whenever a metal atom is set or removed.
+pub struct Coordination {
+    storage: Array3<u8>,
+    unit: u8,
+}
+
+impl Coordination {
+    /// Disabled numbers do not allocate any memory.
+    pub fn new(enabled: bool) -> Self {
+        let storage = Coordination::allocate(enabled);
+        if enabled { println!("Coordination shape {:?} with COORD_DIV {:?}", storage.shape(), COORD_DIV) }
+        Coordination{ storage, unit: (2u16.pow(COORD_BITS as u32) - 1) as u8 }
+    }
+
+    fn allocate(enabled: bool) -> Array3<u8> {
+        if enabled {
+            Array3::<u8>::zeros((FLAKE_MAX.i as usize, FLAKE_MAX.j as usize, (FLAKE_MAX.k/COORD_DIV + 1) as usize))
+        }
+        else {
+            Array3::<u8>::zeros((0, 0, 0))
+        }
+    }
+
+    /// Check if the numbers are allocated (and thereby kept up to date by the crystal).
+    pub fn enabled(&self) -> bool {
+        !self.storage.is_empty()
+    }
+
+    /// Allocate (zeroed) or free the numbers.
+    pub fn enable(&mut self, enabled: bool) {
+        self.storage = Array3::<u8>::zeros((0, 0, 0));
+        self.storage = Coordination::allocate(enabled);
+    }
+
+    /// Reset all numbers -- the same way as the bulk, i.e. freeing the memory first.
+    pub fn clear(&mut self) {
+        let enabled = self.enabled();
+        self.enable(enabled);
+    }
+
+    pub fn get(&self, ijk: IJK) -> usize {
+        let value = self.storage[[ijk.i as usize, ijk.j as usize, (ijk.k/COORD_DIV) as usize]];
+        (value.wrapping_shr(((ijk.k%COORD_DIV)*COORD_BITS) as u32) & self.unit) as usize
+    }
+
+    /// Add one neighbor (or remove it with a negative change) -- the number must not leave 0 to 12.
+    pub fn change(&mut self, ijk: IJK, change: i8) {
+        let shift = ((ijk.k%COORD_DIV)*COORD_BITS) as u32;
+        let register = &mut self.storage[[ijk.i as usize, ijk.j as usize, (ijk.k/COORD_DIV) as usize]];
+        if change > 0 {
+            *register = register.wrapping_add(1u8.wrapping_shl(shift));
+        }
+        else {
+            *register = register.wrapping_sub(1u8.wrapping_shl(shift));
+        }
+    }
+}
 
 
 
 // Since using BTreeSet this abstraction is actually not necessary anymore
+#[derive(Clone)]
 pub struct SurfaceAtoms {
     pub list: BTreeSet<IJK>
 }
@@ -153,6 +692,7 @@ impl SurfaceAtoms {
 
 
 
+#[derive(Clone)]
 pub struct Vacancies {
     pub list: Vec<BTreeSet<IJK>>,
 }
@@ -180,3 +720,144 @@ impl Vacancies {
         }
     } 
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_counts_the_species() {
+        let mut bulk = Bulk::new();
+        let (a, b) = (CENTER, IJK{k: CENTER.k + 1, ..CENTER});
+        bulk.set(a, Atom::Gold);
+        bulk.set(b, Atom::Dirt);
+        assert_eq!((bulk.number_of_atoms, bulk.number_of_gold, bulk.number_of_dirt, bulk.number_of_alloy), (2, 1, 1, 0));
+
+        // replacing an atom only changes the species
+        bulk.set(a, Atom::Alloy);
+        assert_eq!((bulk.number_of_atoms, bulk.number_of_gold, bulk.number_of_alloy), (2, 0, 1));
+        bulk.set(a, Atom::Alloy);
+        assert_eq!(bulk.number_of_atoms, 2);
+        assert!(bulk.get(a, Atom::Alloy) && bulk.atom(a) == Atom::Alloy);
+
+        bulk.set(b, Atom::Empty);
+        bulk.set(b, Atom::Empty);
+        assert_eq!((bulk.number_of_atoms, bulk.number_of_dirt), (1, 0));
+    }
+
+    #[test]
+    fn metal_matches_gold_and_alloy() {
+        let mut bulk = Bulk::new();
+        let positions: Vec<IJK> = (0..4).map(|k| IJK{k: CENTER.k + k, ..CENTER}).collect();
+        for (&ijk, &atom) in positions.iter().zip([Atom::Empty, Atom::Gold, Atom::Dirt, Atom::Alloy].iter()) {
+            bulk.set(ijk, atom);
+        }
+        let metal: Vec<bool> = positions.iter().map(|&ijk| bulk.is_metal(ijk)).collect();
+        assert_eq!(metal, vec![false, true, false, true]);
+    }
+
+    #[test]
+    fn parts_cover_their_own_rows() {
+        let mut bulk = Bulk::new();
+        let mut coordination = Coordination::new(true);
+        let outside = IJK{i: CENTER.i - 5, ..CENTER};
+        bulk.set(outside, Atom::Dirt);
+        let added = [IJK{i: CENTER.i - 10, ..CENTER}, CENTER, IJK{i: CENTER.i + 19, k: CENTER.k + 1, ..CENTER}];
+        {
+            let mut parts = bulk.parts(&mut coordination, &[(CENTER.i - 10, CENTER.i - 5), (CENTER.i, CENTER.i + 20)]);
+            assert_eq!(parts.len(), 2);
+            parts[0].add_gold(added[0]);
+            parts[0].add_neighbor(added[0]);
+            assert!(parts[0].metal(added[0]) && parts[0].coordination(added[0]) == 1);
+            for &ijk in added[1..].iter() {
+                assert!(parts[1].empty(ijk));
+                parts[1].add_gold(ijk);
+                parts[1].add_neighbor(ijk);
+                parts[1].add_neighbor(ijk);
+            }
+        }
+
+        // the parts write into the bulk at the right positions, the rows in between stay untouched
+        bulk.count_gold(&added);
+        assert_eq!((bulk.number_of_atoms, bulk.number_of_gold), (4, 3));
+        assert!(added.iter().all(|&ijk| bulk.get(ijk, Atom::Gold)));
+        assert!(bulk.get(outside, Atom::Dirt));
+        assert_eq!(added.iter().map(|&ijk| coordination.get(ijk)).collect::<Vec<usize>>(), vec![1, 2, 2]);
+        assert_eq!((bulk.i_min, bulk.i_max), (CENTER.i - 10, CENTER.i + 19));
+    }
+
+    #[test]
+    fn reopened_file_keeps_atoms_and_lattice() {
+        let path = std::env::temp_dir().join(format!("{}_test_{}.bulk", FILENAME, std::process::id()));
+        let path = path.to_str().unwrap();
+        let atoms = [CENTER, IJK{i: CENTER.i + 3, ..CENTER}, IJK{k: CENTER.k + 5, ..CENTER}];
+        {
+            let mut bulk = Bulk::create(path).unwrap();
+            bulk.set(atoms[0], Atom::Gold);
+            bulk.set(atoms[1], Atom::Dirt);
+            bulk.set(atoms[2], Atom::Alloy);
+            bulk.lattice = BulkLattice{ stacking_faults: vec![CENTER.k - 2, CENTER.k + 4], screw: Some(Screw{i: CENTER.i, j: CENTER.j, burgers: -3}), period: Some(50), substrate_pos: CENTER.k - 1 };
+        }
+        let bulk = Bulk::open(path).unwrap();
+        assert_eq!(atoms.iter().map(|&ijk| bulk.atom(ijk)).collect::<Vec<Atom>>(), vec![Atom::Gold, Atom::Dirt, Atom::Alloy]);
+        assert_eq!((bulk.number_of_atoms, bulk.number_of_gold, bulk.number_of_dirt, bulk.number_of_alloy), (3, 1, 1, 1));
+        assert_eq!((bulk.i_min, bulk.i_max, bulk.k_min, bulk.k_max), (CENTER.i, CENTER.i + 3, CENTER.k, CENTER.k + 5));
+        let screw = bulk.lattice.screw.unwrap();
+        assert_eq!((screw.i, screw.j, screw.burgers), (CENTER.i, CENTER.j, -3));
+        assert_eq!((bulk.lattice.stacking_faults.clone(), bulk.lattice.period, bulk.lattice.substrate_pos), (vec![CENTER.k - 2, CENTER.k + 4], Some(50), CENTER.k - 1));
+        drop(bulk);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn fork_is_an_independent_snapshot() {
+        let mut bulk = Bulk::new();
+        let (a, b) = (CENTER, IJK{i: CENTER.i + 7, k: CENTER.k + 2, ..CENTER});
+        bulk.set(a, Atom::Gold);
+        bulk.set(b, Atom::Dirt);
+        let mut fork = bulk.fork().unwrap();
+        let path = match &fork.memory {
+            Memory::Fork(_, path) => path.0.clone(),
+            _ => panic!("no fork"),
+        };
+        assert_eq!((fork.atom(a), fork.atom(b), fork.number_of_atoms), (Atom::Gold, Atom::Dirt, 2));
+        assert_eq!((fork.i_min, fork.i_max, fork.k_max), (CENTER.i, CENTER.i + 7, CENTER.k + 2));
+
+        // changes on either side stay there
+        fork.set(a, Atom::Empty);
+        bulk.set(b, Atom::Alloy);
+        assert_eq!((bulk.atom(a), bulk.atom(b)), (Atom::Gold, Atom::Alloy));
+        assert_eq!((fork.atom(a), fork.atom(b), fork.number_of_atoms), (Atom::Empty, Atom::Dirt, 1));
+
+        // the temporary file does not stay behind
+        drop(fork);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn shift_moves_atoms_and_extrema() {
+        let mut bulk = Bulk::new();
+        let mut atoms = [CENTER, IJK{i: CENTER.i + 2, j: CENTER.j - 1, ..CENTER}, IJK{j: CENTER.j + 3, k: CENTER.k + 1, ..CENTER}];
+        bulk.set(atoms[0], Atom::Gold);
+        bulk.set(atoms[1], Atom::Dirt);
+        bulk.set(atoms[2], Atom::Alloy);
+        let moved = |ijk: IJK, shift: [i32; 3]| IJK{i: (ijk.i as i32 + shift[0]) as u16, j: (ijk.j as i32 + shift[1]) as u16, k: (ijk.k as i32 + shift[2]) as u16};
+
+        // forth and back again, so the rows overlap in both directions
+        for &shift in [[5, -3, 2*DIV as i32], [-5, 3, -2*DIV as i32], [1, 0, 0], [0, -1, 0]].iter() {
+            let before: Vec<Atom> = atoms.iter().map(|&ijk| bulk.atom(ijk)).collect();
+            let (i_min, k_max) = (bulk.i_min, bulk.k_max);
+            bulk.shift(shift);
+            let after: Vec<Atom> = atoms.iter().map(|&ijk| bulk.atom(moved(ijk, shift))).collect();
+            assert_eq!(before, after);
+            assert_eq!((bulk.i_min as i32, bulk.k_max as i32), (i_min as i32 + shift[0], k_max as i32 + shift[2]));
+            let mut counted = 0;
+            bulk.for_each_atom(|_, _| counted += 1);
+            assert_eq!(counted, 3);                         // nothing is left at the old positions
+            for atom in atoms.iter_mut() {
+                *atom = moved(*atom, shift);
+            }
+        }
+    }
+}
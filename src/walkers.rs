@@ -0,0 +1,181 @@
+/*!
+Diffusion-limited aggregation with explicit random walkers
+
+As a counterpart to the reaction-limited growth via the vacancy lists, single atoms are released on a sphere around the flake
+and random-walk on the lattice until they stick to it or get lost. Whenever a walker is at a vacancy with n gold neighbors, it sticks
+with the probability STICKING[n-1]. A sticking probability of one for all coordination numbers results in fractal (DLA) clusters,
+small probabilities for low coordination numbers let the walkers explore the surface and result in compact flakes again.
+
+Walkers which leave the kill sphere (three times the release radius) are released again, so every walker ends up in the crystal --
+unless it does not stick within WALKER_MAX_STEPS steps (e.g. with zero sticking probabilities), which stops the walker growth.
+Outside of the release sphere the walkers jump to a random point of the largest sphere around them which does not touch the crystal --
+this is equivalent to the many small steps of a free random walk, but much faster.
+With a substrate the walkers are only released above it and can not enter it.
+*/
+
+use rand::Rng;
+
+use crate::helpers::*;
+use crate::parameters::*;
+use crate::storage::*;
+use crate::crystal::*;
+
+
+/// The two ways atoms can be added to the crystal.
+#[derive(Copy,Clone,Debug,PartialEq)]
+pub enum GrowthMode {
+    Vacancies,
+    Walkers,
+}
+
+impl Crystal {
+    /// Switch between growth via the vacancy lists and via random walkers.
+    pub fn switch_growth_mode(&mut self) {
+        self.growth_mode = match self.growth_mode {
+            GrowthMode::Vacancies => GrowthMode::Walkers,
+            GrowthMode::Walkers => GrowthMode::Vacancies,
+        };
+    }
+
+    /// Add atoms via random walkers -- returns the total number of steps of all walkers.
+    pub fn walker_add(&mut self, number_of_atoms: usize, sticking: &[f32; 12]) -> usize {
+        let mut steps = 0;
+        let mut added = 0;
+        while added < number_of_atoms {
+            if self.advance(added) { break }
+            if !self.walk(sticking, WALKER_MAX_STEPS, &mut steps) {
+                println!("No walker stuck within {} steps -- stopped after {} atoms", WALKER_MAX_STEPS, added);
+                break
+            }
+            added += 1;
+        }
+        steps
+    }
+
+    /// Release a walker and let it walk until its atom is added -- false if it gives up after the given number of steps.
+    fn walk(&mut self, sticking: &[f32; 12], max_steps: usize, steps: &mut usize) -> bool {
+        let mut rng = rand::thread_rng();
+        let (center, radius) = self.release_sphere();
+        let mut ijk = match self.release(center, radius) {
+            Some(ijk) => ijk,
+            None => return false,
+        };
+
+        for _step in 0..max_steps {
+            // stick (unless the atom can not be added, e.g. at the walls) or walk on
+            let n = self.number_of_neighbors(ijk);
+            if n > 0 && rng.gen::<f32>() < sticking[n-1] && self.add_atom(ijk) {
+                return true
+            }
+            let nn_ijk = self.neighbor(ijk, rng.gen_range(0, 12));
+            if self.walk_allowed(nn_ijk) {
+                ijk = nn_ijk;
+            }
+            *steps += 1;
+
+            // lost walkers start again, walkers far away from the crystal take a big jump
+            let xyz = self.lattice_at(ijk).get_xyz(ijk);
+            let d = distance(xyz, center);
+            if d > 3.0*radius {
+                ijk = match self.release(center, radius) {
+                    Some(ijk) => ijk,
+                    None => return false,
+                };
+            }
+            else if d > radius {
+                if let Some(jump_ijk) = self.jump(xyz, d - radius + WALKER_MARGIN/2.0) {
+                    ijk = jump_ijk;
+                }
+            }
+        }
+        false
+    }
+
+    /// Center and radius of the sphere the walkers are released on -- a few nm outside of the crystal.
+    fn release_sphere(&self) -> (XYZ, f32) {
+        let e = &self.extrema;
+        let center = XYZ{x: (e.x_min + e.x_max)/2.0, y: (e.y_min + e.y_max)/2.0, z: (e.z_min + e.z_max)/2.0};
+        let corner = XYZ{x: e.x_max, y: e.y_max, z: e.z_max};
+        (center, distance(corner, center) + WALKER_MARGIN)
+    }
+
+    /// A random empty position on the release sphere (only the upper half with a substrate) -- None if none is found in WALKER_MAX_STEPS attempts.
+    fn release(&self, center: XYZ, radius: f32) -> Option<IJK> {
+        (0..WALKER_MAX_STEPS)
+            .filter_map(|_| self.random_on_sphere(center, radius, self.substrate_pos > 1))
+            .find(|&ijk| self.walk_allowed(ijk))
+    }
+
+    /// A random lattice position on a sphere (or only its upper half) -- None if it is above or below the storage.
+    fn random_on_sphere(&self, center: XYZ, radius: f32, upper_half: bool) -> Option<IJK> {
+        let mut rng = rand::thread_rng();
+        let z: f32 = rng.gen_range(-1.0, 1.0);
+        let z = if upper_half { z.abs() } else { z };
+        let phi = 2.0*std::f32::consts::PI*rng.gen::<f32>();
+        let r = (1.0 - z*z).sqrt();
+        let xyz = XYZ{x: center.x + radius*r*phi.cos(), y: center.y + radius*r*phi.sin(), z: center.z + radius*z};
+        let z_min = self.lattice.get_xyz(IJK{k: 2, ..CENTER}).z;
+        let z_max = self.lattice.get_xyz(IJK{k: FLAKE_MAX.k - 3, ..CENTER}).z;
+        if xyz.z < z_min || xyz.z > z_max { return None }
+        Some(self.lattice.get_ijk(xyz))
+    }
+
+    /// A random position on a sphere around the walker -- for a free random walk every point on it is equally likely the first one reached.
+    fn jump(&self, xyz: XYZ, radius: f32) -> Option<IJK> {
+        if radius < 2.0*DIAMETER { return None }
+        self.random_on_sphere(xyz, radius, false).filter(|&ijk| self.walk_allowed(ijk))
+    }
+
+    /// Check if a walker can move to a position.
+    fn walk_allowed(&self, ijk: IJK) -> bool {
+        ijk.i > 1 && ijk.i < FLAKE_MAX.i - 2
+            && ijk.j > 1 && ijk.j < FLAKE_MAX.j - 2
+            && ijk.k > self.substrate_pos && ijk.k < FLAKE_MAX.k - 2
+            && self.bulk.get(ijk, Atom::Empty)
+    }
+}
+
+fn distance(a: XYZ, b: XYZ) -> f32 {
+    ((a.x - b.x).powi(2) + (a.y - b.y).powi(2) + (a.z - b.z).powi(2)).sqrt()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lattice::*;
+
+    #[test]
+    fn walkers_stick_to_the_crystal() {
+        let mut flake = Crystal::new(Lattice::new(vec![], DIAMETER));
+        flake.add_atom(CENTER);
+        flake.random_add(100);
+        let steps = flake.walker_add(200, &[1.0; 12]);
+        assert!(steps > 0);
+        assert_eq!(flake.bulk.number_of_atoms, 301);
+        assert!(flake.surface.list.iter().all(|&ijk| flake.number_of_neighbors(ijk) > 0));
+    }
+
+    #[test]
+    fn walkers_which_never_stick_give_up() {
+        let mut flake = Crystal::new(Lattice::new(vec![], DIAMETER));
+        flake.add_atom(CENTER);
+        let mut steps = 0;
+        assert!(!flake.walk(&[0.0; 12], 10_000, &mut steps));
+        assert_eq!(steps, 10_000);
+        assert_eq!(flake.bulk.number_of_atoms, 1);
+    }
+
+    #[test]
+    fn release_sphere_encloses_the_crystal() {
+        let mut flake = Crystal::new(Lattice::new(vec![], DIAMETER));
+        flake.add_atom(CENTER);
+        flake.random_add(1000);
+        let (center, radius) = flake.release_sphere();
+        for _ in 0..100 {
+            let ijk = flake.release(center, radius).unwrap();
+            assert!(flake.walk_allowed(ijk));
+            assert!(distance(flake.lattice.get_xyz(ijk), center) > radius - WALKER_MARGIN);
+        }
+    }
+}
@@ -15,6 +15,13 @@ use crate::parameters::*;
 use crate::lattice::*;
 use crate::storage::*;
 use crate::crystal::*;
+use crate::milling::*;
+use crate::scene_file::*;
+use crate::wulff::*;
+use crate::particles::*;
+use crate::protocol::*;
+use crate::stop_conditions::*;
+use crate::job::*;
 use crate::scene::*;
 use crate::planar_scene::*;
 #[cfg(feature = "sidebar")]
@@ -31,6 +38,15 @@ use nalgebra::Point3;
 #[cfg(target_arch = "wasm32")]
 use crate::println;
 
+// the presets are compiled into the binary so that they are also available in wasm
+const SPHERE: &str = include_str!("../scenes/sphere.toml");
+const CYLINDER: &str = include_str!("../scenes/cylinder.toml");
+const ROUNDED_MONOMER_ANTENNA: &str = include_str!("../scenes/rounded_monomer_antenna.toml");
+const DIPOLE_ANTENNA: &str = include_str!("../scenes/dipole_antenna.toml");
+const ROUNDED_DIPOLE_ANTENNA: &str = include_str!("../scenes/rounded_dipole_antenna.toml");
+const JORD_ANTENNA: &str = include_str!("../scenes/jord_antenna.toml");
+const ROUNDED_JORD_ANTENNA: &str = include_str!("../scenes/rounded_jord_antenna.toml");
+
 pub struct World {
     overlay: PlanarScene,
     scene: Scene,
@@ -40,6 +56,10 @@ pub struct World {
     i: u16,
     j: u16,
     k: u16,
+    #[cfg(not(target_arch = "wasm32"))]
+    scene_file_num: usize,
+    #[cfg(not(target_arch = "wasm32"))]
+    job: Option<Job>,
     #[cfg(feature = "sidebar")]
     sidebar: SideBar,
 }
@@ -59,9 +79,18 @@ impl State for World {
         if self.scene.show.hexagon {
             self.scene.draw_hexgon_outline(window);                     // lines have to be redrawn every time   
         }
-        if self.overlay.show {
+        #[cfg(not(target_arch = "wasm32"))]
+        let running = self.job.is_some();
+        #[cfg(target_arch = "wasm32")]
+        let running = false;
+        if self.overlay.show && !running {
             self.overlay.draw_scene(window, &self.flake);               // text has to be redrawn every time, too
             self.overlay.update_indicators(window, &self.flake);        // update length of indicators
+            self.overlay.update_monolayer(window, &self.flake);         // top view of a monolayer island
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(job) = &self.job {
+            self.overlay.draw_job(window, job);                         // progress of a running job instead
         }
         if self.overlay.show_help {                                                  
             self.overlay.show_help(window);                             // draw help overlay
@@ -85,6 +114,13 @@ impl State for World {
             }
         }
 
+        // while a job is running it can only be cancelled
+        #[cfg(not(target_arch = "wasm32"))]
+        if running {
+            self.poll_job(window, key);
+            return
+        }
+
         // handle all virtual and real key events
         match key { 
             // special actions
@@ -93,7 +129,7 @@ impl State for World {
             Key::P =>       self.flake.next_prob_list(),                // change probabilities
             
             #[cfg(not(target_arch = "wasm32"))]
-            Key::Comma =>   self.statistics(window),                    // some statistics
+            Key::Comma =>   self.statistics(),                          // some statistics
          
             #[cfg(not(target_arch = "wasm32"))]
             Key::M =>   self.flake.save(),                              // save flake
@@ -112,16 +148,32 @@ impl State for World {
             // add some special geometries
             Key::L =>       self.add_gold_layer(window),                // add gold layer
             Key::O =>       self.add_dirt_layer(window),                // add dirt layer on top
-            // Key::Z =>       self.add_box(window),                       // add box     
-            Key::Home =>    self.add_sphere(window),                    // add sphere     
-            Key::End =>     self.add_cylinder(window),                  // add cylinder                        
-            Key::Delete =>  self.add_rounded_monomer_antenna(window),   // add rounded monomer antenna
-            Key::I =>       self.add_rounded_dipole_antenna(window),    // add rounded Dipole antenna  
-            Key::K =>       self.add_dipole_antenna(window),            // add Dipole antenna
-            Key::U =>       self.add_rounded_jord_antenna(window),      // add rounded Jord antenna
-            Key::J =>       self.add_jord_antenna(window),              // add Jord antenna
+            Key::Home =>    self.add_preset(window, SPHERE),                    // add sphere     
+            Key::End =>     self.add_preset(window, CYLINDER),                  // add cylinder                        
+            Key::Delete =>  self.add_preset(window, ROUNDED_MONOMER_ANTENNA),   // add rounded monomer antenna
+            Key::I =>       self.add_preset(window, ROUNDED_DIPOLE_ANTENNA),    // add rounded Dipole antenna  
+            Key::K =>       self.add_preset(window, DIPOLE_ANTENNA),            // add Dipole antenna
+            Key::U =>       self.add_preset(window, ROUNDED_JORD_ANTENNA),      // add rounded Jord antenna
+            Key::J =>       self.add_preset(window, JORD_ANTENNA),              // add Jord antenna
+            #[cfg(not(target_arch = "wasm32"))]
+            Key::Z =>       self.load_next_scene_file(window),                  // load next scene file from the scene folder
             Key::Down =>    self.add_remove_substrate(window),          // add/remove substrat below lowest vacancies layer    
-            // Key::N =>       self.add_column(window),                    // add column step by step    
+            Key::N =>       self.mill_gap(window),                      // mill a gap through the middle
+            Key::Tab =>     self.anneal(window),                        // anneal the crystal
+            Key::Insert =>  self.add_wulff_shape(window),               // replace the crystal by its Wulff shape
+            Key::F10 =>     self.add_seeds(window),                     // restart with several random seeds
+            Key::F11 =>     self.switch_supply(),                       // finite precursor supply on/off
+            Key::F12 =>     self.switch_growth_mode(),                  // vacancy lists or random walkers
+            Key::Right =>   self.ripen(window),                         // Ostwald ripening of the particles
+            Key::Left =>    self.switch_flux(),                         // directional deposition on/off
+            Key::Equals =>  self.switch_alloy(),                        // alloy growth on/off
+            Key::Slash =>   self.switch_impurities(),                   // incorporation of impurities on/off
+            Key::Key0 =>    self.switch_screw_dislocation(window),      // screw dislocation at the current atom on/off
+            #[cfg(not(target_arch = "wasm32"))]
+            Key::Backslash => self.run_protocol(window, PROTOCOL_FILE), // execute the stages of the protocol file
+            Key::RBracket => self.grow_to_target(window, STOP_TARGET),  // grow until the target geometry is reached
+            Key::LBracket => self.switch_film(window),                  // periodic thin film on/off
+            Key::Apostrophe => self.switch_monolayer(window),           // monolayer growth on a substrate on/off
             
             // tweak stacking
             Key::Up =>      self.reset_stacking(window),                // reset stacking
@@ -194,6 +246,10 @@ impl World {
             i,
             j,
             k,
+            #[cfg(not(target_arch = "wasm32"))]
+            scene_file_num: 0,
+            #[cfg(not(target_arch = "wasm32"))]
+            job: None,
             #[cfg(feature = "sidebar")]
             sidebar,
         }
@@ -220,6 +276,7 @@ impl World {
         self.overlay.show = !self.overlay.show;
         self.overlay.scene.set_visible(self.overlay.show);
         self.overlay.layers.set_visible(self.overlay.show);
+        self.overlay.monolayer.set_visible(self.overlay.show);
     }
 
     fn highlight_current_atom(&mut self, window: &mut Window) {
@@ -249,11 +306,13 @@ impl World {
                 //         &mut self.scene.surface, &self.lattice.position(ijk), ATOM_COLORS[(self.lattice.stacking.pos[ijk.k as usize].rem_euclid(3)) as usize])    
                 // }
                 self.flake.surface.list.clone().iter().for_each(|&ijk| add_atom_to_group(
-                    &mut self.scene.surface, &self.lattice.position(ijk), ATOM_COLORS[(self.lattice.stacking.pos[ijk.k as usize].rem_euclid(3)) as usize])
+                    &mut self.scene.surface, &self.flake.lattice_at(ijk).position(ijk),
+                    if self.flake.bulk.get(ijk, Atom::Alloy) { ALLOY_COLOR } else { ATOM_COLORS[(self.flake.lattice_at(ijk).stacking.pos[ijk.k as usize].rem_euclid(3)) as usize] })
                 );   
             }
             else {
-                self.flake.surface.list.clone().iter().for_each(|&ijk| add_atom_to_group(&mut self.scene.surface, &self.lattice.position(ijk), GOLD) );
+                self.flake.surface.list.clone().iter().for_each(|&ijk| add_atom_to_group(&mut self.scene.surface, &self.flake.lattice_at(ijk).position(ijk),
+                    if self.flake.bulk.get(ijk, Atom::Alloy) { ALLOY_COLOR } else { GOLD }) );
 
             }
             self.scene.surface.set_visible(true);
@@ -330,114 +389,184 @@ impl World {
     //     self.scene.update_boundaries(window, &self.flake);
     // }
 
-    fn add_sphere(&mut self, window: &mut Window) {
-        self.flake.clear();
-        let pos = XYZ{x: 0.0, y: 0.0, z: 0.0};
-        self.flake.add_sphere(pos, 5.0);
-        self.camera = ArcBall::new(Point3::new(-45.0, 22.5, 0.0), Point3::origin());
+    fn add_preset(&mut self, window: &mut Window, text: &str) {
+        match SceneFile::parse(text) {
+            Ok(scene_file) => self.load_scene(window, &scene_file),
+            Err(error) => println!("{}", error),
+        }
+    }
+
+    pub fn load_scene(&mut self, window: &mut Window, scene_file: &SceneFile) {
+        self.flake.load_scene(scene_file);
+        self.lattice = self.flake.lattice.clone();
+        self.scene.lattice = self.lattice.clone();
+        if let Some([x, y, z]) = scene_file.camera {
+            self.camera = ArcBall::new(Point3::new(x, y, z), Point3::origin());
+        }
+        self.scene.show.substrate = self.flake.substrate_pos > 1;
         self.scene.update_surface(window, &self.flake);
+        self.scene.update_dirt(window, &self.flake);
         self.scene.update_vacancies(window, &self.flake, false);
+        self.scene.update_boundaries(window, &self.flake);
+        self.scene.substrate.set_visible(self.scene.show.substrate);
     }
 
-    fn add_cylinder(&mut self, window: &mut Window) {
-        self.flake.clear();
-        let pos = XYZ{x: 0.0, y: 0.0, z: 0.0};
-        self.flake.add_cylinder(pos, 20.0, 5.0);
-        self.camera = ArcBall::new(Point3::new(-45.0, 22.5, 0.0), Point3::origin());
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_scene_file(&mut self, window: &mut Window, path: &str) {
+        match SceneFile::load(path) {
+            Ok(scene_file) => {
+                self.load_scene(window, &scene_file);
+                println!("Scene loaded: {}", path);
+            },
+            Err(error) => println!("{}", error),
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn load_next_scene_file(&mut self, window: &mut Window) {
+        // all scene files in the scene folder in alphabetical order
+        let mut paths: Vec<String> = match std::fs::read_dir(SCENE_DIR) {
+            Ok(dir) => dir.filter_map(|entry| entry.ok())
+                          .map(|entry| entry.path().to_string_lossy().into_owned())
+                          .filter(|path| path.ends_with(".toml"))
+                          .collect(),
+            Err(_) => Vec::new(),
+        };
+        paths.sort();
+        if paths.is_empty() {
+            println!("No scene files found in {}", SCENE_DIR);
+        }
+        else {
+            self.scene_file_num = self.scene_file_num.rem_euclid(paths.len());
+            self.load_scene_file(window, &paths[self.scene_file_num]);
+            self.scene_file_num += 1;
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn run_protocol(&mut self, window: &mut Window, path: &str) {
+        let protocol = match Protocol::load(path) {
+            Ok(protocol) => protocol,
+            Err(error) => { println!("{}", error); return }
+        };
+        println!("Protocol loaded: {}", path);
+        let start = Instant::now();
+        let atoms = self.flake.bulk.number_of_atoms;
+        let logs = self.flake.run_protocol(&protocol);
+        self.flake.save_protocol(&logs);
+        self.overlay.added_atoms = self.flake.bulk.number_of_atoms.saturating_sub(atoms);
+        self.overlay.duration = start.elapsed();
+
+        // stacking faults and the substrate might have changed
+        self.lattice = self.flake.lattice.clone();
+        self.scene.lattice = self.lattice.clone();
+        if self.flake.substrate_pos > 1 && !self.scene.show.substrate {
+            self.scene.add_substrate(&mut self.flake);
+            self.scene.show.substrate = true;
+            self.scene.substrate.set_visible(self.scene.show.substrate);
+        }
         self.scene.update_surface(window, &self.flake);
+        self.scene.update_dirt(window, &self.flake);
         self.scene.update_vacancies(window, &self.flake, false);
+        self.scene.update_boundaries(window, &self.flake);
     }
 
-    fn add_rounded_monomer_antenna(&mut self, window: &mut Window) {
-        self.flake.clear();
-        // antenna arm
-        let pos = XYZ{x: 0.0, y: 0.0, z: -3.0};
-        self.flake.add_rounded_box(pos, 20.0, 10.0, 6.0, 3.0);
-        // other stuff
-        self.camera = ArcBall::new(Point3::new(-45.0, 22.5, 0.0), Point3::origin());
+    fn mill_gap(&mut self, window: &mut Window) {
+        // a 3nm wide gap across the whole structure
+        let min = XYZ{x: -1.5, y: self.flake.extrema.y_min - DIAMETER, z: 0.0};
+        let max = XYZ{x:  1.5, y: self.flake.extrema.y_max + DIAMETER, z: 0.0};
+        let (removed, redeposited) = self.flake.mill(&Beam::default(), &BeamPath::Raster{min, max});
+        println!("Milled gap: {} atoms removed, {} redeposited", removed, redeposited);
         self.scene.update_surface(window, &self.flake);
+        self.scene.update_dirt(window, &self.flake);
         self.scene.update_vacancies(window, &self.flake, false);
+        self.scene.update_boundaries(window, &self.flake);
     }
 
-    fn add_dipole_antenna(&mut self, window: &mut Window) {
-        self.flake.clear();
-        // left arm
-        let pos = XYZ{x: -10.0, y: 0.0, z: 0.0};
-        self.flake.add_box(pos, 14.0, 6.0, 6.0, Atom::Gold);
-        // // left arm scond level
-        // let pos = XYZ{x: -15.0, y: 0.0, z: 3.5};
-        // self.flake.add_box(pos, 18.0, 8.0, 1.0, Atom::Gold);
-        // right arm
-        let pos = XYZ{x: 10.0, y: 0.0, z: 0.0};
-        self.flake.add_box(pos, 14.0, 6.0, 6.0, Atom::Gold);
-        // other stuff
-        self.camera = ArcBall::new(Point3::new(-45.0, 22.5, 0.0), Point3::origin());
+    fn anneal(&mut self, window: &mut Window) {
+        let start = Instant::now();
+        let energies = self.flake.anneal(ANNEAL_TEMPERATURE, ANNEAL_SWEEPS, BOND_ENERGY);
+        if let (Some(first), Some(last)) = (energies.first(), energies.last()) {
+            println!("Annealed at {}K for {} sweeps: surface energy {:.1}eV -> {:.1}eV", ANNEAL_TEMPERATURE, ANNEAL_SWEEPS, first, last);
+        }
+        self.overlay.added_atoms = 0;
+        self.overlay.duration = start.elapsed();
         self.scene.update_surface(window, &self.flake);
         self.scene.update_vacancies(window, &self.flake, false);
+        self.scene.update_boundaries(window, &self.flake);
     }
 
-    fn add_rounded_dipole_antenna(&mut self, window: &mut Window) {
-        self.flake.clear();
-        // left arm
-        let pos = XYZ{x: -15.0, y: 0.0, z: -3.0};
-        self.flake.add_rounded_box(pos, 20.0, 10.0, 6.0, 3.0);
-        // right arm
-         let pos = XYZ{x: 15.0, y: 0.0, z: -3.0};
-        self.flake.add_rounded_box(pos, 20.0, 10.0, 7.0, 3.5);
-        // other stuff
-        self.camera = ArcBall::new(Point3::new(-45.0, 22.5, 0.0), Point3::origin());
+    fn add_wulff_shape(&mut self, window: &mut Window) {
+        // the equilibrium shape with the same number of atoms as the current crystal
+        let atoms = self.flake.bulk.number_of_gold;
+        let wulff = Wulff::default();
+        self.flake.add_wulff_shape(&wulff, atoms);
+        let [..,r] = self.flake.get_size();
+        println!("Wulff shape with {} atoms -- aspect ratio: {:.2}", self.flake.bulk.number_of_atoms, r);
+        self.scene.show.substrate = self.flake.substrate_pos > 1;
         self.scene.update_surface(window, &self.flake);
+        self.scene.update_dirt(window, &self.flake);
         self.scene.update_vacancies(window, &self.flake, false);
+        self.scene.update_boundaries(window, &self.flake);
+        self.scene.substrate.set_visible(self.scene.show.substrate);
     }
 
-    fn add_jord_antenna(&mut self, window: &mut Window) {
-        self.flake.clear();
-        // left arm
-        let pos = XYZ{x: -15.0, y: 0.0, z: 0.0};
-        self.flake.add_box(pos, 20.0, 10.0, 6.0, Atom::Gold);
-        // right arm
-        let pos = XYZ{x: 15.0, y: 0.0, z: 0.0};
-        self.flake.add_box(pos, 20.0, 10.0, 6.0, Atom::Gold);
-        // left connector;
-        let pos = XYZ{x: -15.0, y: 25.0, z: -1.5};
-        self.flake.add_box(pos, 5.0, 40.0, 3.0, Atom::Gold);
-        // right connector;
-        let pos = XYZ{x: 15.0, y: -25.0, z: -1.5};
-        self.flake.add_box(pos, 5.0, 40.0, 3.0, Atom::Gold);
-        // other stuff
-        self.camera = ArcBall::new(Point3::new(-60.0, 60.0, 0.0), Point3::origin());
+    fn add_seeds(&mut self, window: &mut Window) {
+        let seeds = random_seeds(SEEDS, SEED_RADIUS, INDEPENDENT_STACKINGS, &self.flake.lattice);
+        self.flake.add_seeds(&seeds);
+        println!("Restarted with {} seeds", seeds.len());
+        self.overlay.added_atoms = 0;
         self.scene.update_surface(window, &self.flake);
+        self.scene.update_dirt(window, &self.flake);
         self.scene.update_vacancies(window, &self.flake, false);
+        self.scene.update_boundaries(window, &self.flake);
     }
 
-    fn add_rounded_jord_antenna(&mut self, window: &mut Window) {
-        self.flake.clear();
-        // left arm
-        let pos = XYZ{x: -14.0, y: 0.0, z: -3.0};
-        self.flake.add_rounded_box(pos, 20.0, 10.0, 6.0, 3.0);
-        // right arm
-        let pos = XYZ{x: 14.0, y: 0.0, z: -3.0};
-        self.flake.add_rounded_box(pos, 20.0, 10.0, 6.0, 3.0);
-        // left connector
-        let pos = XYZ{x: -14.0, y: 25.0, z: -3.0};
-        self.flake.add_rounded_box(pos, 5.0, 42.0, 3.0, 2.0);
-        // right connector
-        let pos = XYZ{x: 14.0, y: -25.0, z: -3.0};
-        self.flake.add_rounded_box(pos, 5.0, 42.0, 3.0, 2.0);
-        // left protector
-        let pos = XYZ{x: -14.0, y: 25.0, z: -1.5};
-        self.flake.add_box(pos, 6.0, 44.0, 4.0, Atom::Dirt);
-        // right protector
-        let pos = XYZ{x: 14.0, y: -25.0, z: -1.5};
-        self.flake.add_box(pos, 6.0, 44.0, 4.0, Atom::Dirt);
-        // waist for the left arm
-        let pos = XYZ{x: -14.0, y: 0.0, z: 0.0};
-        self.flake.add_box(pos, 10.0, 12.0, 7.0, Atom::Dirt);
-        // other stuff
-        self.camera = ArcBall::new(Point3::new(-60.0, 60.0, 0.0), Point3::origin());
+    fn switch_supply(&mut self) {
+        self.flake.switch_supply();
+        println!("Finite precursor supply: {}", if self.flake.supply.is_some() { "on" } else { "off" });
+    }
+
+    fn switch_growth_mode(&mut self) {
+        self.flake.switch_growth_mode();
+        println!("Growth mode: {:?}", self.flake.growth_mode);
+    }
+
+    fn ripen(&mut self, window: &mut Window) {
+        let start = Instant::now();
+        let steps = self.flake.ripen(RIPENING_TEMPERATURE, RIPENING_SWEEPS, BOND_ENERGY);
+        if let (Some(first), Some(last)) = (steps.first(), steps.last()) {
+            println!("Ripened at {}K for {} sweeps: particles {:?} -> {:?} atoms, {} monomers", RIPENING_TEMPERATURE, RIPENING_SWEEPS, first.atoms, last.atoms, last.monomers);
+        }
+        self.overlay.added_atoms = 0;
+        self.overlay.duration = start.elapsed();
         self.scene.update_surface(window, &self.flake);
-        self.scene.update_dirt(window, &self.flake);
         self.scene.update_vacancies(window, &self.flake, false);
+        self.scene.update_boundaries(window, &self.flake);
+    }
+
+    fn switch_flux(&mut self) {
+        self.flake.switch_flux();
+        match self.flake.flux {
+            Some(flux) => println!("Directional flux: tilt {}°, azimuth {}°, spread {}°", flux.tilt, flux.azimuth, flux.spread),
+            None => println!("Directional flux: off"),
+        }
+    }
+
+    fn switch_alloy(&mut self) {
+        self.flake.switch_alloy();
+        match &self.flake.alloy {
+            Some(alloy) => println!("Alloy growth: bonds {:?}, feed {:?}", alloy.bonds, alloy.feed),
+            None => println!("Alloy growth: off"),
+        }
+    }
+
+    fn switch_impurities(&mut self) {
+        self.flake.switch_impurities();
+        match self.flake.impurities {
+            Some(impurities) => println!("Impurities: probabilities {:?}, desorption {}", impurities.probabilities, impurities.desorption),
+            None => println!("Impurities: off"),
+        }
     }
 
     fn add_remove_substrate(&mut self, window: &mut Window) {
@@ -460,6 +589,7 @@ impl World {
 
     fn reset_stacking(&mut self, window: &mut Window) {
         self.lattice = Lattice::new([].to_vec(), DIAMETER);
+        self.lattice.periodic = self.flake.lattice.periodic;
         self.flake.lattice = self.lattice.clone();
         self.flake.update_vacancies();
         self.scene.lattice = self.lattice.clone();
@@ -468,20 +598,59 @@ impl World {
         println!("Stacking faults {:?}", self.lattice.stacking_faults);
     }
     
-    fn add_stacking_fault_top(&mut self, window: &mut Window) {
-        let mut stacking = self.flake.lattice.stacking_faults.clone();
-        let new_fault = self.flake.extrema_ijk.z_max.k + 1;
-        match stacking.binary_search(&(new_fault)) {
-            Ok(_pos) => {} // element already in vector @ `pos` 
-            Err(pos) => {
-                stacking.insert(pos, new_fault);
-                self.lattice = Lattice::new(stacking, DIAMETER);
-                self.flake.lattice = self.lattice.clone();
-                self.flake.update_vacancies();
-                self.scene.lattice = self.lattice.clone();
+    fn switch_screw_dislocation(&mut self, window: &mut Window) {
+        let screw = match self.flake.lattice.screw {
+            Some(_) => None,
+            None => Some(Screw{i: self.i, j: self.j, burgers: SCREW_BURGERS}),
+        };
+        self.flake.set_screw(screw);
+        self.lattice = self.flake.lattice.clone();
+        self.scene.lattice = self.lattice.clone();
+        self.scene.update_vacancies(window, &self.flake, false);
+        println!("Screw dislocation {:?}", self.flake.lattice.screw);
+    }
+
+    fn switch_monolayer(&mut self, window: &mut Window) {
+        self.flake.switch_monolayer();
+        match &self.flake.monolayer {
+            Some(monolayer) => {
+                println!("Monolayer growth in layer {}", monolayer.k);
+                self.back(window);
+                self.scene.show.substrate = true;
+                self.scene.update_boundaries(window, &self.flake);
+            },
+            None => {
                 self.scene.update_vacancies(window, &self.flake, false);
+                println!("Monolayer growth: off");
             },
         }
+        self.overlay.update_monolayer(window, &self.flake);
+    }
+
+    fn switch_film(&mut self, window: &mut Window) {
+        let period = match self.flake.lattice.periodic {
+            Some(_) => None,
+            None => Some(FILM_PERIOD),
+        };
+        self.flake.set_periodic(period);
+        self.lattice = self.flake.lattice.clone();
+        self.scene.lattice = self.lattice.clone();
+        self.i = CENTER.i;
+        self.j = CENTER.j;
+        self.k = CENTER.k;
+        self.overlay.added_atoms = 0;
+        self.scene.update_surface(window, &self.flake);
+        self.scene.update_dirt(window, &self.flake);
+        self.scene.update_vacancies(window, &self.flake, false);
+        self.scene.update_boundaries(window, &self.flake);
+        println!("Periodic film: {:?}", period);
+    }
+
+    fn add_stacking_fault_top(&mut self, window: &mut Window) {
+        self.flake.add_stacking_fault(self.flake.extrema_ijk.z_max.k + 1);
+        self.lattice = self.flake.lattice.clone();
+        self.scene.lattice = self.lattice.clone();
+        self.scene.update_vacancies(window, &self.flake, false);
         println!("Stacking faults {:?}", self.lattice.stacking_faults);
     }
 
@@ -497,7 +666,7 @@ impl World {
                     Ok(_pos) => {stacking.remove(0);} 
                     Err(pos) => {stacking.insert(pos, 0)}
                 }
-                self.lattice = Lattice::new(stacking, DIAMETER);
+                self.lattice = self.flake.lattice.with_stacking_faults(stacking);
                 self.flake.lattice = self.lattice.clone();
                 self.flake.update_vacancies();
                 self.scene.lattice = self.lattice.clone();
@@ -542,21 +711,42 @@ impl World {
         self.scene.update_boundaries(window, &self.flake);
     }
 
+    fn grow_to_target(&mut self, window: &mut Window, target: StopCondition) {
+        let start = Instant::now();
+        println!("Growing until {:?}... ", target);
+        let added_atoms = self.flake.grow_until(&target);
+        println!(" ...finished with {} atoms", self.flake.bulk.number_of_atoms.separated_string());
+        self.overlay.added_atoms = added_atoms;
+        self.overlay.duration = start.elapsed();
+        self.scene.update_surface(window, &self.flake);
+        self.scene.update_vacancies(window, &self.flake, false);
+        self.scene.update_boundaries(window, &self.flake);
+    }
+
     pub fn add_random_atoms(&mut self, window: &mut Window, show_process: bool, number: usize) {
         let start = Instant::now();
         let mut ijk = IJK{i: self.i, j: self.j, k:self.k};
         if show_process {
             for _index in 0..number {
-                ijk = self.flake.random_vacancy();
-                self.flake.add_atom(ijk);
+                ijk = self.flake.random_vacancy().unwrap_or(CENTER);         // an empty crystal starts in the center
+                self.flake.attach(ijk);
             }                   
             self.scene.update_surface(window, &self.flake);
             self.scene.update_vacancies(window, &self.flake, false);
         } 
         else {
             println!("Calculation {} atoms... ", number.separated_string());
-            self.flake.random_add(number);
-            println!(" ...finished");
+            #[cfg(not(target_arch = "wasm32"))]
+            return self.start_job(JobKind::Growth, number, move |flake| {
+                let atoms = flake.bulk.number_of_atoms;
+                flake.random_add(number);
+                flake.bulk.number_of_atoms.saturating_sub(atoms)
+            });
+            #[cfg(target_arch = "wasm32")]
+            {
+                self.flake.random_add(number);
+                println!(" ...finished");
+            }
         }
         self.overlay.added_atoms = number;
         self.overlay.duration = start.elapsed();
@@ -567,11 +757,49 @@ impl World {
     }
 
     #[cfg(not(target_arch = "wasm32"))]
-    fn statistics(&mut self, window: &mut Window) {
-        let start = Instant::now();
-        let added_atoms = self.flake.statistics();
-        self.overlay.added_atoms = added_atoms;
-        self.overlay.duration = start.elapsed();
+    fn statistics(&mut self) {
+        let total = if STOP_CONDITIONS.is_empty() { NUMBER_OF_CYCLES*STOP_MARKS[STOP_MARKS.len()-1] } else { 0 };
+        self.start_job(JobKind::Statistics, total, |flake| flake.statistics());
+    }
+
+    /// Move the crystal into a background job -- the world keeps an empty crystal in the memory until the job is finished
+    /// (a BULK_FILE stays with the crystal of the job).
+    #[cfg(not(target_arch = "wasm32"))]
+    fn start_job<F>(&mut self, kind: JobKind, total: usize, function: F) where F: FnOnce(&mut Crystal) -> usize + Send + 'static {
+        let placeholder = Crystal::with_bulk(self.flake.lattice.clone(), Bulk::in_memory());
+        let flake = std::mem::replace(&mut self.flake, placeholder);
+        self.job = Some(Job::spawn(kind, flake, total, function));
+        self.overlay.scene.set_visible(false);
+    }
+
+    /// Cancel the running job with the back key and take the crystal back when the job is finished.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn poll_job(&mut self, window: &mut Window, key: Key) {
+        let finished = match &self.job {
+            Some(job) => {
+                if key == Key::Back { job.cancel() }
+                job.finished()
+            },
+            None => false,
+        };
+        if !finished { return }
+        if let Some(job) = self.job.take() {
+            let (kind, duration, cancelled) = (job.kind, job.start.elapsed(), job.progress.cancelled.load(std::sync::atomic::Ordering::Relaxed));
+            let (flake, added_atoms) = job.join();
+            self.flake = flake;
+            println!(" ...{} after {:#?}", if cancelled { "cancelled" } else { "finished" }, duration);
+            self.overlay.added_atoms = added_atoms;
+            self.overlay.duration = duration;
+            self.overlay.scene.set_visible(self.overlay.show);
+            match kind {
+                JobKind::Growth => self.scene.update_boundaries(window, &self.flake),
+                JobKind::Statistics => self.show_statistics(window),
+            }
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn show_statistics(&mut self, window: &mut Window) {
         // switch representation to hexagon (to not end in a freeze)
         window.remove_node(&mut self.scene.surface);
         self.scene.surface.set_visible(false);
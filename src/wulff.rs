@@ -0,0 +1,196 @@
+/*!
+Wulff construction as thermodynamic reference for the grown flakes
+
+The equilibrium shape is the inner envelope of all {111}, {100} and {110} planes at distances proportional to their facet energies.
+Twin planes are taken into account via the stacking of the lattice: above (or below) a stacking fault the lattice is mirrored,
+so every layer uses the facet normals of its own twin domain around a common center ("modified" Wulff construction).
+An adhesion energy to a substrate truncates the bottom {111} facet accordingly (Winterbottom construction).
+
+The shape is filled into the crystal for a requested number of atoms, so that its size and aspect ratio are directly comparable to grown flakes.
+*/
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::{io::Write, fs::File};
+
+use crate::helpers::*;
+use crate::parameters::*;
+use crate::crystal::*;
+
+
+/// Facet energies of the {111}, {100} and {110} planes and the adhesion energy to a substrate (if any).
+#[derive(Copy,Clone,Debug)]
+pub struct Wulff {
+    pub energies: [f32; 3],
+    pub adhesion: Option<f32>,
+}
+
+impl Default for Wulff {
+    fn default() -> Self {
+        Wulff{ energies: WULFF_ENERGIES, adhesion: WULFF_ADHESION }
+    }
+}
+
+impl Wulff {
+    /// All facet normals in the coordinate system of the lattice (z is the [111] direction) together with their distances for lambda = 1.
+    /// Mirrored is used for the twinned domains.
+    fn facets(&self, mirrored: bool) -> Vec<(XYZ, f32)> {
+        // the cubic axes expressed in the lattice coordinates
+        let s = if mirrored { -1.0 } else { 1.0 };
+        let ex = [1.0/2f32.sqrt(), -1.0/2f32.sqrt(), 0.0];
+        let ey = [-s/6f32.sqrt(), -s/6f32.sqrt(), 2.0*s/6f32.sqrt()];
+        let ez = [1.0/3f32.sqrt(), 1.0/3f32.sqrt(), 1.0/3f32.sqrt()];
+
+        let mut facets = Vec::new();
+        let mut add_family = |directions: Vec<[f32; 3]>, energy: f32| {
+            for d in directions {
+                let norm = (d[0]*d[0] + d[1]*d[1] + d[2]*d[2]).sqrt();
+                let n = XYZ{x: (d[0]*ex[0] + d[1]*ex[1] + d[2]*ex[2])/norm,
+                            y: (d[0]*ey[0] + d[1]*ey[1] + d[2]*ey[2])/norm,
+                            z: (d[0]*ez[0] + d[1]*ez[1] + d[2]*ez[2])/norm};
+                facets.push((n, energy));
+            }
+        };
+
+        // {111}
+        let mut directions = Vec::new();
+        for &a in [-1.0, 1.0].iter() { for &b in [-1.0, 1.0].iter() { for &c in [-1.0, 1.0].iter() {
+            directions.push([a, b, c]);
+        }}}
+        add_family(directions, self.energies[0]);
+
+        // {100}
+        let mut directions = Vec::new();
+        for axis in 0..3 { for &a in [-1.0, 1.0].iter() {
+            let mut d = [0.0; 3];
+            d[axis] = a;
+            directions.push(d);
+        }}
+        add_family(directions, self.energies[1]);
+
+        // {110}
+        let mut directions = Vec::new();
+        for axis in 0..3 { for &a in [-1.0, 1.0].iter() { for &b in [-1.0, 1.0].iter() {
+            let mut d = [a, b, 0.0];
+            d.rotate_right(axis);
+            directions.push(d);
+        }}}
+        add_family(directions, self.energies[2]);
+
+        // the bottom (111) facet is shifted by the adhesion energy
+        if let Some(adhesion) = self.adhesion {
+            for facet in facets.iter_mut() {
+                if facet.0.z < -0.999 { facet.1 -= adhesion }
+            }
+        }
+        facets
+    }
+}
+
+
+impl Crystal {
+    /// Fill the crystal with the Wulff shape containing (approximately) the given number of atoms -- returns the used lambda.
+    pub fn add_wulff_shape(&mut self, wulff: &Wulff, atoms: usize) -> f32 {
+        self.clear();
+        self.substrate_pos = 1;
+        let facets = [wulff.facets(false), wulff.facets(true)];
+
+        // find lambda by bisection -- first get an upper bound
+        let mut lambda_min = 0.0;
+        let mut lambda_max = DIAMETER;
+        while self.wulff_positions(&facets, lambda_max).len() < atoms {
+            lambda_min = lambda_max;
+            lambda_max *= 2.0;
+        }
+        for _step in 0..20 {
+            let lambda = (lambda_min + lambda_max)/2.0;
+            if self.wulff_positions(&facets, lambda).len() < atoms { lambda_min = lambda } else { lambda_max = lambda }
+        }
+
+        // and fill the shape
+        for ijk in self.wulff_positions(&facets, lambda_max) {
+            self.add_atom(ijk);
+        }
+
+        // put the substrate below the shape
+        if wulff.adhesion.is_some() {
+            self.substrate_pos = self.extrema_ijk.z_min.k - 1;
+            self.update_vacancies();
+        }
+        lambda_max
+    }
+
+    /// All lattice positions within the Wulff shape for a given lambda.
+    fn wulff_positions(&mut self, facets: &[Vec<(XYZ, f32)>; 2], lambda: f32) -> Vec<IJK> {
+        let energy_max = facets[0].iter().map(|facet| facet.1).fold(0.0, f32::max);
+        let radius = lambda*energy_max*3f32.sqrt();
+        let min = XYZ{x: -radius, y: -radius, z: -radius};
+        let max = XYZ{x:  radius, y:  radius, z:  radius};
+
+        let mut positions = Vec::new();
+        self.lattice.init_box_iter(min, max);
+        for ijk in self.lattice.clone() {
+            let pos = self.lattice.get_xyz(ijk);
+            let mirrored = self.lattice.stacking.shift_j[ijk.k as usize] < 0;
+            let inside = facets[mirrored as usize].iter().all(|(n, energy)| pos.x*n.x + pos.y*n.y + pos.z*n.z <= lambda*energy);
+            if inside { positions.push(ijk) }
+        }
+        positions
+    }
+
+    /// Wulff shapes for all STOP_MARKS written to a file in the same format as the statistics module.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn wulff_statistics(&mut self, wulff: &Wulff) {
+
+        // create file and write header
+        let filename = format!("{}_wulff_stack{:?}_energies{:?}_adhesion{:?}.csv", FILENAME, self.lattice.stacking_faults, wulff.energies, wulff.adhesion);
+        let mut f = File::create(filename).expect("Unable to create file");
+        writeln!(f, "wulff energies: {:?} adhesion: {:?} stacking: {:?}", wulff.energies, wulff.adhesion, self.lattice.stacking_faults).expect("Unable to write in file");
+        writeln!(f, "atoms, aspect ratio, k_min, k_max, len1, len2, len ratio, boundary").expect("Unable to write in file");
+
+        for &atoms in STOP_MARKS.iter() {
+            self.add_wulff_shape(wulff, atoms);
+            let [..,r] = self.get_size();
+            let [ax,ay,bx,by,cx,cy,..] = self.get_hexagon();
+            let len1 = ((ax-bx).powi(2) + (ay-by).powi(2)).sqrt();
+            let len2 = ((cx-bx).powi(2) + (cy-by).powi(2)).sqrt();
+            println!("{:>15} atoms (Wulff) -- aspect ratio: {:>4.1}, length ratio: {:>5.3}", self.bulk.number_of_atoms, r, len1/(len1+len2));
+            writeln!(f, "{}, {}, {}, {}, {}, {}, {}, {}", self.bulk.number_of_atoms, r, self.bulk.k_min, self.bulk.k_max, len1, len2, len1/(len1+len2), self.boundary.is_some() as u8).unwrap();
+        }
+        println!(" ...finished");
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lattice::*;
+
+    #[test]
+    fn facet_normals_are_unit_vectors() {
+        let wulff = Wulff{ energies: [1.0, 1.1, 1.2], adhesion: Some(0.5) };
+        for &mirrored in [false, true].iter() {
+            let facets = wulff.facets(mirrored);
+            assert_eq!(facets.len(), 8 + 6 + 12);
+            for (n, _) in facets.iter() {
+                assert!((n.x*n.x + n.y*n.y + n.z*n.z - 1.0).abs() < 1e-5);
+            }
+            // only the bottom (111) facet is lowered by the adhesion
+            assert_eq!(facets.iter().filter(|facet| facet.1 == 0.5).count(), 1);
+            assert!(facets.iter().any(|facet| facet.0.z > 0.999 && facet.1 == 1.0));
+        }
+    }
+
+    #[test]
+    fn wulff_shape_has_the_requested_size() {
+        let mut flake = Crystal::new(Lattice::new(vec![], DIAMETER));
+        let wulff = Wulff{ energies: [1.0, 1.1, 1.2], adhesion: None };
+        flake.add_wulff_shape(&wulff, 5000);
+        let atoms = flake.bulk.number_of_atoms;
+        assert!((5000..5500).contains(&atoms), "{} atoms", atoms);
+
+        // with adhesion the substrate is right below the shape
+        flake.add_wulff_shape(&Wulff{ adhesion: Some(0.5), ..wulff }, 5000);
+        assert_eq!(flake.substrate_pos, flake.extrema_ijk.z_min.k - 1);
+    }
+}